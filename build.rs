@@ -39,6 +39,16 @@ fn main() -> std::io::Result<()> {
         ".CoinType.contract_address",
         "#[serde_as(as = \"Option<::serde_with::hex::Hex>\")]",
     );
+    for field in [
+        ".Features.revision",
+        ".Features.bootloader_hash",
+        ".Features.firmware_hash",
+    ] {
+        config.field_attribute(
+            field,
+            "#[serde_as(as = \"Option<::serde_with::hex::Hex>\")]",
+        );
+    }
     config.btree_map(["."]);
     config.compile_protos(
         &[
@@ -0,0 +1,657 @@
+use crate::{
+    cli::{expect_field, expect_message, output, CliCommand},
+    messages::{self, Message},
+    transport::{ProtocolAdapter, PASSPHRASE_SOURCE, PIN_SOURCE},
+};
+use anyhow::{anyhow, bail, Result};
+use bitcoin::bech32::{self, FromBase32, ToBase32, Variant};
+use clap::Args;
+use rand::Rng;
+use std::{
+    cell::RefCell,
+    io::{stdin, stdout, BufRead, Write},
+};
+
+const RECIPIENT_HRP: &str = "age1keepkey";
+const IDENTITY_HRP: &str = "age-plugin-keepkey-";
+const STANZA_TYPE: &str = "keepkey";
+/// m/10016'/1; file keys are wrapped at a fixed path, so recipients only have to say which seed
+/// (and passphrase) they're for.
+const ADDRESS: [u32; 2] = [0x8000_0000 | 10016, 1];
+/// Key name for the value that identifies the key, which doesn't need confirming.
+const CHECK_KEY_NAME: &str = "age identity";
+/// Key name for file keys, which the device shows when asked to unwrap one.
+const WRAP_KEY_NAME: &str = "age file key";
+const KEY_LEN: usize = 16;
+/// Lines in stanza bodies are this long, except for the last.
+const BODY_LINE_LEN: usize = 64;
+
+/// Use the device with age, as the age-plugin-keepkey plugin
+///
+/// Without --age-plugin, this prints a new identity file, with the recipient to encrypt to in a
+/// comment. age runs the plugin when it sees either, so for it to be found, link kkcli into PATH
+/// as age-plugin-keepkey. Then `age -r age1keepkey1...` and `age -d -i IDENTITY_FILE` work.
+///
+/// File keys are wrapped by CipherKeyValue at a fixed path, since the device has no ECDH, so the
+/// device is needed to encrypt as well as to decrypt. Only decrypting asks for confirmation.
+#[derive(Debug, Clone, Args)]
+pub struct AgePluginKeepkey {
+    /// state machine to run, which age sets: recipient-v1 or identity-v1
+    #[clap(long, value_name = "STATE_MACHINE")]
+    age_plugin: Option<String>,
+}
+
+/// A command or response in age's plugin protocol.
+struct Stanza {
+    args: Vec<String>,
+    body: Vec<u8>,
+}
+
+/// age's end of the plugin protocol, which is on stdin and stdout.
+struct Connection {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl Connection {
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            bail!("age closed the connection");
+        }
+        Ok(line.trim_end_matches('\n').to_owned())
+    }
+
+    fn read(&mut self) -> Result<Stanza> {
+        let line = self.read_line()?;
+        let args = match line.strip_prefix("-> ") {
+            Some(x) => x.split(' ').map(str::to_owned).collect(),
+            None => bail!("expected a stanza from age, not {:?}", line),
+        };
+        let mut encoded = String::new();
+        loop {
+            let line = self.read_line()?;
+            encoded.push_str(&line);
+            if line.len() < BODY_LINE_LEN {
+                break;
+            }
+        }
+        let body = base64::decode_config(encoded, base64::STANDARD_NO_PAD)?;
+        Ok(Stanza { args, body })
+    }
+
+    fn write(&mut self, args: &[&str], body: &[u8]) -> Result<()> {
+        let mut out = format!("-> {}\n", args.join(" "));
+        let encoded = base64::encode_config(body, base64::STANDARD_NO_PAD);
+        let mut lines = encoded.as_bytes().chunks(BODY_LINE_LEN).peekable();
+        while let Some(line) = lines.next() {
+            out.push_str(core::str::from_utf8(line).unwrap());
+            out.push('\n');
+            if lines.peek().is_none() && line.len() == BODY_LINE_LEN {
+                out.push('\n');
+            }
+        }
+        if encoded.is_empty() {
+            out.push('\n');
+        }
+        self.output.write_all(out.as_bytes())?;
+        Ok(self.output.flush()?)
+    }
+
+    /// Sends a command in the second phase, giving age's response.
+    fn command(&mut self, args: &[&str], body: &[u8]) -> Result<Stanza> {
+        self.write(args, body)?;
+        self.read()
+    }
+
+    /// Has age ask the user for something secret.
+    fn request_secret(&mut self, prompt: &str) -> Result<String> {
+        let resp = self.command(&["request-secret"], prompt.as_bytes())?;
+        match resp.args.first().map(String::as_str) {
+            Some("ok") => Ok(String::from_utf8(resp.body)?),
+            _ => bail!("age couldn't ask for a secret"),
+        }
+    }
+}
+
+/// Answers the device's requests through age, since stdin and stdout belong to it.
+fn handle_through_age<'a>(
+    connection: &'a RefCell<Connection>,
+) -> impl Fn(&Message) -> Result<Option<Message>> + 'a {
+    move |msg| {
+        Ok(match msg {
+            Message::ButtonRequest(_) => {
+                connection
+                    .borrow_mut()
+                    .command(&["msg"], b"Confirm on your KeepKey")?;
+                Some(messages::ButtonAck::default().into())
+            }
+            Message::PinMatrixRequest(_) => {
+                let description =
+                    "Enter the positions of your PIN digits in the matrix shown on your KeepKey.";
+                let pin = match PIN_SOURCE.read().unwrap().read(description, "PIN: ")? {
+                    Some(x) => x,
+                    None => connection.borrow_mut().request_secret(description)?,
+                };
+                Some(messages::PinMatrixAck { pin }.into())
+            }
+            Message::PassphraseRequest(_) => {
+                let description = "Enter the BIP-39 passphrase for your KeepKey.";
+                let passphrase = match PASSPHRASE_SOURCE
+                    .read()
+                    .unwrap()
+                    .read(description, "Passphrase: ")?
+                {
+                    Some(x) => x,
+                    None => connection.borrow_mut().request_secret(description)?,
+                };
+                Some(messages::PassphraseAck { passphrase }.into())
+            }
+            _ => None,
+        })
+    }
+}
+
+fn cipher_key_value(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    key_name: &str,
+    value: &[u8],
+    iv: &[u8],
+    encrypt: bool,
+    ask_on_decrypt: bool,
+) -> Result<[u8; KEY_LEN]> {
+    let resp = expect_message!(
+        Message::CipheredKeyValue,
+        protocol_adapter.with_standard_handler().handle(
+            messages::CipherKeyValue {
+                address_n: ADDRESS.to_vec(),
+                key: Some(key_name.to_owned()),
+                value: Some(value.to_vec()),
+                encrypt: Some(encrypt),
+                ask_on_encrypt: Some(false),
+                ask_on_decrypt: Some(ask_on_decrypt),
+                iv: Some(iv.to_vec()),
+            }
+            .into(),
+        )
+    )?;
+    match expect_field!(resp.value)?[..].try_into() {
+        Ok(x) => Ok(x),
+        Err(_) => bail!("unexpected value from device"),
+    }
+}
+
+/// What recipients and identities hold: a value only the same seed and passphrase give, so the
+/// wrong device is caught before it wraps or unwraps anything.
+fn key_check(protocol_adapter: &mut dyn ProtocolAdapter) -> Result<[u8; KEY_LEN]> {
+    let zeros = [0; KEY_LEN];
+    cipher_key_value(
+        protocol_adapter,
+        CHECK_KEY_NAME,
+        &zeros,
+        &zeros,
+        true,
+        false,
+    )
+}
+
+/// Unwraps a file key from a stanza for `check`, once the device is known to be the one for it.
+fn unwrap_file_key(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    device_check: &mut Option<[u8; KEY_LEN]>,
+    check: [u8; KEY_LEN],
+    iv: [u8; KEY_LEN],
+    wrapped: &[u8],
+) -> Result<[u8; KEY_LEN]> {
+    let actual = match device_check {
+        Some(x) => *x,
+        None => *device_check.insert(key_check(protocol_adapter)?),
+    };
+    if actual != check {
+        bail!("the KeepKey connected (or its passphrase) isn't the one the file was encrypted to");
+    }
+    cipher_key_value(protocol_adapter, WRAP_KEY_NAME, wrapped, &iv, false, true)
+}
+
+fn encode(hrp: &str, check: &[u8; KEY_LEN]) -> String {
+    bech32::encode(hrp, check.to_base32(), Variant::Bech32).unwrap()
+}
+
+/// Decodes a recipient or identity, where `what` says which for errors.
+fn decode(hrp: &str, what: &str, encoded: &str) -> Result<[u8; KEY_LEN]> {
+    let (data, variant) = match bech32::decode(&encoded.to_lowercase()) {
+        Ok((actual_hrp, data, variant)) if actual_hrp == hrp => (data, variant),
+        _ => bail!("not an age-plugin-keepkey {}", what),
+    };
+    match Vec::<u8>::from_base32(&data).map(TryInto::try_into) {
+        Ok(Ok(x)) if variant == Variant::Bech32 => Ok(x),
+        _ => bail!("malformed age-plugin-keepkey {}", what),
+    }
+}
+
+/// Decodes unpadded base64, as stanza arguments are.
+fn decode_arg(arg: &str) -> Result<[u8; KEY_LEN]> {
+    base64::decode_config(arg, base64::STANDARD_NO_PAD)?
+        .try_into()
+        .map_err(|_| anyhow!("wrong length"))
+}
+
+fn encode_arg(arg: &[u8]) -> String {
+    base64::encode_config(arg, base64::STANDARD_NO_PAD)
+}
+
+/// Wraps file keys for recipients, as described in age's plugin spec.
+fn recipient_v1(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    connection: &RefCell<Connection>,
+) -> Result<()> {
+    let handler = handle_through_age(connection);
+    let mut protocol_adapter = protocol_adapter.with_handler(&handler);
+    let mut checks = Vec::new();
+    let mut errors = Vec::new();
+    let mut file_keys = Vec::new();
+    let (mut recipients, mut identities) = (0, 0);
+    loop {
+        let stanza = connection.borrow_mut().read()?;
+        match stanza.args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["add-recipient", x] => {
+                match decode(RECIPIENT_HRP, "recipient", x) {
+                    Ok(check) => checks.push((check, "recipient", recipients)),
+                    Err(e) => errors.push(("recipient", recipients, e.to_string())),
+                }
+                recipients += 1;
+            }
+            ["add-identity", x] => {
+                match decode(IDENTITY_HRP, "identity", x) {
+                    Ok(check) => checks.push((check, "identity", identities)),
+                    Err(e) => errors.push(("identity", identities, e.to_string())),
+                }
+                identities += 1;
+            }
+            ["wrap-file-key"] => file_keys.push(stanza.body),
+            ["done"] => break,
+            _ => {}
+        }
+    }
+
+    if errors.is_empty() && !checks.is_empty() && !file_keys.is_empty() {
+        let check = key_check(&mut *protocol_adapter)?;
+        for (expected, kind, index) in &checks {
+            if *expected != check {
+                let message = "the KeepKey connected (or its passphrase) isn't the one this is for";
+                errors.push((*kind, *index, message.to_owned()));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        for (kind, index, message) in errors {
+            let index = index.to_string();
+            connection
+                .borrow_mut()
+                .command(&["error", kind, &index], message.as_bytes())?;
+        }
+        connection.borrow_mut().write(&["done"], &[])?;
+        return Ok(());
+    }
+
+    // Every recipient is the connected device's key, so one stanza per file covers them all.
+    if !checks.is_empty() {
+        for (index, file_key) in file_keys.iter().enumerate() {
+            let mut iv = [0; KEY_LEN];
+            rand::thread_rng().fill(&mut iv);
+            let wrapped = cipher_key_value(
+                &mut *protocol_adapter,
+                WRAP_KEY_NAME,
+                file_key,
+                &iv,
+                true,
+                true,
+            )?;
+            let index = index.to_string();
+            let check = encode_arg(&checks[0].0);
+            let iv = encode_arg(&iv);
+            connection.borrow_mut().command(
+                &["recipient-stanza", &index, STANZA_TYPE, &check, &iv],
+                &wrapped,
+            )?;
+        }
+    }
+    connection.borrow_mut().write(&["done"], &[])
+}
+
+/// Unwraps file keys from stanzas for our identities, as described in age's plugin spec.
+fn identity_v1(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    connection: &RefCell<Connection>,
+) -> Result<()> {
+    let handler = handle_through_age(connection);
+    let mut protocol_adapter = protocol_adapter.with_handler(&handler);
+    let mut checks = Vec::new();
+    let mut errors = Vec::new();
+    let mut stanzas: Vec<Vec<Stanza>> = Vec::new();
+    let mut identities = 0;
+    loop {
+        let stanza = connection.borrow_mut().read()?;
+        match &stanza.args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["add-identity", x] => {
+                match decode(IDENTITY_HRP, "identity", x) {
+                    Ok(check) => checks.push(check),
+                    Err(e) => errors.push((vec!["identity".to_owned(), identities.to_string()], e)),
+                }
+                identities += 1;
+            }
+            ["recipient-stanza", index, ..] => {
+                let index: usize = index.parse()?;
+                if stanzas.len() <= index {
+                    stanzas.resize_with(index + 1, Vec::new);
+                }
+                stanzas[index].push(Stanza {
+                    args: stanza.args[2..].to_vec(),
+                    body: stanza.body,
+                });
+            }
+            ["done"] => break,
+            _ => {}
+        }
+    }
+
+    let mut device_check = None;
+    for (file_index, file_stanzas) in stanzas.iter().enumerate() {
+        for (stanza_index, stanza) in file_stanzas.iter().enumerate() {
+            if stanza.args.first().map(String::as_str) != Some(STANZA_TYPE) {
+                continue;
+            }
+            let location = vec![
+                "stanza".to_owned(),
+                file_index.to_string(),
+                stanza_index.to_string(),
+            ];
+            let (check, iv) = match &stanza.args[1..] {
+                [check, iv] => match (decode_arg(check), decode_arg(iv)) {
+                    (Ok(check), Ok(iv)) if stanza.body.len() == KEY_LEN => (check, iv),
+                    _ => {
+                        errors.push((location, anyhow!("malformed {} stanza", STANZA_TYPE)));
+                        continue;
+                    }
+                },
+                _ => {
+                    errors.push((location, anyhow!("malformed {} stanza", STANZA_TYPE)));
+                    continue;
+                }
+            };
+            if !checks.contains(&check) {
+                continue;
+            }
+
+            match unwrap_file_key(
+                &mut *protocol_adapter,
+                &mut device_check,
+                check,
+                iv,
+                &stanza.body,
+            ) {
+                Ok(file_key) => {
+                    connection
+                        .borrow_mut()
+                        .command(&["file-key", &file_index.to_string()], &file_key)?;
+                    break;
+                }
+                Err(e) => errors.push((location, e)),
+            }
+        }
+    }
+
+    for (location, error) in errors {
+        let mut args = vec!["error"];
+        args.extend(location.iter().map(String::as_str));
+        connection
+            .borrow_mut()
+            .command(&args, format!("{:#}", error).as_bytes())?;
+    }
+    connection.borrow_mut().write(&["done"], &[])
+}
+
+impl CliCommand for AgePluginKeepkey {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let connection = RefCell::new(Connection {
+            input: Box::new(stdin().lock()),
+            output: Box::new(stdout()),
+        });
+
+        match self.age_plugin.as_deref() {
+            Some("recipient-v1") => recipient_v1(protocol_adapter, &connection),
+            Some("identity-v1") => identity_v1(protocol_adapter, &connection),
+            Some(x) => bail!("unsupported state machine {}", x),
+            None => {
+                let check = key_check(protocol_adapter)?;
+                let recipient = encode(RECIPIENT_HRP, &check);
+                let identity = encode(IDENTITY_HRP, &check).to_uppercase();
+                output!("recipient" => recipient, "# recipient: {}", recipient);
+                output!("identity" => identity, "{}", identity);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Transport;
+    use core::time::Duration;
+    use std::{collections::VecDeque, io::Cursor, rc::Rc};
+
+    /// What the stand-in device wraps values with, so its key check is all `WRAP`.
+    const WRAP: u8 = 0x5a;
+
+    /// Answers CipherKeyValue by XORing the value with `WRAP`, keeping the requests.
+    #[derive(Default)]
+    struct StandIn {
+        requests: Vec<messages::CipherKeyValue>,
+        replies: VecDeque<Message>,
+    }
+
+    impl Transport for StandIn {
+        type Error = std::io::Error;
+
+        fn write(&mut self, msg: &[u8], _: Duration) -> Result<usize, Self::Error> {
+            let reply = match Message::decode(&mut &*msg).unwrap() {
+                Message::CipherKeyValue(x) => {
+                    let value = x
+                        .value
+                        .as_ref()
+                        .map(|x| x.iter().map(|x| x ^ WRAP).collect());
+                    self.requests.push(x);
+                    messages::CipheredKeyValue { value }.into()
+                }
+                x => panic!("unexpected message {:?}", x),
+            };
+            self.replies.push_back(reply);
+            Ok(msg.len())
+        }
+
+        fn read(&mut self, buf: &mut Vec<u8>, _: Duration) -> Result<(), Self::Error> {
+            self.replies.pop_front().unwrap().encode(buf).unwrap();
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// Somewhere for a connection to write that can be read once it's done.
+    #[derive(Clone, Default)]
+    struct Written(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A connection reading `input`, with what it writes.
+    fn connection(input: &[u8]) -> (RefCell<Connection>, Written) {
+        let written = Written::default();
+        let connection = Connection {
+            input: Box::new(Cursor::new(input.to_vec())),
+            output: Box::new(written.clone()),
+        };
+        (RefCell::new(connection), written)
+    }
+
+    /// Every stanza in `input`.
+    fn stanzas(input: &[u8]) -> Vec<Stanza> {
+        let (connection, _) = connection(input);
+        let mut stanzas = Vec::new();
+        while let Ok(x) = connection.borrow_mut().read() {
+            stanzas.push(x);
+        }
+        stanzas
+    }
+
+    /// Frames `args` and `body` as a stanza, checking they read back the same.
+    fn framed(args: &[&str], body: &[u8]) -> String {
+        let (connection, written) = connection(b"");
+        connection.borrow_mut().write(args, body).unwrap();
+        let written = written.0.take();
+        let stanzas = stanzas(&written);
+        assert_eq!(stanzas.len(), 1);
+        assert_eq!(stanzas[0].args, args);
+        assert_eq!(stanzas[0].body, body);
+        String::from_utf8(written).unwrap()
+    }
+
+    #[test]
+    fn frames_stanzas() {
+        assert_eq!(framed(&["done"], b""), "-> done\n\n");
+        assert_eq!(framed(&["msg"], b"hello"), "-> msg\naGVsbG8\n");
+        // A body filling the last line needs an empty one after it to say it's ended.
+        let full = framed(&["file-key", "0"], &[0xff; 48]);
+        assert_eq!(full, format!("-> file-key 0\n{}\n\n", "/".repeat(64)));
+        let long = framed(&["a", "b", "c"], &[0xff; 99]);
+        assert_eq!(
+            long,
+            format!(
+                "-> a b c\n{}\n{}\n{}\n",
+                "/".repeat(64),
+                "/".repeat(64),
+                "/".repeat(4)
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_bad_framing() {
+        assert!(stanzas(b"hello\n\n").is_empty());
+        assert!(stanzas(b"-> msg\n").is_empty());
+        assert!(stanzas(b"-> msg\naGVsbG8!\n").is_empty());
+    }
+
+    #[test]
+    fn encodes_recipients_and_identities() {
+        let check = [3; KEY_LEN];
+        let recipient = encode(RECIPIENT_HRP, &check);
+        let identity = encode(IDENTITY_HRP, &check).to_uppercase();
+        assert!(recipient.starts_with("age1keepkey1"));
+        assert!(identity.starts_with("AGE-PLUGIN-KEEPKEY-1"));
+        assert_eq!(
+            decode(RECIPIENT_HRP, "recipient", &recipient).unwrap(),
+            check
+        );
+        assert_eq!(decode(IDENTITY_HRP, "identity", &identity).unwrap(), check);
+        assert_eq!(
+            decode(IDENTITY_HRP, "identity", &identity.to_lowercase()).unwrap(),
+            check
+        );
+
+        let wrong_hrp = decode(IDENTITY_HRP, "identity", &recipient).unwrap_err();
+        assert_eq!(wrong_hrp.to_string(), "not an age-plugin-keepkey identity");
+        let short = bech32::encode(RECIPIENT_HRP, [3; 8].to_base32(), Variant::Bech32).unwrap();
+        assert_eq!(
+            decode(RECIPIENT_HRP, "recipient", &short)
+                .unwrap_err()
+                .to_string(),
+            "malformed age-plugin-keepkey recipient"
+        );
+        let bech32m = bech32::encode(RECIPIENT_HRP, check.to_base32(), Variant::Bech32m).unwrap();
+        assert!(decode(RECIPIENT_HRP, "recipient", &bech32m).is_err());
+    }
+
+    #[test]
+    fn unwraps_only_its_own_stanzas() {
+        let ours = encode_arg(&[WRAP; KEY_LEN]);
+        let theirs = encode_arg(&[1; KEY_LEN]);
+        let iv = encode_arg(&[2; KEY_LEN]);
+        let file_key = [7; KEY_LEN];
+        let wrapped = file_key.map(|x| x ^ WRAP);
+
+        // What age sends: identities, stanzas, then its responses to the file key and errors.
+        let (age, input) = connection(b"");
+        let send = |args: &[&str], body: &[u8]| age.borrow_mut().write(args, body).unwrap();
+        send(
+            &[
+                "add-identity",
+                &encode(IDENTITY_HRP, &[WRAP; KEY_LEN]).to_uppercase(),
+            ],
+            b"",
+        );
+        send(
+            &["add-identity", &encode(RECIPIENT_HRP, &[WRAP; KEY_LEN])],
+            b"",
+        );
+        send(&["recipient-stanza", "0", "X25519", "abc"], &[9; 32]);
+        send(&["recipient-stanza", "0", STANZA_TYPE, &ours], &wrapped);
+        send(
+            &["recipient-stanza", "0", STANZA_TYPE, &theirs, &iv],
+            &wrapped,
+        );
+        send(
+            &["recipient-stanza", "0", STANZA_TYPE, &ours, &iv],
+            &wrapped,
+        );
+        send(
+            &["recipient-stanza", "1", STANZA_TYPE, &ours, &iv],
+            &wrapped[1..],
+        );
+        send(&["done"], b"");
+        for _ in 0..4 {
+            send(&["ok"], b"");
+        }
+
+        let (connection, output) = connection(&input.0.take());
+        let mut protocol_adapter = StandIn::default();
+        identity_v1(&mut protocol_adapter, &connection).unwrap();
+        let sent = stanzas(&output.0.take());
+        let args: Vec<_> = sent.iter().map(|x| x.args.join(" ")).collect();
+        assert_eq!(
+            args,
+            [
+                "file-key 0",
+                "error identity 1",
+                "error stanza 0 1",
+                "error stanza 1 0",
+                "done"
+            ]
+        );
+        assert_eq!(sent[0].body, file_key);
+        assert_eq!(sent[1].body, b"not an age-plugin-keepkey identity");
+        assert_eq!(sent[2].body, b"malformed keepkey stanza");
+        assert_eq!(sent[3].body, b"malformed keepkey stanza");
+
+        // The key check, then the one stanza that was ours, which is the only one confirmed.
+        let requests = &protocol_adapter.requests;
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].key.as_deref(), Some(CHECK_KEY_NAME));
+        assert_eq!(requests[0].ask_on_decrypt, Some(false));
+        assert_eq!(requests[1].key.as_deref(), Some(WRAP_KEY_NAME));
+        assert_eq!(requests[1].value.as_deref(), Some(&wrapped[..]));
+        assert_eq!(requests[1].iv.as_deref(), Some(&[2; KEY_LEN][..]));
+        assert_eq!(requests[1].encrypt, Some(false));
+        assert_eq!(requests[1].ask_on_decrypt, Some(true));
+    }
+}
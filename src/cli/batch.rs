@@ -0,0 +1,110 @@
+use super::{
+    output,
+    shell::{run_line, split_words},
+    CliDebugCommand,
+};
+use crate::transport::ProtocolAdapter;
+use anyhow::{anyhow, Context, Result};
+use clap::{ArgAction::SetTrue, Args};
+use serde::Deserialize;
+use serde_json::json;
+use std::io::Read;
+
+/// Run a list of commands over one connection and print all of their results as one JSON document
+#[derive(Debug, Clone, Args)]
+pub struct Batch {
+    /// keep running the rest of the commands after one fails
+    #[clap(short, long, action = SetTrue)]
+    continue_on_error: bool,
+    /// file with one command per line (# starts a comment), or a JSON array whose entries are command lines or arrays of arguments; - reads from stdin
+    file: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Line(String),
+    Args(Vec<String>),
+}
+
+fn parse_commands(text: &str) -> Result<Vec<Vec<String>>> {
+    let commands = if text.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<Entry>>(text)
+            .context("expected a JSON array of strings or arrays of strings")?
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| match x {
+                Entry::Line(x) => split_words(&x).with_context(|| format!("entry {}", i)),
+                Entry::Args(x) => Ok(x),
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        text.lines()
+            .enumerate()
+            .map(|(i, x)| split_words(x).with_context(|| format!("line {}", i + 1)))
+            .collect::<Result<Vec<_>>>()?
+    };
+    Ok(commands.into_iter().filter(|x| !x.is_empty()).collect())
+}
+
+impl CliDebugCommand for Batch {
+    fn handle_debug(
+        self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        mut debug_protocol_adapter: Option<&mut dyn ProtocolAdapter>,
+    ) -> Result<()> {
+        let text = if self.file == "-" {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            text
+        } else {
+            std::fs::read_to_string(&self.file)
+                .with_context(|| format!("couldn't read {}", self.file))?
+        };
+        let commands = parse_commands(&text)?;
+
+        // Every command's output goes into the one document, so there's no text mode.
+        *output::JSON.write().unwrap() = true;
+
+        let total = commands.len();
+        let mut results = Vec::new();
+        let mut failed = 0;
+        let mut stopped_by = None;
+        for (i, words) in commands.into_iter().enumerate() {
+            let command = words.join(" ");
+            let outcome = run_line(
+                words,
+                protocol_adapter,
+                debug_protocol_adapter
+                    .as_deref_mut()
+                    .map(|x| -> &mut dyn ProtocolAdapter { x }),
+            );
+            let result = output::take_result();
+            let mut entry = json!({
+                "command": command,
+                "success": outcome.is_ok(),
+            });
+            if !result.is_empty() || outcome.is_ok() {
+                entry["result"] = result.into();
+            }
+            if let Err(e) = outcome {
+                entry["error"] = output::error_object(&e);
+                failed += 1;
+                if !self.continue_on_error {
+                    stopped_by = Some(e.context(format!("command {} ({}) failed", i + 1, command)));
+                }
+            }
+            results.push(entry);
+            if stopped_by.is_some() {
+                break;
+            }
+        }
+
+        output::record("commands", &results)?;
+        match stopped_by {
+            Some(e) => Err(e),
+            None if failed > 0 => Err(anyhow!("{} of {} commands failed", failed, total)),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use super::schema::Transaction;
 use crate::{
     cli::{
-        expect_field, expect_message,
+        expect_field, expect_message, output,
         parsers::{Bip32PathParser, SerdeJsonFileOrLiteralParser},
         types::Bip32Path,
         CliCommand,
@@ -57,14 +57,10 @@ impl CliCommand for BinanceSignTx {
                 )
         )?;
 
-        println!(
-            "Public Key:\t{}",
-            hex::encode(expect_field!(resp.public_key)?)
-        );
-        println!(
-            "Signature:\t{}",
-            hex::encode(expect_field!(resp.signature)?)
-        );
+        let public_key = hex::encode(expect_field!(resp.public_key)?);
+        output!("publicKey" => public_key, "Public Key:\t{}", public_key);
+        let signature = hex::encode(expect_field!(resp.signature)?);
+        output!("signature" => signature, "Signature:\t{}", signature);
 
         Ok(())
     }
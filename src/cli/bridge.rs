@@ -0,0 +1,232 @@
+use crate::{
+    cli::{
+        http::{self, Response},
+        CliCommand,
+    },
+    messages::Message,
+    transport::{list_devices, ProtocolAdapter, Transport, UsbTransport},
+};
+use anyhow::Result;
+use clap::Args;
+use core::time::Duration;
+use rusb::{Device, GlobalContext};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Used for messages we can't decode; the device may be waiting on the user.
+const PASSTHROUGH_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// How long `/listen` waits for a change before answering with the same list.
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(60);
+const LISTEN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Let browser wallets use connected KeepKeys through the KeepKey Bridge HTTP API
+///
+/// Implements enumerate, listen, acquire, release, call, post and read. Messages are passed
+/// through to the device unchanged, so this works with any firmware.
+#[derive(Debug, Clone, Args)]
+pub struct Bridge {
+    /// localhost address to listen on
+    #[clap(long, value_parser, default_value = "127.0.0.1:1646")]
+    listen: SocketAddr,
+    /// web origin allowed to use the bridge, like https://wallet.example (may be repeated; pages served from localhost are always allowed)
+    #[clap(long = "allow-origin")]
+    allowed_origins: Vec<String>,
+}
+
+type SharedTransport = Arc<Mutex<UsbTransport<GlobalContext>>>;
+
+/// A device someone has acquired. It stays open until it's released, even if another session steals it.
+struct OpenDevice {
+    session: String,
+    transport: SharedTransport,
+}
+
+#[derive(Default)]
+struct State {
+    next_session: u64,
+    /// Keyed by path.
+    devices: HashMap<String, OpenDevice>,
+}
+
+struct HttpError(u16, String);
+
+impl<E: std::fmt::Display> From<E> for HttpError {
+    fn from(x: E) -> Self {
+        Self(400, x.to_string())
+    }
+}
+
+enum Body {
+    Json(Value),
+    Hex(Vec<u8>),
+}
+
+fn device_path(device: &Device<GlobalContext>) -> String {
+    format!("{:03}:{:03}", device.bus_number(), device.address())
+}
+
+fn enumerate(state: &Mutex<State>) -> Result<Value, HttpError> {
+    let devices = list_devices()?;
+    let mut state = state.lock().unwrap();
+    let paths = devices.iter().map(device_path).collect::<Vec<_>>();
+    // Forget sessions on devices that have gone away.
+    state.devices.retain(|path, _| paths.contains(path));
+
+    let mut out = Vec::new();
+    for (device, path) in devices.iter().zip(paths) {
+        let device_desc = device.device_descriptor()?;
+        out.push(json!({
+            "path": path,
+            "vendor": device_desc.vendor_id(),
+            "product": device_desc.product_id(),
+            "session": state.devices.get(&path).map(|x| &x.session),
+            "debug": false,
+            "debugSession": null,
+        }));
+    }
+    Ok(Value::Array(out))
+}
+
+/// Waits for the device list to differ from the one the client already has.
+fn listen(state: &Mutex<State>, body: &[u8]) -> Result<Value, HttpError> {
+    let known = serde_json::from_slice::<Value>(body).unwrap_or(Value::Null);
+    let started = Instant::now();
+    loop {
+        let current = enumerate(state)?;
+        if current != known || started.elapsed() >= LISTEN_TIMEOUT {
+            return Ok(current);
+        }
+        std::thread::sleep(LISTEN_POLL_INTERVAL);
+    }
+}
+
+fn acquire(state: &Mutex<State>, path: &str, previous: &str) -> Result<Value, HttpError> {
+    let mut state = state.lock().unwrap();
+    let current = state.devices.get(path).map(|x| x.session.as_str());
+    let previous = Some(previous).filter(|x| *x != "null");
+    if current != previous {
+        return Err(HttpError(400, "wrong previous session".to_owned()));
+    }
+
+    state.next_session += 1;
+    let session = state.next_session.to_string();
+    match state.devices.get_mut(path) {
+        Some(x) => x.session = session.clone(),
+        None => {
+            let devices = list_devices()?;
+            let device = devices
+                .iter()
+                .find(|x| device_path(x) == path)
+                .ok_or_else(|| HttpError(404, "device not found".to_owned()))?;
+            let (transport, _, _) = UsbTransport::new(device, 0)?;
+            state.devices.insert(
+                path.to_owned(),
+                OpenDevice {
+                    session: session.clone(),
+                    transport: Arc::new(Mutex::new(transport)),
+                },
+            );
+        }
+    }
+    Ok(json!({ "session": session }))
+}
+
+fn release(state: &Mutex<State>, session: &str) -> Result<Value, HttpError> {
+    let mut state = state.lock().unwrap();
+    let before = state.devices.len();
+    state.devices.retain(|_, x| x.session != session);
+    if state.devices.len() == before {
+        return Err(HttpError(400, "session not found".to_owned()));
+    }
+    Ok(json!({}))
+}
+
+fn session_transport(state: &Mutex<State>, session: &str) -> Result<SharedTransport, HttpError> {
+    state
+        .lock()
+        .unwrap()
+        .devices
+        .values()
+        .find(|x| x.session == session)
+        .map(|x| Arc::clone(&x.transport))
+        .ok_or_else(|| HttpError(400, "session not found".to_owned()))
+}
+
+/// Bridge messages are our wire format without the leading "##".
+fn exchange(
+    transport: &SharedTransport,
+    body: &[u8],
+    write: bool,
+    read: bool,
+) -> Result<Body, HttpError> {
+    let mut transport = transport.lock().unwrap();
+    let mut read_timeout = PASSTHROUGH_TIMEOUT;
+    if write {
+        let mut msg = b"##".to_vec();
+        msg.extend(hex::decode(std::str::from_utf8(body)?.trim())?);
+        if msg.len() < 8 {
+            return Err(HttpError(400, "message is too short".to_owned()));
+        }
+        let write_timeout = match Message::decode(&mut &*msg) {
+            Ok(x) => {
+                read_timeout = x.read_timeout();
+                x.write_timeout()
+            }
+            Err(_) => PASSTHROUGH_TIMEOUT,
+        };
+        transport.write(&msg, write_timeout)?;
+    }
+    if !read {
+        return Ok(Body::Json(json!({})));
+    }
+    let mut buf = Vec::new();
+    transport.read(&mut buf, read_timeout)?;
+    Ok(Body::Hex(buf.split_off(2)))
+}
+
+fn route(state: &Mutex<State>, path: &str, body: &[u8]) -> Result<Body, HttpError> {
+    let parts = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    Ok(Body::Json(match parts[..] {
+        [""] => json!({ "version": env!("CARGO_PKG_VERSION") }),
+        ["enumerate"] => enumerate(state)?,
+        ["listen"] => listen(state, body)?,
+        ["acquire", path, previous] => acquire(state, path, previous)?,
+        ["release", session] => release(state, session)?,
+        ["call", session] => {
+            return exchange(&session_transport(state, session)?, body, true, true)
+        }
+        ["post", session] => {
+            return exchange(&session_transport(state, session)?, body, true, false)
+        }
+        ["read", session] => {
+            return exchange(&session_transport(state, session)?, body, false, true)
+        }
+        _ => return Err(HttpError(404, "not found".to_owned())),
+    }))
+}
+
+impl Bridge {
+    pub fn handle(self) -> Result<()> {
+        let listener = http::bind(self.listen)?;
+        let state = Mutex::new(State::default());
+        http::serve(listener, self.allowed_origins, move |request| {
+            match route(&state, &request.path, &request.body) {
+                Ok(Body::Json(x)) => Response::json(&x),
+                Ok(Body::Hex(x)) => Response::text(hex::encode(x)),
+                Err(HttpError(status, message)) => Response::error(status, &message),
+            }
+        });
+        Ok(())
+    }
+}
+
+impl CliCommand for Bridge {
+    fn handle(self, _: &mut dyn ProtocolAdapter) -> Result<()> {
+        unreachable!();
+    }
+}
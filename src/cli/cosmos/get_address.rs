@@ -1,5 +1,8 @@
 use crate::{
-    cli::{expect_field, expect_message, parsers::Bip32PathParser, types::Bip32Path, CliCommand},
+    cli::{
+        expect_field, expect_message, output, parsers::Bip32PathParser, types::Bip32Path,
+        CliCommand,
+    },
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
@@ -30,7 +33,8 @@ impl CliCommand for CosmosGetAddress {
             )
         )?;
 
-        println!("{}", expect_field!(resp.address)?);
+        let address = expect_field!(resp.address)?;
+        output!("address" => address, "{}", address);
 
         Ok(())
     }
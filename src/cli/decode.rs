@@ -1,5 +1,9 @@
 use super::parsers::HexParser;
-use crate::{cli::CliCommand, messages::Message, transport::ProtocolAdapter};
+use crate::{
+    cli::{output, CliCommand},
+    messages::Message,
+    transport::ProtocolAdapter,
+};
 use anyhow::{anyhow, Result};
 use clap::Args;
 
@@ -19,7 +23,7 @@ impl Decode {
         }
         let msg = Message::decode(&mut data.as_slice()).map_err(|x| anyhow!(x))?;
 
-        println!("{:?}", msg);
+        output!("message" => msg, "{:?}", msg);
         Ok(())
     }
 }
@@ -0,0 +1,43 @@
+use super::{decrypt, open_input, write_output};
+use crate::{cli::CliCommand, transport::ProtocolAdapter};
+use anyhow::{anyhow, Result};
+use clap::{ArgAction::SetTrue, Args};
+
+/// Decrypt a file made by encrypt-file
+///
+/// The device is asked to unwrap the file's key, which it asks to confirm, showing the key name
+/// the file was encrypted with.
+#[derive(Debug, Clone, Args)]
+pub struct DecryptFile {
+    /// file to decrypt, or - for stdin
+    input: String,
+    /// where to write the decrypted file, or - for stdout; defaults to the input without .kkenc
+    #[clap(short, long)]
+    output: Option<String>,
+    /// replace the output if it already exists
+    #[clap(short, long, action = SetTrue)]
+    force: bool,
+}
+
+impl CliCommand for DecryptFile {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let output = match (&self.output, self.input.strip_suffix(".kkenc")) {
+            (Some(x), _) => x.clone(),
+            (None, Some(x)) if !x.is_empty() => x.to_owned(),
+            _ => {
+                return Err(anyhow!(
+                    "--output is required when the input doesn't end with .kkenc"
+                ))
+            }
+        };
+        let mut input = open_input(&self.input)?;
+
+        write_output(&output, self.force, |out| {
+            decrypt(protocol_adapter, &mut input, out)
+        })?;
+        if output != "-" {
+            eprintln!("Wrote decrypted file to {}", output);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use super::{encrypt, open_input, write_output, DataKey, DEFAULT_ADDRESS};
+use crate::{
+    cli::{parsers::Bip32PathParser, types::Bip32Path, CliCommand},
+    transport::ProtocolAdapter,
+};
+use anyhow::{anyhow, Result};
+use clap::{ArgAction::SetTrue, Args};
+use std::path::Path;
+
+/// Encrypt a file with a key that only the device can unwrap
+///
+/// A random key encrypts the file with ChaCha20-Poly1305, and the device wraps that key with
+/// CipherKeyValue, asking for confirmation. Decrypting needs the same device (or seed and
+/// passphrase) and another confirmation. The key name is shown on the device both times.
+#[derive(Debug, Clone, Args)]
+pub struct EncryptFile {
+    /// file to encrypt, or - for stdin
+    input: String,
+    /// where to write the encrypted file, or - for stdout; defaults to the input with .kkenc added
+    #[clap(short, long)]
+    output: Option<String>,
+    /// BIP-32 path to the key that wraps the file's key
+    #[clap(short = 'n', long, value_parser = Bip32PathParser, default_value = DEFAULT_ADDRESS)]
+    address: Bip32Path,
+    /// key name shown on the device when encrypting and decrypting; defaults to the input's file name
+    #[clap(short, long)]
+    key_name: Option<String>,
+    /// replace the output if it already exists
+    #[clap(short, long, action = SetTrue)]
+    force: bool,
+}
+
+impl CliCommand for EncryptFile {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let output = match (&self.output, self.input.as_str()) {
+            (Some(x), _) => x.clone(),
+            (None, "-") => return Err(anyhow!("--output is required when encrypting stdin")),
+            (None, x) => format!("{}.kkenc", x),
+        };
+        let key_name = match self.key_name {
+            Some(x) => x,
+            None => Path::new(&self.input)
+                .file_name()
+                .map_or_else(|| self.input.clone(), |x| x.to_string_lossy().into_owned()),
+        };
+        let mut input = open_input(&self.input)?;
+
+        let data_key = DataKey::generate(protocol_adapter, self.address.into(), key_name)?;
+        write_output(&output, self.force, |out| {
+            encrypt(&data_key, &mut input, out)
+        })?;
+        if output != "-" {
+            eprintln!("Wrote encrypted file to {}", output);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,483 @@
+mod decrypt_file;
+mod encrypt_file;
+
+pub use decrypt_file::*;
+pub use encrypt_file::*;
+
+use crate::{
+    cli::{expect_field, expect_message, ssh::Reader},
+    messages::{self, Message},
+    transport::ProtocolAdapter,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305,
+};
+use rand::Rng;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{stdin, stdout, ErrorKind, Read, Write},
+};
+
+const MAGIC: &[u8] = b"KKCLIENC";
+const VERSION: u8 = 1;
+/// How much plaintext goes in each sealed chunk, so files don't have to fit in memory.
+const CHUNK_LEN: usize = 64 * 1024;
+const IV_LEN: usize = 16;
+
+pub(crate) const KEY_LEN: usize = 32;
+pub(crate) const NONCE_LEN: usize = 12;
+pub(crate) const TAG_LEN: usize = 16;
+
+/// The default BIP-32 path for the key the device wraps data keys with.
+pub(crate) const DEFAULT_ADDRESS: &str = "m/10016'/0";
+
+/// A random key that's only kept wrapped by the device, through CipherKeyValue.
+///
+/// The device wraps and unwraps it with AES-256-CBC under a key derived from its seed, the path
+/// and the key name, which it shows when asking for confirmation.
+pub(crate) struct DataKey {
+    pub address: Vec<u32>,
+    pub key_name: String,
+    iv: [u8; IV_LEN],
+    wrapped: [u8; KEY_LEN],
+    key: [u8; KEY_LEN],
+}
+
+impl DataKey {
+    fn cipher_key_value(
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        address: &[u32],
+        key_name: &str,
+        iv: &[u8; IV_LEN],
+        value: &[u8; KEY_LEN],
+        encrypt: bool,
+    ) -> Result<[u8; KEY_LEN]> {
+        let resp = expect_message!(
+            Message::CipheredKeyValue,
+            protocol_adapter.with_standard_handler().handle(
+                messages::CipherKeyValue {
+                    address_n: address.to_vec(),
+                    key: Some(key_name.to_owned()),
+                    value: Some(value.to_vec()),
+                    encrypt: Some(encrypt),
+                    ask_on_encrypt: Some(true),
+                    ask_on_decrypt: Some(true),
+                    iv: Some(iv.to_vec()),
+                }
+                .into(),
+            )
+        )?;
+        match expect_field!(resp.value)?[..].try_into() {
+            Ok(x) => Ok(x),
+            Err(_) => bail!("unexpected value from device"),
+        }
+    }
+
+    /// Makes a new key and has the device wrap it, which it asks to confirm.
+    pub fn generate(
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        address: Vec<u32>,
+        key_name: String,
+    ) -> Result<Self> {
+        let mut rng = rand::thread_rng();
+        let mut key = [0; KEY_LEN];
+        let mut iv = [0; IV_LEN];
+        rng.fill(&mut key);
+        rng.fill(&mut iv);
+        let wrapped =
+            Self::cipher_key_value(protocol_adapter, &address, &key_name, &iv, &key, true)?;
+        Ok(Self {
+            address,
+            key_name,
+            iv,
+            wrapped,
+            key,
+        })
+    }
+
+    /// Has the device unwrap a key, which it asks to confirm.
+    pub fn unwrap(
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        address: Vec<u32>,
+        key_name: String,
+        iv: [u8; IV_LEN],
+        wrapped: [u8; KEY_LEN],
+    ) -> Result<Self> {
+        let key =
+            Self::cipher_key_value(protocol_adapter, &address, &key_name, &iv, &wrapped, false)?;
+        Ok(Self {
+            address,
+            key_name,
+            iv,
+            wrapped,
+            key,
+        })
+    }
+
+    pub fn key(&self) -> &[u8; KEY_LEN] {
+        &self.key
+    }
+
+    /// Everything needed to unwrap the key again: the path, key name, IV and wrapped key.
+    pub fn header(&self) -> Vec<u8> {
+        let mut out = vec![self.address.len() as u8];
+        for x in &self.address {
+            out.extend_from_slice(&x.to_be_bytes());
+        }
+        out.extend_from_slice(&(self.key_name.len() as u16).to_be_bytes());
+        out.extend_from_slice(self.key_name.as_bytes());
+        out.extend_from_slice(&self.iv);
+        out.extend_from_slice(&self.wrapped);
+        out
+    }
+
+    /// Reads what `header` wrote, and has the device unwrap the key.
+    pub fn from_header(
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        reader: &mut Reader,
+    ) -> Result<Self> {
+        let address = (0..reader.u8()?)
+            .map(|_| reader.u32())
+            .collect::<Result<_>>()?;
+        let len = u16::from_be_bytes(reader.bytes(2)?.try_into().unwrap());
+        let key_name = String::from_utf8(reader.bytes(len.into())?.to_vec())?;
+        let iv = reader.bytes(IV_LEN)?.try_into().unwrap();
+        let wrapped = reader.bytes(KEY_LEN)?.try_into().unwrap();
+        Self::unwrap(protocol_adapter, address, key_name, iv, wrapped)
+    }
+}
+
+/// Opens `path` for reading, with - meaning stdin.
+fn open_input(path: &str) -> Result<Box<dyn Read>> {
+    Ok(match path {
+        "-" => Box::new(stdin()),
+        _ => Box::new(File::open(path).with_context(|| format!("couldn't open {}", path))?),
+    })
+}
+
+/// Writes to `path` with `write`, with - meaning stdout. Files that already exist are only
+/// replaced with `force`, and only once `write` has succeeded: it writes to a temporary file
+/// beside `path`, which is removed if it fails.
+fn write_output(
+    path: &str,
+    force: bool,
+    write: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    if path == "-" {
+        let mut out = stdout().lock();
+        write(&mut out)?;
+        return Ok(out.flush()?);
+    }
+    if !force && fs::symlink_metadata(path).is_ok() {
+        bail!("{} already exists (use --force to replace it)", path);
+    }
+    let temp = format!("{}.tmp", path);
+    // A leftover from an earlier attempt would keep its permissions, so it's started afresh.
+    match fs::remove_file(&temp) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            return Err(e).with_context(|| format!("couldn't remove {}", temp))
+        }
+        _ => {}
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .with_context(|| format!("couldn't create {}", temp))?;
+    if let Err(e) = write(&mut file).and_then(|_| Ok(file.sync_all()?)) {
+        drop(file);
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    fs::rename(&temp, path).with_context(|| format!("couldn't replace {}", path))
+}
+
+/// Encrypts `plaintext` with ChaCha20-Poly1305, giving the ciphertext with the tag after it.
+pub(crate) fn seal(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Vec<u8> {
+    ChaCha20Poly1305::new(key.into())
+        .encrypt(
+            nonce.into(),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .expect("plaintext is too long for ChaCha20-Poly1305")
+}
+
+/// Checks and decrypts what `seal` gave.
+pub(crate) fn open(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    sealed: &[u8],
+) -> Result<Vec<u8>> {
+    ChaCha20Poly1305::new(key.into())
+        .decrypt(nonce.into(), Payload { msg: sealed, aad })
+        .map_err(|_| anyhow!("ciphertext doesn't authenticate"))
+}
+
+/// Nonces only have to be unique per data key, and there's a new one for every file, so they
+/// count chunks. The first byte marks the last chunk, so a file cut short at the end of a chunk
+/// doesn't decrypt.
+fn chunk_nonce(index: u64, last: bool) -> [u8; NONCE_LEN] {
+    let mut out = [0; NONCE_LEN];
+    out[0] = last.into();
+    out[4..].copy_from_slice(&index.to_be_bytes());
+    out
+}
+
+/// Reads until `buf` is full or there's nothing left, giving how much was read.
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(x) => len += x,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(len)
+}
+
+/// Writes the container: the magic, version and data key header, then the plaintext in sealed
+/// chunks, each authenticating the header too. The last chunk is the first that's short, even
+/// if that leaves it empty.
+pub(crate) fn encrypt(
+    data_key: &DataKey,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let mut header = MAGIC.to_vec();
+    header.push(VERSION);
+    header.extend(data_key.header());
+    output.write_all(&header)?;
+
+    let mut buf = vec![0; CHUNK_LEN];
+    for index in 0.. {
+        let len = read_full(input, &mut buf)?;
+        let last = len < CHUNK_LEN;
+        output.write_all(&seal(
+            data_key.key(),
+            &chunk_nonce(index, last),
+            &header,
+            &buf[..len],
+        ))?;
+        if last {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Reads what `encrypt` wrote, having the device unwrap the data key. Each chunk is checked
+/// before it's written, but a damaged file can still leave some of its plaintext written before
+/// the error.
+pub(crate) fn decrypt(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let mut buf = vec![0; CHUNK_LEN + TAG_LEN];
+    let len = read_full(input, &mut buf)?;
+    let mut reader = Reader(&buf[..len]);
+    if reader.bytes(MAGIC.len()).ok() != Some(MAGIC) {
+        bail!("not a file encrypted by encrypt-file");
+    }
+    match reader.u8()? {
+        VERSION => {}
+        x => bail!("unsupported version {} (this supports {})", x, VERSION),
+    }
+    let data_key =
+        DataKey::from_header(protocol_adapter, &mut reader).context("couldn't read the header")?;
+    let header_len = len - reader.0.len();
+    let header = buf[..header_len].to_vec();
+
+    // The rest of what was read already is the start of the first chunk.
+    buf.copy_within(header_len..len, 0);
+    let mut len = len - header_len;
+    for index in 0.. {
+        len += read_full(input, &mut buf[len..])?;
+        let last = len < buf.len();
+        let plaintext = open(data_key.key(), &chunk_nonce(index, last), &header, &buf[..len])
+            .context("couldn't decrypt: the file is damaged or truncated, or the device doesn't have the seed (and passphrase) it was encrypted with")?;
+        output.write_all(&plaintext)?;
+        if last {
+            break;
+        }
+        len = 0;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Transport;
+    use core::time::Duration;
+    use std::{collections::VecDeque, path::Path};
+
+    /// What the stand-in device wraps keys with.
+    const WRAP: u8 = 0x5a;
+
+    /// Answers CipherKeyValue by XORing the value with `WRAP`.
+    #[derive(Default)]
+    struct StandIn {
+        replies: VecDeque<Message>,
+    }
+
+    impl Transport for StandIn {
+        type Error = std::io::Error;
+
+        fn write(&mut self, msg: &[u8], _: Duration) -> Result<usize, Self::Error> {
+            let reply = match Message::decode(&mut &*msg).unwrap() {
+                Message::CipherKeyValue(x) => messages::CipheredKeyValue {
+                    value: x.value.map(|x| x.iter().map(|x| x ^ WRAP).collect()),
+                }
+                .into(),
+                x => panic!("unexpected message {:?}", x),
+            };
+            self.replies.push_back(reply);
+            Ok(msg.len())
+        }
+
+        fn read(&mut self, buf: &mut Vec<u8>, _: Duration) -> Result<(), Self::Error> {
+            self.replies.pop_front().unwrap().encode(buf).unwrap();
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    fn data_key() -> DataKey {
+        let key = [7; KEY_LEN];
+        DataKey {
+            address: vec![0x8000_2720, 0],
+            key_name: "test".to_owned(),
+            iv: [1; IV_LEN],
+            wrapped: key.map(|x| x ^ WRAP),
+            key,
+        }
+    }
+
+    fn encrypted(plaintext: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        encrypt(&data_key(), &mut &*plaintext, &mut out).unwrap();
+        out
+    }
+
+    fn decrypted(file: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        decrypt(&mut StandIn::default(), &mut &*file, &mut out)?;
+        Ok(out)
+    }
+
+    fn header_len() -> usize {
+        MAGIC.len() + 1 + data_key().header().len()
+    }
+
+    #[test]
+    fn seals_rfc_8439_example() {
+        // The AEAD example from RFC 8439 section 2.8.2.
+        let key: [u8; KEY_LEN] = core::array::from_fn(|x| 0x80 + x as u8);
+        let nonce = hex::decode("070000004041424344454647").unwrap();
+        let aad = hex::decode("50515253c0c1c2c3c4c5c6c7").unwrap();
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let nonce = nonce[..].try_into().unwrap();
+        let sealed = seal(&key, nonce, &aad, plaintext);
+        assert_eq!(sealed.len(), plaintext.len() + TAG_LEN);
+        assert!(hex::encode(&sealed).starts_with("d31a8d34648e60db7b86afbc53ef7ec2"));
+        assert!(hex::encode(&sealed).ends_with("1ae10b594f09e26a7e902ecbd0600691"));
+        assert_eq!(open(&key, nonce, &aad, &sealed).unwrap(), plaintext);
+        assert!(open(&key, nonce, &[], &sealed).is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        for len in [0, 1, CHUNK_LEN - 1, CHUNK_LEN + 1, 3 * CHUNK_LEN + 5] {
+            let plaintext: Vec<u8> = (0..len).map(|x| x as u8).collect();
+            let file = encrypted(&plaintext);
+            let chunks = len / CHUNK_LEN + 1;
+            assert_eq!(file.len(), header_len() + len + chunks * TAG_LEN);
+            assert_eq!(decrypted(&file).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn ends_whole_chunks_with_an_empty_one() {
+        let plaintext = vec![3; 2 * CHUNK_LEN];
+        let file = encrypted(&plaintext);
+        assert_eq!(
+            file.len(),
+            header_len() + 2 * (CHUNK_LEN + TAG_LEN) + TAG_LEN
+        );
+        assert_eq!(decrypted(&file).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn rejects_truncation_at_a_chunk_boundary() {
+        let file = encrypted(&vec![3; 2 * CHUNK_LEN]);
+        // Without the empty last chunk, and without the last full one too.
+        assert!(decrypted(&file[..file.len() - TAG_LEN]).is_err());
+        assert!(decrypted(&file[..header_len() + CHUNK_LEN + TAG_LEN]).is_err());
+        assert!(decrypted(&file[..header_len()]).is_err());
+    }
+
+    #[test]
+    fn rejects_a_changed_header() {
+        let mut file = encrypted(b"hello");
+        // The last byte of the IV, which the stand-in doesn't need to unwrap the key.
+        file[header_len() - KEY_LEN - 1] ^= 1;
+        assert!(decrypted(&file).is_err());
+    }
+
+    #[test]
+    fn rejects_a_changed_chunk() {
+        let mut file = encrypted(b"hello");
+        file[header_len()] ^= 1;
+        assert!(decrypted(&file).is_err());
+    }
+
+    #[test]
+    fn keeps_the_output_when_decrypting_fails() {
+        let dir = std::env::temp_dir().join(format!("kkcli-encryption-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("output").to_str().unwrap().to_owned();
+        fs::write(&path, b"existing").unwrap();
+
+        let mut file = encrypted(b"hello");
+        file[header_len()] ^= 1;
+        let mut protocol_adapter = StandIn::default();
+        let result = write_output(&path, true, |out| {
+            decrypt(&mut protocol_adapter, &mut &file[..], out)
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"existing");
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+
+        // Without --force it isn't touched even when decrypting would work.
+        let file = encrypted(b"hello");
+        let result = write_output(&path, false, |out| {
+            decrypt(&mut protocol_adapter, &mut &file[..], out)
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"existing");
+
+        write_output(&path, true, |out| {
+            decrypt(&mut protocol_adapter, &mut &file[..], out)
+        })
+        .unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
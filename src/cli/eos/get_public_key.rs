@@ -1,6 +1,6 @@
 use crate::{
     cli::{
-        expect_field, expect_message,
+        expect_field, expect_message, output,
         parsers::Bip32PathParser,
         types::{Bip32Path, EosPublicKeyKind},
         CliCommand,
@@ -38,7 +38,8 @@ impl CliCommand for EosGetPublicKey {
             )
         )?;
 
-        println!("{}", expect_field!(resp.wif_public_key)?);
+        let public_key = expect_field!(resp.wif_public_key)?;
+        output!("publicKey" => public_key, "{}", public_key);
 
         Ok(())
     }
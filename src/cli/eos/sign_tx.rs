@@ -1,7 +1,7 @@
 use super::schema::Transaction;
 use crate::{
     cli::{
-        expect_field, expect_message,
+        expect_field, expect_message, output,
         parsers::{Bip32PathParser, HexParser32, SerdeJsonFileOrLiteralParser},
         types::Bip32Path,
         CliCommand,
@@ -72,7 +72,8 @@ impl CliCommand for EosSignTx {
         let v: u8 = v.try_into()?;
         assert_eq!(r.len(), 32);
         assert_eq!(s.len(), 32);
-        println!("{}{}{}", hex::encode(r), hex::encode(s), hex::encode([v]));
+        let signature = format!("{}{}{}", hex::encode(r), hex::encode(s), hex::encode([v]));
+        output!("signature" => signature, "{}", signature);
 
         Ok(())
     }
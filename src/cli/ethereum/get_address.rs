@@ -1,5 +1,8 @@
 use crate::{
-    cli::{expect_field, expect_message, parsers::Bip32PathParser, types::Bip32Path, CliCommand},
+    cli::{
+        expect_field, expect_message, output, parsers::Bip32PathParser, types::Bip32Path,
+        CliCommand,
+    },
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
@@ -30,7 +33,8 @@ impl CliCommand for EthereumGetAddress {
             )
         )?;
 
-        println!("{}", expect_field!(resp.address_str)?);
+        let address = expect_field!(resp.address_str)?;
+        output!("address" => address, "{}", address);
 
         Ok(())
     }
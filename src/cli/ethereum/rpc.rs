@@ -0,0 +1,772 @@
+use super::sign_tx::sign_tx;
+use crate::{
+    cli::{
+        expect_field, expect_message,
+        http::{self, Request, Response},
+        parsers::Bip32PathParser,
+        serve::{
+            rpc_response, RpcError, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR,
+        },
+        types::{Bip32Path, IntoBigEndian},
+        CliCommand,
+    },
+    messages::{self, Message},
+    transport::ProtocolAdapter,
+};
+use anyhow::{bail, Result};
+use clap::Args;
+use primitive_types::U256;
+use serde_json::{json, Map, Value};
+use std::{
+    net::SocketAddr,
+    sync::mpsc::{channel, Sender},
+};
+
+/// Sign Ethereum transactions and messages for dapp tools through a JSON-RPC endpoint on localhost
+///
+/// Point ethers, web3 or anything else that can use an external signer (like Clef) at
+/// http://127.0.0.1:8550/. It answers eth_accounts, eth_signTransaction, personal_sign and
+/// eth_sign, and each signature has to be confirmed on the device. There's no node to fill in
+/// missing fields, so transactions have to give their nonce, gas limit and fees; the result is the
+/// signed raw transaction, ready for eth_sendRawTransaction.
+#[derive(Debug, Clone, Args)]
+pub struct EthereumRpc {
+    /// BIP-32 path to an account to offer (may be repeated)
+    #[clap(short = 'n', long, value_parser = Bip32PathParser, default_value = "m/44'/60'/0'/0/0")]
+    address: Vec<Bip32Path>,
+    /// EIP-155 chain id for transactions that don't give one
+    #[clap(short, long, default_value_t = 1)]
+    chain_id: u32,
+    /// localhost address to listen on
+    #[clap(long, value_parser, default_value = "127.0.0.1:8550")]
+    listen: SocketAddr,
+    /// web origin allowed to make requests, like https://dapp.example (may be repeated; pages served from localhost are always allowed)
+    #[clap(long = "allow-origin")]
+    allowed_origins: Vec<String>,
+}
+
+/// A request waiting its turn on the device, and where to send its outcome.
+struct Job {
+    method: String,
+    params: Value,
+    reply: Sender<Result<Value, RpcError>>,
+}
+
+struct Account {
+    address: String,
+    path: Bip32Path,
+}
+
+struct Signer {
+    accounts: Vec<Account>,
+    chain_id: u32,
+}
+
+fn invalid_params(message: impl ToString) -> RpcError {
+    RpcError::new(INVALID_PARAMS, message)
+}
+
+fn param(params: &Value, index: usize) -> Result<&Value, RpcError> {
+    params
+        .as_array()
+        .and_then(|x| x.get(index))
+        .ok_or_else(|| invalid_params(format!("expected at least {} params", index + 1)))
+}
+
+fn hex_bytes(x: &Value, name: &str) -> Result<Vec<u8>, RpcError> {
+    x.as_str()
+        .and_then(|x| x.strip_prefix("0x"))
+        .and_then(|x| hex::decode(x).ok())
+        .ok_or_else(|| invalid_params(format!("{} must be 0x-prefixed hex", name)))
+}
+
+fn quantity(x: &Value, name: &str) -> Result<U256, RpcError> {
+    match x {
+        Value::String(x) => x
+            .strip_prefix("0x")
+            .and_then(|x| U256::from_str_radix(x, 16).ok()),
+        Value::Number(x) => x.as_u64().map(U256::from),
+        _ => None,
+    }
+    .ok_or_else(|| invalid_params(format!("{} must be a 0x-prefixed hex number", name)))
+}
+
+/// Messages are meant to be hex, but some tools send text as it is.
+fn message_bytes(x: &Value) -> Result<Vec<u8>, RpcError> {
+    match x.as_str() {
+        Some(text) => Ok(hex_bytes(x, "message").unwrap_or_else(|_| text.as_bytes().to_vec())),
+        None => Err(invalid_params("message must be a string")),
+    }
+}
+
+fn rlp_header(out: &mut Vec<u8>, offset: u8, len: usize) {
+    if len < 56 {
+        out.push(offset + len as u8);
+    } else {
+        let len = (len as u64).to_be_bytes();
+        let len = &len[len.iter().take_while(|x| **x == 0).count()..];
+        out.push(offset + 55 + len.len() as u8);
+        out.extend_from_slice(len);
+    }
+}
+
+fn rlp_bytes(x: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    if x.len() != 1 || x[0] >= 0x80 {
+        rlp_header(&mut out, 0x80, x.len());
+    }
+    out.extend_from_slice(x);
+    out
+}
+
+fn rlp_int(x: U256) -> Vec<u8> {
+    rlp_bytes(&x.into_big_endian())
+}
+
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    let mut out = Vec::new();
+    rlp_header(&mut out, 0xc0, payload.len());
+    out.extend(payload);
+    out
+}
+
+/// The fields of an eth_signTransaction request, checked and parsed.
+struct Transaction {
+    path: Bip32Path,
+    chain_id: u32,
+    nonce: U256,
+    gas_limit: U256,
+    to: Option<Vec<u8>>,
+    value: U256,
+    data: Vec<u8>,
+    fees: Fees,
+}
+
+#[derive(Clone, Copy)]
+enum Fees {
+    Legacy {
+        gas_price: U256,
+    },
+    Eip1559 {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+}
+
+impl Signer {
+    fn account(&self, address: Option<&Value>) -> Result<&Account, RpcError> {
+        let address = match address {
+            None | Some(Value::Null) if self.accounts.len() == 1 => return Ok(&self.accounts[0]),
+            None | Some(Value::Null) => return Err(invalid_params("from is required")),
+            Some(x) => x
+                .as_str()
+                .ok_or_else(|| invalid_params("address must be a string"))?,
+        };
+        self.accounts
+            .iter()
+            .find(|x| x.address.eq_ignore_ascii_case(address))
+            .ok_or_else(|| invalid_params(format!("unknown account {}", address)))
+    }
+
+    fn transaction(&self, tx: &Map<String, Value>) -> Result<Transaction, RpcError> {
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|x| tx.get(*x).filter(|x| !x.is_null()))
+        };
+        let required = |name: &str| {
+            field(&[name]).ok_or_else(|| invalid_params(format!("{} is required", name)))
+        };
+
+        if let Some(x) = field(&["accessList"]) {
+            if x.as_array().map(Vec::len) != Some(0) {
+                return Err(invalid_params("access lists aren't supported"));
+            }
+        }
+        let eip1559 = match field(&["type"]).map(|x| quantity(x, "type")).transpose()? {
+            None => field(&["maxFeePerGas"]).is_some(),
+            Some(x) if x.is_zero() => false,
+            Some(x) if x == 2.into() => true,
+            Some(_) => {
+                return Err(invalid_params(
+                    "only legacy and EIP-1559 transactions are supported",
+                ))
+            }
+        };
+        let fees = if eip1559 {
+            Fees::Eip1559 {
+                max_fee_per_gas: quantity(required("maxFeePerGas")?, "maxFeePerGas")?,
+                max_priority_fee_per_gas: quantity(
+                    required("maxPriorityFeePerGas")?,
+                    "maxPriorityFeePerGas",
+                )?,
+            }
+        } else {
+            Fees::Legacy {
+                gas_price: quantity(required("gasPrice")?, "gasPrice")?,
+            }
+        };
+        let chain_id = match field(&["chainId"]) {
+            Some(x) => quantity(x, "chainId")?
+                .try_into()
+                .map_err(|_| invalid_params("chainId is too big"))?,
+            None => self.chain_id,
+        };
+        if eip1559 && chain_id == 0 {
+            return Err(invalid_params("EIP-1559 transactions need a chainId"));
+        }
+        let to = field(&["to"]).map(|x| hex_bytes(x, "to")).transpose()?;
+        if matches!(&to, Some(x) if x.len() != 20) {
+            return Err(invalid_params("to must be 20 bytes"));
+        }
+
+        Ok(Transaction {
+            path: self.account(field(&["from"]))?.path.clone(),
+            chain_id,
+            nonce: quantity(required("nonce")?, "nonce")?,
+            gas_limit: quantity(
+                field(&["gas", "gasLimit"]).ok_or_else(|| invalid_params("gas is required"))?,
+                "gas",
+            )?,
+            to,
+            value: field(&["value"])
+                .map(|x| quantity(x, "value"))
+                .transpose()?
+                .unwrap_or_default(),
+            data: field(&["data", "input"])
+                .map(|x| hex_bytes(x, "data"))
+                .transpose()?
+                .unwrap_or_default(),
+            fees,
+        })
+    }
+
+    fn sign_transaction(
+        &self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        tx: Transaction,
+    ) -> Result<Value> {
+        let (gas_price, max_fee_per_gas, max_priority_fee_per_gas) = match tx.fees {
+            Fees::Legacy { gas_price } => (Some(gas_price), None, None),
+            Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => (None, Some(max_fee_per_gas), Some(max_priority_fee_per_gas)),
+        };
+        let resp = sign_tx(
+            protocol_adapter,
+            messages::EthereumSignTx {
+                address_n: tx.path.into(),
+                nonce: Some(tx.nonce.into_big_endian()),
+                gas_price: gas_price.map(|x| x.into_big_endian()),
+                gas_limit: Some(tx.gas_limit.into_big_endian()),
+                to: tx.to.clone(),
+                value: Some(tx.value.into_big_endian()),
+                max_fee_per_gas: max_fee_per_gas.map(|x| x.into_big_endian()),
+                max_priority_fee_per_gas: max_priority_fee_per_gas.map(|x| x.into_big_endian()),
+                chain_id: if tx.chain_id == 0 {
+                    None
+                } else {
+                    Some(tx.chain_id)
+                },
+                address_type: None,
+                r#type: Some(if max_fee_per_gas.is_some() { 2 } else { 0 }),
+                data_length: None,
+                data_initial_chunk: None,
+                to_address_n: Vec::new(),
+                token_value: None,
+                token_to: None,
+                token_shortcut: None,
+                tx_type: None,
+            },
+            Some(&tx.data[..]).filter(|x| !x.is_empty()),
+        )?;
+
+        let v = *expect_field!(resp.signature_v)?;
+        let r = U256::from_big_endian(expect_field!(resp.signature_r)?);
+        let s = U256::from_big_endian(expect_field!(resp.signature_s)?);
+        // Depending on the firmware and the kind of transaction, v may come back as the bare
+        // recovery id, offset by 27 or offset by EIP-155's 35 + 2 * chain id.
+        let recovery_id = match v {
+            0 | 1 => v,
+            27 | 28 => v - 27,
+            v if v >= 35 => (v - 35) % 2,
+            v => bail!("device returned an unexpected signature v of {}", v),
+        };
+
+        let to = rlp_bytes(tx.to.as_deref().unwrap_or_default());
+        let data = rlp_bytes(&tx.data);
+        let raw = match tx.fees {
+            Fees::Legacy { gas_price } => {
+                let v = match tx.chain_id {
+                    0 => 27 + u64::from(recovery_id),
+                    x => 35 + 2 * u64::from(x) + u64::from(recovery_id),
+                };
+                rlp_list(&[
+                    rlp_int(tx.nonce),
+                    rlp_int(gas_price),
+                    rlp_int(tx.gas_limit),
+                    to,
+                    rlp_int(tx.value),
+                    data,
+                    rlp_int(v.into()),
+                    rlp_int(r),
+                    rlp_int(s),
+                ])
+            }
+            Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                let mut raw = vec![2];
+                raw.extend(rlp_list(&[
+                    rlp_int(tx.chain_id.into()),
+                    rlp_int(tx.nonce),
+                    rlp_int(max_priority_fee_per_gas),
+                    rlp_int(max_fee_per_gas),
+                    rlp_int(tx.gas_limit),
+                    to,
+                    rlp_int(tx.value),
+                    data,
+                    rlp_list(&[]),
+                    rlp_int(recovery_id.into()),
+                    rlp_int(r),
+                    rlp_int(s),
+                ]));
+                raw
+            }
+        };
+        Ok(json!(format!("0x{}", hex::encode(raw))))
+    }
+
+    fn sign_message(
+        &self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        account: &Account,
+        message: Vec<u8>,
+    ) -> Result<Value> {
+        let resp = expect_message!(
+            Message::EthereumMessageSignature,
+            protocol_adapter.with_standard_handler().handle(
+                messages::EthereumSignMessage {
+                    address_n: account.path.clone().into(),
+                    message,
+                }
+                .into()
+            )
+        )?;
+
+        let address = format!("0x{}", hex::encode(expect_field!(resp.address)?));
+        if !address.eq_ignore_ascii_case(&account.address) {
+            bail!(
+                "device signed with {} instead of {}; was the passphrase changed?",
+                address,
+                account.address
+            );
+        }
+        Ok(json!(format!(
+            "0x{}",
+            hex::encode(expect_field!(resp.signature)?)
+        )))
+    }
+
+    fn call(
+        &self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, RpcError> {
+        let outcome = match method {
+            "eth_accounts" => return Ok(self.accounts.iter().map(|x| json!(x.address)).collect()),
+            "eth_signTransaction" => {
+                let tx = param(params, 0)?
+                    .as_object()
+                    .ok_or_else(|| invalid_params("expected a transaction object"))?;
+                let tx = self.transaction(tx)?;
+                self.sign_transaction(protocol_adapter, tx)
+            }
+            "personal_sign" => {
+                let message = message_bytes(param(params, 0)?)?;
+                let account = self.account(Some(param(params, 1)?))?;
+                self.sign_message(protocol_adapter, account, message)
+            }
+            "eth_sign" => {
+                let account = self.account(Some(param(params, 0)?))?;
+                let message = message_bytes(param(params, 1)?)?;
+                self.sign_message(protocol_adapter, account, message)
+            }
+            x => {
+                return Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("no such method: {}", x),
+                ))
+            }
+        };
+        outcome.map_err(|e| RpcError::from_error(&e))
+    }
+}
+
+fn call(request: &Value, jobs: &Sender<Job>) -> Result<Value, RpcError> {
+    if request.get("jsonrpc") != Some(&json!("2.0")) {
+        return Err(RpcError::new(INVALID_REQUEST, "expected jsonrpc 2.0"));
+    }
+    let method = request
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new(INVALID_REQUEST, "expected a method name"))?;
+
+    let (reply, outcome) = channel();
+    jobs.send(Job {
+        method: method.to_owned(),
+        params: request.get("params").cloned().unwrap_or(Value::Null),
+        reply,
+    })
+    .map_err(|_| RpcError::new(INVALID_REQUEST, "server is shutting down"))?;
+    outcome
+        .recv()
+        .map_err(|_| RpcError::new(INVALID_REQUEST, "server is shutting down"))?
+}
+
+/// Handles one request, returning the response to send, if any.
+fn handle_request(request: &Value, jobs: &Sender<Job>) -> Option<Value> {
+    match request.get("id") {
+        // Notifications get no response, but they're still run.
+        None if request.is_object() => {
+            call(request, jobs).ok();
+            None
+        }
+        id => Some(rpc_response(
+            id.cloned().unwrap_or(Value::Null),
+            call(request, jobs),
+        )),
+    }
+}
+
+fn handle_http(request: Request, jobs: &Sender<Job>) -> Response {
+    let response = match serde_json::from_slice::<Value>(&request.body) {
+        Err(e) => Some(rpc_response(
+            Value::Null,
+            Err(RpcError::new(PARSE_ERROR, e)),
+        )),
+        Ok(Value::Array(x)) if x.is_empty() => Some(rpc_response(
+            Value::Null,
+            Err(RpcError::new(INVALID_REQUEST, "empty batch")),
+        )),
+        Ok(Value::Array(x)) => {
+            let responses = x
+                .iter()
+                .filter_map(|x| handle_request(x, jobs))
+                .collect::<Vec<_>>();
+            Some(responses).filter(|x| !x.is_empty()).map(Value::Array)
+        }
+        Ok(x) => handle_request(&x, jobs),
+    };
+    match response {
+        Some(x) => Response::json(&x),
+        None => Response::empty(204),
+    }
+}
+
+impl CliCommand for EthereumRpc {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let mut accounts = Vec::new();
+        for path in self.address {
+            let resp = expect_message!(
+                Message::EthereumAddress,
+                protocol_adapter.with_standard_handler().handle(
+                    messages::EthereumGetAddress {
+                        address_n: path.clone().into(),
+                        show_display: None,
+                    }
+                    .into(),
+                )
+            )?;
+            let address = expect_field!(resp.address_str)?.clone();
+            eprintln!("{}\t{}", address, path);
+            accounts.push(Account { address, path });
+        }
+        let signer = Signer {
+            accounts,
+            chain_id: self.chain_id,
+        };
+
+        let listener = http::bind(self.listen)?;
+        let allowed_origins = self.allowed_origins;
+        // Requests queue up here; running them all on this thread is what keeps two of them from
+        // talking to the device at once.
+        let (jobs, queue) = channel();
+        std::thread::spawn(move || {
+            http::serve(listener, allowed_origins, move |request| {
+                handle_http(request, &jobs)
+            })
+        });
+
+        for Job {
+            method,
+            params,
+            reply,
+        } in queue
+        {
+            reply
+                .send(signer.call(protocol_adapter, &method, &params))
+                .ok();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Transport;
+    use core::time::Duration;
+    use sha3::{Digest, Keccak256};
+    use std::collections::VecDeque;
+
+    /// Signs every EthereumSignTx with a fixed signature, keeping the requests it was sent.
+    struct StandIn {
+        v: u32,
+        r: &'static str,
+        s: &'static str,
+        requests: Vec<messages::EthereumSignTx>,
+        replies: VecDeque<Message>,
+    }
+
+    impl StandIn {
+        fn new(v: u32, r: &'static str, s: &'static str) -> Self {
+            Self {
+                v,
+                r,
+                s,
+                requests: Vec::new(),
+                replies: VecDeque::new(),
+            }
+        }
+    }
+
+    impl Transport for StandIn {
+        type Error = std::io::Error;
+
+        fn write(&mut self, msg: &[u8], _: Duration) -> Result<usize, Self::Error> {
+            match Message::decode(&mut &*msg).unwrap() {
+                Message::EthereumSignTx(x) => self.requests.push(x),
+                x => panic!("unexpected message {:?}", x),
+            }
+            self.replies.push_back(
+                messages::EthereumTxRequest {
+                    signature_v: Some(self.v),
+                    signature_r: Some(hex::decode(self.r).unwrap()),
+                    signature_s: Some(hex::decode(self.s).unwrap()),
+                    ..Default::default()
+                }
+                .into(),
+            );
+            Ok(msg.len())
+        }
+
+        fn read(&mut self, buf: &mut Vec<u8>, _: Duration) -> Result<(), Self::Error> {
+            self.replies.pop_front().unwrap().encode(buf).unwrap();
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    fn signer() -> Signer {
+        Signer {
+            accounts: vec![Account {
+                address: "0x9d8A62f656a8d1615C1294fd71e9CFb3E4855A4F".to_owned(),
+                path: "m/44'/60'/0'/0/0".parse().unwrap(),
+            }],
+            chain_id: 1,
+        }
+    }
+
+    fn transaction(tx: Value) -> Result<Transaction, String> {
+        signer()
+            .transaction(tx.as_object().unwrap())
+            .map_err(|e| e.message)
+    }
+
+    /// Signs `tx` with the stand-in, giving the raw transaction and what the device was asked.
+    fn sign(tx: Value, device: &mut StandIn) -> Vec<u8> {
+        let tx = transaction(tx).ok().unwrap();
+        let raw = signer().sign_transaction(device, tx).unwrap();
+        hex::decode(raw.as_str().unwrap().strip_prefix("0x").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn encodes_rlp() {
+        let hex = |x: Vec<u8>| hex::encode(x);
+        assert_eq!(hex(rlp_bytes(b"")), "80");
+        assert_eq!(hex(rlp_bytes(&[0x00])), "00");
+        assert_eq!(hex(rlp_bytes(&[0x7f])), "7f");
+        assert_eq!(hex(rlp_bytes(&[0x80])), "8180");
+        assert_eq!(hex(rlp_bytes(b"dog")), "83646f67");
+        assert_eq!(
+            hex(rlp_bytes(&[0xaa; 55])),
+            format!("b7{}", "aa".repeat(55))
+        );
+        assert_eq!(
+            hex(rlp_bytes(&[0xaa; 56])),
+            format!("b838{}", "aa".repeat(56))
+        );
+        assert_eq!(
+            hex(rlp_bytes(&[0xaa; 1024])),
+            format!("b90400{}", "aa".repeat(1024))
+        );
+        assert_eq!(hex(rlp_int(0.into())), "80");
+        assert_eq!(hex(rlp_int(15.into())), "0f");
+        assert_eq!(hex(rlp_int(1024.into())), "820400");
+
+        assert_eq!(hex(rlp_list(&[])), "c0");
+        assert_eq!(
+            hex(rlp_list(&[rlp_bytes(b"cat"), rlp_bytes(b"dog")])),
+            "c88363617483646f67"
+        );
+        // The set-theoretic representation of three, [ [], [[]], [ [], [[]] ] ].
+        let zero = rlp_list(&[]);
+        let one = rlp_list(std::slice::from_ref(&zero));
+        let two = rlp_list(&[zero.clone(), one.clone()]);
+        assert_eq!(hex(rlp_list(&[zero, one, two])), "c7c0c1c0c3c0c1c0");
+        assert_eq!(
+            hex(rlp_list(&[rlp_bytes(&[0xaa; 54])])),
+            format!("f7b6{}", "aa".repeat(54))
+        );
+        assert_eq!(
+            hex(rlp_list(&[rlp_bytes(&[0xaa; 55])])),
+            format!("f838b7{}", "aa".repeat(55))
+        );
+    }
+
+    #[test]
+    fn signs_legacy_transaction() {
+        // The example from EIP-155.
+        let tx = json!({
+            "nonce": "0x9",
+            "gasPrice": "0x4a817c800",
+            "gas": 21000,
+            "to": "0x3535353535353535353535353535353535353535",
+            "value": "0xde0b6b3a7640000",
+        });
+        let r = "28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276";
+        let s = "67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+        // However the firmware gives v, the transaction comes out the same.
+        for v in [0, 27, 37] {
+            let mut device = StandIn::new(v, r, s);
+            let raw = sign(tx.clone(), &mut device);
+            assert_eq!(
+                hex::encode(&raw),
+                "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+            );
+            assert_eq!(
+                hex::encode(Keccak256::digest(&raw)),
+                "33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788"
+            );
+            let request = &device.requests[0];
+            assert_eq!(request.chain_id, Some(1));
+            assert_eq!(request.r#type, Some(0));
+            assert_eq!(request.nonce.as_deref(), Some(&[9][..]));
+        }
+    }
+
+    #[test]
+    fn signs_eip1559_transaction() {
+        // Mainnet transaction 0x938913ef1df8cd17e0893a85586ade463014559fb1bd2d536ac282f3b1bdea53.
+        let tx = json!({
+            "type": "0x2",
+            "chainId": "0x1",
+            "nonce": "0x1bb",
+            "maxPriorityFeePerGas": "0x5f5e100",
+            "maxFeePerGas": "0x96a1d45b7",
+            "gas": "0x5208",
+            "to": "0xd696a5c568160bbbf5a1356f8ac56ee81a190588",
+            "value": "0x1550f7dca70000",
+            "accessList": [],
+        });
+        let mut device = StandIn::new(
+            0,
+            "7df2299b0181d6d5b817795a7d2eff5897d0d3914ff5f602e17d5b75d32ec25f",
+            "51833973e8a8c222e682d2dcea02ad7bf3ec5bc3a86bfbcdbbaa3b853e52ad08",
+        );
+        let raw = sign(tx, &mut device);
+        assert_eq!(
+            hex::encode(&raw),
+            "02f874018201bb8405f5e10085096a1d45b782520894d696a5c568160bbbf5a1356f8ac56ee81a190588871550f7dca7000080c080a07df2299b0181d6d5b817795a7d2eff5897d0d3914ff5f602e17d5b75d32ec25fa051833973e8a8c222e682d2dcea02ad7bf3ec5bc3a86bfbcdbbaa3b853e52ad08"
+        );
+        assert_eq!(
+            hex::encode(Keccak256::digest(&raw)),
+            "938913ef1df8cd17e0893a85586ade463014559fb1bd2d536ac282f3b1bdea53"
+        );
+        let request = &device.requests[0];
+        assert_eq!(request.r#type, Some(2));
+        assert_eq!(request.gas_price, None);
+        assert_eq!(
+            request.max_fee_per_gas,
+            Some(hex::decode("096a1d45b7").unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_transaction_params() {
+        let tx = transaction(json!({
+            "from": "0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f",
+            "nonce": "0x10",
+            "gasLimit": 21000,
+            "gasPrice": "0x0",
+            "input": "0xabcd",
+            "chainId": "0x89",
+        }))
+        .ok()
+        .unwrap();
+        assert_eq!(tx.nonce, 16.into());
+        assert_eq!(tx.gas_limit, 21000.into());
+        assert_eq!(tx.chain_id, 137);
+        assert_eq!(tx.to, None);
+        assert_eq!(tx.value, 0.into());
+        assert_eq!(tx.data, [0xab, 0xcd]);
+        assert!(matches!(tx.fees, Fees::Legacy { gas_price } if gas_price.is_zero()));
+
+        // EIP-1559 is picked from the fees when there's no type, and the chain id defaults.
+        let tx = transaction(json!({
+            "nonce": 0,
+            "gas": "0x5208",
+            "maxFeePerGas": "0x2",
+            "maxPriorityFeePerGas": "0x1",
+        }))
+        .ok()
+        .unwrap();
+        assert_eq!(tx.chain_id, 1);
+        assert!(matches!(tx.fees, Fees::Eip1559 { .. }));
+
+        let base = json!({ "nonce": "0x0", "gas": "0x5208", "gasPrice": "0x1" });
+        let with = |key: &str, value: Value| {
+            let mut tx = base.clone();
+            tx[key] = value;
+            transaction(tx).err()
+        };
+        let without = |key: &str| {
+            let mut tx = base.clone();
+            tx.as_object_mut().unwrap().remove(key);
+            transaction(tx).err()
+        };
+        assert_eq!(without("nonce").unwrap(), "nonce is required");
+        assert_eq!(without("gas").unwrap(), "gas is required");
+        assert_eq!(without("gasPrice").unwrap(), "gasPrice is required");
+        assert!(with("nonce", json!("10")).is_some());
+        assert!(with("nonce", json!("0xzz")).is_some());
+        assert_eq!(with("to", json!("0x1234")).unwrap(), "to must be 20 bytes");
+        assert!(with("data", json!("abcd")).is_some());
+        assert!(with("type", json!("0x1")).is_some());
+        assert!(with("accessList", json!([{}])).is_some());
+        assert!(with("from", json!("0x0000000000000000000000000000000000000001")).is_some());
+        assert_eq!(
+            with("type", json!("0x2")).unwrap(),
+            "maxFeePerGas is required"
+        );
+        assert!(with("to", json!("0x3535353535353535353535353535353535353535")).is_none());
+    }
+}
@@ -1,5 +1,8 @@
 use crate::{
-    cli::{expect_field, expect_message, parsers::Bip32PathParser, types::Bip32Path, CliCommand},
+    cli::{
+        expect_field, expect_message, output, parsers::Bip32PathParser, types::Bip32Path,
+        CliCommand,
+    },
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
@@ -29,11 +32,10 @@ impl CliCommand for EthereumSignMessage {
             )
         )?;
 
-        println!("Address:\t0x{}", hex::encode(expect_field!(resp.address)?));
-        println!(
-            "Signature:\t0x{}",
-            hex::encode(expect_field!(resp.signature)?)
-        );
+        let address = format!("0x{}", hex::encode(expect_field!(resp.address)?));
+        output!("address" => address, "Address:\t{}", address);
+        let signature = format!("0x{}", hex::encode(expect_field!(resp.signature)?));
+        output!("signature" => signature, "Signature:\t{}", signature);
 
         Ok(())
     }
@@ -1,6 +1,6 @@
 use crate::{
     cli::{
-        expect_field, expect_message,
+        expect_field, expect_message, output,
         parsers::{Bip32PathParser, HexParser, HexParser20, U256Parser},
         types::{Bip32Path, ByteVec},
         types::{IntoBigEndian, OutputAddressType},
@@ -61,80 +61,94 @@ pub struct EthereumSignTx {
     max_priority_fee_per_gas: Option<U256>,
 }
 
-impl CliCommand for EthereumSignTx {
-    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
-        let data_length = self.data.as_ref().map(|x| x.len().try_into().unwrap());
-        let mut data = self.data.as_ref().map(|x| x.split_at(min(x.len(), 1024)));
-        let data_initial_chunk = data.map(|x| x.0.to_owned());
+/// Sends `tx` with `data`, feeding the device the rest of the data as it asks for it, and returns
+/// the signature.
+pub(crate) fn sign_tx(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    mut tx: messages::EthereumSignTx,
+    data: Option<&[u8]>,
+) -> Result<messages::EthereumTxRequest> {
+    tx.data_length = data.map(|x| x.len().try_into().unwrap());
+    let mut data = data.map(|x| x.split_at(min(x.len(), 1024)));
+    tx.data_initial_chunk = data.map(|x| x.0.to_owned());
 
-        let resp = expect_message!(
-            Message::EthereumTxRequest,
-            protocol_adapter
-                .with_standard_handler()
-                .with_mut_handler(&mut |msg| {
-                    Ok(match msg {
-                        Message::EthereumTxRequest(messages::EthereumTxRequest {
-                            data_length: Some(data_length),
-                            ..
-                        }) => {
-                            data = data.map(|(_, x)| {
-                                x.split_at(min(x.len(), (*data_length).try_into().unwrap()))
-                            });
-                            Some(
-                                messages::EthereumTxAck {
-                                    data_chunk: data.map(|x| x.0.to_owned()),
-                                }
-                                .into(),
-                            )
-                        }
-                        _ => None,
-                    })
-                },)
-                .handle(
-                    messages::EthereumSignTx {
-                        address_n: self.address.into(),
-                        nonce: Some(self.nonce.into_big_endian()),
-                        gas_price: self.gas_price.map(|x| x.into_big_endian()),
-                        gas_limit: Some(self.gas_limit.into_big_endian()),
-                        to: self.to.map(|x| x.to_vec()),
-                        value: self.value.map(|x| x.into_big_endian()),
-                        max_fee_per_gas: self.max_fee_per_gas.map(|x| x.into_big_endian()),
-                        max_priority_fee_per_gas: self
-                            .max_priority_fee_per_gas
-                            .map(|x| x.into_big_endian()),
-                        chain_id: if self.chain_id == 0 {
-                            None
-                        } else {
-                            Some(self.chain_id)
-                        },
-                        address_type: self
-                            .to_path
-                            .as_ref()
-                            .map_or_else(|| None, |_| Some(OutputAddressType::Transfer as i32)),
-                        r#type: self
-                            .max_priority_fee_per_gas
-                            .map_or_else(|| Some(0), |_| Some(2)),
-                        data_length,
-                        data_initial_chunk,
-                        to_address_n: self.to_path.unwrap_or_default().into(),
-                        token_value: None,
-                        token_to: None,
-                        token_shortcut: None,
-                        tx_type: None,
+    let resp = expect_message!(
+        Message::EthereumTxRequest,
+        protocol_adapter
+            .with_standard_handler()
+            .with_mut_handler(&mut |msg| {
+                Ok(match msg {
+                    Message::EthereumTxRequest(messages::EthereumTxRequest {
+                        data_length: Some(data_length),
+                        ..
+                    }) => {
+                        data = data.map(|(_, x)| {
+                            x.split_at(min(x.len(), (*data_length).try_into().unwrap()))
+                        });
+                        Some(
+                            messages::EthereumTxAck {
+                                data_chunk: data.map(|x| x.0.to_owned()),
+                            }
+                            .into(),
+                        )
                     }
-                    .into(),
-                )
+                    _ => None,
+                })
+            },)
+            .handle(tx.into())
+    )?;
+
+    assert_eq!(data.as_ref().map(|x| x.1.len()).unwrap_or(0), 0);
+    Ok(resp)
+}
+
+impl CliCommand for EthereumSignTx {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let resp = sign_tx(
+            protocol_adapter,
+            messages::EthereumSignTx {
+                address_n: self.address.into(),
+                nonce: Some(self.nonce.into_big_endian()),
+                gas_price: self.gas_price.map(|x| x.into_big_endian()),
+                gas_limit: Some(self.gas_limit.into_big_endian()),
+                to: self.to.map(|x| x.to_vec()),
+                value: self.value.map(|x| x.into_big_endian()),
+                max_fee_per_gas: self.max_fee_per_gas.map(|x| x.into_big_endian()),
+                max_priority_fee_per_gas: self
+                    .max_priority_fee_per_gas
+                    .map(|x| x.into_big_endian()),
+                chain_id: if self.chain_id == 0 {
+                    None
+                } else {
+                    Some(self.chain_id)
+                },
+                address_type: self
+                    .to_path
+                    .as_ref()
+                    .map_or_else(|| None, |_| Some(OutputAddressType::Transfer as i32)),
+                r#type: self
+                    .max_priority_fee_per_gas
+                    .map_or_else(|| Some(0), |_| Some(2)),
+                data_length: None,
+                data_initial_chunk: None,
+                to_address_n: self.to_path.unwrap_or_default().into(),
+                token_value: None,
+                token_to: None,
+                token_shortcut: None,
+                tx_type: None,
+            },
+            self.data.as_deref(),
         )?;
 
         let v = *expect_field!(resp.signature_v)?;
         let r = expect_field!(resp.signature_r)?;
         let s = expect_field!(resp.signature_s)?;
 
-        assert_eq!(data.as_ref().map(|x| x.1.len()).unwrap_or(0), 0);
         let v: u8 = v.try_into()?;
         assert_eq!(r.len(), 32);
         assert_eq!(s.len(), 32);
-        println!("{}{}{}", hex::encode(r), hex::encode(s), hex::encode([v]));
+        let signature = format!("{}{}{}", hex::encode(r), hex::encode(s), hex::encode([v]));
+        output!("signature" => signature, "{}", signature);
 
         Ok(())
     }
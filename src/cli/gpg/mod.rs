@@ -0,0 +1,317 @@
+mod public_key;
+mod sign;
+
+pub use public_key::*;
+pub use sign::*;
+
+use crate::{
+    cli::{
+        ssh::{armor, decompress_p256, ed25519_key},
+        system::{identity_public_key, sign_with_identity},
+    },
+    messages,
+    transport::ProtocolAdapter,
+};
+use anyhow::Result;
+use bitcoin::{
+    hashes::{sha1, Hash},
+    secp256k1::PublicKey,
+};
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Numbers from RFC 4880, and RFC 6637 and draft-koch-eddsa-for-openpgp for the curves.
+const TAG_SIGNATURE: u8 = 2;
+const TAG_PUBLIC_KEY: u8 = 6;
+const TAG_USER_ID: u8 = 13;
+const SIG_BINARY: u8 = 0x00;
+const SIG_POSITIVE_CERTIFICATION: u8 = 0x13;
+const SUBPACKET_CREATION_TIME: u8 = 2;
+const SUBPACKET_ISSUER: u8 = 16;
+const SUBPACKET_PREFERRED_HASH: u8 = 21;
+const SUBPACKET_KEY_FLAGS: u8 = 27;
+const SUBPACKET_ISSUER_FINGERPRINT: u8 = 33;
+const KEY_FLAGS_CERTIFY_SIGN: u8 = 0x03;
+const HASH_SHA256: u8 = 8;
+const ALGORITHM_ECDSA: u8 = 19;
+const ALGORITHM_EDDSA: u8 = 22;
+
+/// Curves the device can make OpenPGP keys on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum GpgCurve {
+    Nist256p1,
+    Secp256k1,
+    Ed25519,
+}
+
+impl GpgCurve {
+    /// What the device calls it.
+    fn curve_name(self) -> &'static str {
+        match self {
+            Self::Nist256p1 => "nist256p1",
+            Self::Secp256k1 => "secp256k1",
+            Self::Ed25519 => "ed25519",
+        }
+    }
+
+    fn algorithm(self) -> u8 {
+        match self {
+            Self::Nist256p1 | Self::Secp256k1 => ALGORITHM_ECDSA,
+            Self::Ed25519 => ALGORITHM_EDDSA,
+        }
+    }
+
+    /// The curve's OID, without the tag and length DER would give it.
+    fn oid(self) -> &'static [u8] {
+        match self {
+            Self::Nist256p1 => &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07],
+            Self::Secp256k1 => &[0x2b, 0x81, 0x04, 0x00, 0x0a],
+            Self::Ed25519 => &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xda, 0x47, 0x0f, 0x01],
+        }
+    }
+}
+
+fn packet(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![0xc0 | tag];
+    match body.len() {
+        x if x < 192 => out.push(x as u8),
+        x if x < 8384 => out.extend_from_slice(&[((x - 192) >> 8) as u8 + 192, (x - 192) as u8]),
+        x => {
+            out.push(0xff);
+            out.extend_from_slice(&(x as u32).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+fn subpacket(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![body.len() as u8 + 1, kind];
+    out.extend_from_slice(body);
+    out
+}
+
+fn mpi(x: &[u8]) -> Vec<u8> {
+    let x = &x[x.iter().take_while(|x| **x == 0).count()..];
+    let bits = x
+        .first()
+        .map_or(0, |first| x.len() * 8 - first.leading_zeros() as usize);
+    let mut out = (bits as u16).to_be_bytes().to_vec();
+    out.extend_from_slice(x);
+    out
+}
+
+fn crc24(data: &[u8]) -> u32 {
+    let mut crc = 0xb704ce;
+    for x in data {
+        crc ^= u32::from(*x) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= 0x1864cfb;
+            }
+        }
+    }
+    crc & 0xffffff
+}
+
+/// ASCII armor, like gpg --armor gives.
+fn pgp_armor(kind: &str, data: &[u8]) -> String {
+    let checksum = base64::encode(&crc24(data).to_be_bytes()[1..]);
+    armor(
+        &format!("PGP {}", kind),
+        64,
+        "\n",
+        data,
+        &format!("={}\n", checksum),
+    )
+}
+
+/// A key the device holds for a `gpg://` identity.
+pub(crate) struct GpgKey {
+    pub user_id: String,
+    curve: GpgCurve,
+    created: u32,
+    identity: messages::IdentityType,
+    public_key: Vec<u8>,
+}
+
+impl GpgKey {
+    /// Fetches the public key for `user_id`, without anything needing confirming on the device.
+    /// `created` is part of the key's fingerprint, so it has to be the same every time.
+    pub fn fetch(
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        user_id: &str,
+        curve: GpgCurve,
+        created: u32,
+    ) -> Result<Self> {
+        let identity = messages::IdentityType {
+            proto: Some("gpg".to_owned()),
+            host: Some(user_id.to_owned()),
+            ..Default::default()
+        };
+
+        let public_key = identity_public_key(protocol_adapter, &identity, curve.curve_name())?;
+
+        Ok(Self {
+            user_id: user_id.to_owned(),
+            curve,
+            created,
+            identity,
+            public_key,
+        })
+    }
+
+    /// The body of the public key packet.
+    fn key_packet_body(&self) -> Result<Vec<u8>> {
+        let point = match self.curve {
+            GpgCurve::Nist256p1 => decompress_p256(&self.public_key)?,
+            GpgCurve::Secp256k1 => PublicKey::from_slice(&self.public_key)?
+                .serialize_uncompressed()
+                .to_vec(),
+            // The 0x40 says it's a native point, as opposed to a SEC1 one.
+            GpgCurve::Ed25519 => [&[0x40], ed25519_key(&self.public_key)?].concat(),
+        };
+        let oid = self.curve.oid();
+
+        let mut out = vec![4];
+        out.extend_from_slice(&self.created.to_be_bytes());
+        out.push(self.curve.algorithm());
+        out.push(oid.len() as u8);
+        out.extend_from_slice(oid);
+        out.extend(mpi(&point));
+        Ok(out)
+    }
+
+    /// What's hashed for the fingerprint, and at the start of a certification signature.
+    fn key_hash_prefix(&self) -> Result<Vec<u8>> {
+        let body = self.key_packet_body()?;
+        let mut out = vec![0x99];
+        out.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        out.extend(body);
+        Ok(out)
+    }
+
+    pub fn fingerprint(&self) -> Result<[u8; 20]> {
+        Ok(sha1::Hash::hash(&self.key_hash_prefix()?).into_inner())
+    }
+
+    /// Makes a signature packet over `data`, which is whatever the signature type says is
+    /// hashed before the signature's own fields.
+    fn signature(
+        &self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        sig_type: u8,
+        data: &[u8],
+        subpackets: &[Vec<u8>],
+    ) -> Result<Vec<u8>> {
+        let fingerprint = self.fingerprint()?;
+        let now: u32 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs()
+            .try_into()?;
+        let mut hashed = subpacket(SUBPACKET_CREATION_TIME, &now.to_be_bytes());
+        hashed.extend(subpacket(
+            SUBPACKET_ISSUER_FINGERPRINT,
+            &[&[4], &fingerprint[..]].concat(),
+        ));
+        hashed.extend(subpackets.concat());
+
+        let mut body = vec![4, sig_type, self.curve.algorithm(), HASH_SHA256];
+        body.extend_from_slice(&(hashed.len() as u16).to_be_bytes());
+        body.extend(hashed);
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        hasher.update(&body);
+        hasher.update([4, 0xff]);
+        hasher.update((body.len() as u32).to_be_bytes());
+        let digest = hasher.finalize();
+        let signature = sign_with_identity(
+            protocol_adapter,
+            &self.identity,
+            self.curve.curve_name(),
+            &self.public_key,
+            &digest,
+        )?;
+
+        let unhashed = subpacket(SUBPACKET_ISSUER, &fingerprint[12..]);
+        body.extend_from_slice(&(unhashed.len() as u16).to_be_bytes());
+        body.extend(unhashed);
+        body.extend_from_slice(&digest[..2]);
+        body.extend(mpi(&signature[..32]));
+        body.extend(mpi(&signature[32..]));
+        Ok(packet(TAG_SIGNATURE, &body))
+    }
+
+    /// The armored public key, with its user ID and a self-signature binding the two.
+    pub fn certificate(&self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<String> {
+        let user_id = self.user_id.as_bytes();
+        let mut data = self.key_hash_prefix()?;
+        data.push(0xb4);
+        data.extend_from_slice(&(user_id.len() as u32).to_be_bytes());
+        data.extend_from_slice(user_id);
+        let signature = self.signature(
+            protocol_adapter,
+            SIG_POSITIVE_CERTIFICATION,
+            &data,
+            &[
+                subpacket(SUBPACKET_KEY_FLAGS, &[KEY_FLAGS_CERTIFY_SIGN]),
+                subpacket(SUBPACKET_PREFERRED_HASH, &[HASH_SHA256]),
+            ],
+        )?;
+
+        let mut out = packet(TAG_PUBLIC_KEY, &self.key_packet_body()?);
+        out.extend(packet(TAG_USER_ID, user_id));
+        out.extend(signature);
+        Ok(pgp_armor("PUBLIC KEY BLOCK", &out))
+    }
+
+    /// An armored detached signature over `data`.
+    pub fn sign(&self, protocol_adapter: &mut dyn ProtocolAdapter, data: &[u8]) -> Result<String> {
+        let signature = self.signature(protocol_adapter, SIG_BINARY, data, &[])?;
+        Ok(pgp_armor("SIGNATURE", &signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc24_matches_rfc_4880() {
+        // The CRC of nothing is CRC24_INIT, and 0x21cf02 is the standard check value.
+        assert_eq!(crc24(b""), 0xb704ce);
+        assert_eq!(crc24(b"123456789"), 0x21cf02);
+    }
+
+    #[test]
+    fn fingerprints_sample_key() {
+        // The sample key from draft-koch-eddsa-for-openpgp, also in RFC 9580 A.3.
+        let key = GpgKey {
+            user_id: String::new(),
+            curve: GpgCurve::Ed25519,
+            created: 0x53f35f0b,
+            identity: Default::default(),
+            public_key: hex::decode(
+                "003f098994bdd916ed4053197934e4a87c80733a1280d62f8010992e43ee3b2406",
+            )
+            .unwrap(),
+        };
+        assert_eq!(
+            pgp_armor(
+                "PUBLIC KEY BLOCK",
+                &packet(TAG_PUBLIC_KEY, &key.key_packet_body().unwrap())
+            ),
+            "-----BEGIN PGP PUBLIC KEY BLOCK-----\n\n\
+             xjMEU/NfCxYJKwYBBAHaRw8BAQdAPwmJlL3ZFu1AUxl5NOSofIBzOhKA1i+AEJku\n\
+             Q+47JAY=\n\
+             =zD4a\n\
+             -----END PGP PUBLIC KEY BLOCK-----\n"
+        );
+        assert_eq!(
+            hex::encode_upper(key.fingerprint().unwrap()),
+            "C959BDBAFA32A2F89A153B678CFDE12197965A9A"
+        );
+    }
+}
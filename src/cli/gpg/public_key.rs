@@ -0,0 +1,37 @@
+use super::{GpgCurve, GpgKey};
+use crate::{
+    cli::{output, CliCommand},
+    transport::ProtocolAdapter,
+};
+use anyhow::Result;
+use clap::Args;
+
+/// Get an OpenPGP public key for a gpg:// identity, ready for gpg --import
+///
+/// The key is the device's gpg:// identity for the user ID, and the device is asked to sign the
+/// user ID onto it. The key's creation time is part of its fingerprint, so gpg-sign has to be
+/// given the same --created for its signatures to match the key.
+#[derive(Debug, Clone, Args)]
+pub struct GpgPublicKey {
+    /// user ID, like "Alice <alice@example.com>"
+    user_id: String,
+    /// curve for the key
+    #[clap(short, long, value_enum, default_value = "nist256p1")]
+    curve: GpgCurve,
+    /// creation time of the key, in seconds since 1970
+    #[clap(long, default_value_t = 0)]
+    created: u32,
+}
+
+impl CliCommand for GpgPublicKey {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let key = GpgKey::fetch(protocol_adapter, &self.user_id, self.curve, self.created)?;
+
+        let fingerprint = hex::encode_upper(key.fingerprint()?);
+        eprintln!("Fingerprint:\t{}", fingerprint);
+        let public_key = key.certificate(protocol_adapter)?;
+        output!("publicKey" => public_key, "{}", public_key.trim_end());
+
+        Ok(())
+    }
+}
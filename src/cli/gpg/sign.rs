@@ -0,0 +1,47 @@
+use super::{GpgCurve, GpgKey};
+use crate::{cli::CliCommand, transport::ProtocolAdapter};
+use anyhow::{Context, Result};
+use clap::Args;
+use std::{
+    fs,
+    io::{stdin, stdout, Read, Write},
+};
+
+/// Make detached, armored OpenPGP signatures over files with a gpg:// identity's key
+///
+/// The key is the one gpg-public-key gives for the same user ID, curve and --created.
+#[derive(Debug, Clone, Args)]
+pub struct GpgSign {
+    /// user ID of the key, like "Alice <alice@example.com>"
+    user_id: String,
+    /// curve of the key
+    #[clap(short, long, value_enum, default_value = "nist256p1")]
+    curve: GpgCurve,
+    /// creation time of the key, in seconds since 1970
+    #[clap(long, default_value_t = 0)]
+    created: u32,
+    /// files to sign, each getting its signature written next to it with .asc added; without any, stdin is signed to stdout
+    files: Vec<String>,
+}
+
+impl CliCommand for GpgSign {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let key = GpgKey::fetch(protocol_adapter, &self.user_id, self.curve, self.created)?;
+
+        if self.files.is_empty() || self.files == ["-"] {
+            let mut data = Vec::new();
+            stdin().read_to_end(&mut data)?;
+            let signature = key.sign(protocol_adapter, &data)?;
+            stdout().write_all(signature.as_bytes())?;
+            return Ok(());
+        }
+        for file in &self.files {
+            let data = fs::read(file).with_context(|| format!("couldn't read {}", file))?;
+            let signature = key.sign(protocol_adapter, &data)?;
+            let path = format!("{}.asc", file);
+            fs::write(&path, signature).with_context(|| format!("couldn't write {}", path))?;
+            eprintln!("Wrote signature to {}", path);
+        }
+        Ok(())
+    }
+}
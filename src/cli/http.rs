@@ -0,0 +1,201 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+};
+
+/// Firmware images are the biggest thing anyone sends through the bridge, and they're about a megabyte.
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+pub(crate) struct Request {
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+pub(crate) struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    pub fn json(value: &Value) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            body: value.to_string(),
+        }
+    }
+
+    pub fn text(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/plain",
+            body,
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            ..Self::json(&json!({ "error": message }))
+        }
+    }
+
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: String::new(),
+        }
+    }
+}
+
+/// Pages served from this machine are always allowed; anything else has to be allowed explicitly.
+fn is_allowed_origin(origin: &str, allowed_origins: &[String]) -> bool {
+    if allowed_origins.iter().any(|x| x == origin) {
+        return true;
+    }
+    let host = match origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+    {
+        Some(x) => x,
+        None => return false,
+    };
+    let host = match host.rsplit_once(':') {
+        Some((host, port)) if !port.is_empty() && port.bytes().all(|x| x.is_ascii_digit()) => host,
+        _ => host,
+    };
+    ["localhost", "127.0.0.1", "[::1]"].contains(&host)
+}
+
+fn respond(stream: &mut TcpStream, origin: Option<&str>, response: Response) -> Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Error",
+    };
+    write!(stream, "HTTP/1.1 {} {}\r\n", response.status, reason)?;
+    if let Some(x) = origin {
+        write!(
+            stream,
+            "Access-Control-Allow-Origin: {}\r\nAccess-Control-Allow-Methods: POST, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\nVary: Origin\r\n",
+            x
+        )?;
+    }
+    write!(
+        stream,
+        "Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    allowed_origins: &[String],
+    handler: &(dyn Fn(Request) -> Response + Send + Sync),
+) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let (method, path) = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        [method, path, _] => (method.to_owned(), path.to_owned()),
+        _ => bail!("malformed request line"),
+    };
+
+    let mut origin = None;
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            bail!("connection closed in the middle of the headers");
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "origin" => origin = Some(value.trim().to_owned()),
+                "content-length" => content_length = value.trim().parse()?,
+                _ => (),
+            }
+        }
+    }
+
+    if let Some(x) = &origin {
+        if !is_allowed_origin(x, allowed_origins) {
+            return respond(
+                &mut stream,
+                None,
+                Response::error(403, "origin not allowed"),
+            );
+        }
+    }
+    let origin = origin.as_deref();
+    if content_length > MAX_BODY_LEN {
+        return respond(
+            &mut stream,
+            origin,
+            Response::error(413, "request too large"),
+        );
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body)?;
+
+    let response = match method.as_str() {
+        "OPTIONS" => Response::empty(204),
+        "POST" => handler(Request { path, body }),
+        _ => Response::error(405, "use POST"),
+    };
+    respond(&mut stream, origin, response)
+}
+
+/// Anyone who can connect can use the device, so only localhost addresses are allowed.
+pub(crate) fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    if !addr.ip().is_loopback() {
+        bail!("refusing to listen on {}: anyone who can connect can use the device, so only localhost addresses are allowed", addr);
+    }
+    let listener =
+        TcpListener::bind(addr).with_context(|| format!("couldn't listen on {}", addr))?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    Ok(listener)
+}
+
+/// Answers POST requests (and CORS preflights) with `handler`, one thread per connection, forever.
+pub(crate) fn serve(
+    listener: TcpListener,
+    allowed_origins: Vec<String>,
+    handler: impl Fn(Request) -> Response + Send + Sync + 'static,
+) {
+    let allowed_origins = Arc::new(allowed_origins);
+    let handler = Arc::new(handler);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("Error accepting connection: {}", e);
+                continue;
+            }
+        };
+        let allowed_origins = Arc::clone(&allowed_origins);
+        let handler = Arc::clone(&handler);
+        std::thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &allowed_origins, &*handler) {
+                eprintln!("Error: {:?}", e);
+            }
+        });
+    }
+}
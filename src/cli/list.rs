@@ -1,11 +1,121 @@
-use crate::{cli::CliCommand, transport::ProtocolAdapter};
+use crate::{
+    cli::{output, CliCommand},
+    error::DeviceError,
+    transport::{list_devices, read_features, ProtocolAdapter},
+};
 use anyhow::Result;
 use clap::Args;
+use serde_json::json;
 
 /// List connected KeepKey USB devices
 #[derive(Debug, Clone, Args)]
 pub struct List;
 
+/// Explains the usual reasons a device can't be opened.
+fn open_error_hint(error: &rusb::Error) -> String {
+    match error {
+        rusb::Error::Access => "permission denied (on Linux, install udev rules that give your user access to KeepKey devices)".to_owned(),
+        rusb::Error::Busy => "in use by another program".to_owned(),
+        x => x.to_string(),
+    }
+}
+
+impl List {
+    pub fn handle(self) -> Result<()> {
+        let mut devices = Vec::new();
+        for device in list_devices()?.iter() {
+            let device_desc = device.device_descriptor()?;
+            let location = format!(
+                "Bus {:03} Device {:03} ID {:04x}:{:04x}",
+                device.bus_number(),
+                device.address(),
+                device_desc.vendor_id(),
+                device_desc.product_id(),
+            );
+            let mut info = json!({
+                "bus": device.bus_number(),
+                "address": device.address(),
+                "vendorId": device_desc.vendor_id(),
+                "productId": device_desc.product_id(),
+            });
+
+            let device_handle = match device.open() {
+                Ok(x) => x,
+                Err(e) => {
+                    let hint = open_error_hint(&e);
+                    if !output::is_json() {
+                        println!("{}\tcan't open: {}", location, hint);
+                    }
+                    info["error"] = hint.into();
+                    devices.push(info);
+                    continue;
+                }
+            };
+            let product = device_handle.read_product_string_ascii(&device_desc);
+            let serial_number = device_handle.read_serial_number_string_ascii(&device_desc);
+            drop(device_handle);
+            let (product, serial_number) = match (product, serial_number) {
+                (Ok(product), Ok(serial_number)) => (product, serial_number),
+                (Err(e), _) | (_, Err(e)) => {
+                    let hint = format!("can't read its descriptors: {}", open_error_hint(&e));
+                    if !output::is_json() {
+                        println!("{}\t{}", location, hint);
+                    }
+                    info["error"] = hint.into();
+                    devices.push(info);
+                    continue;
+                }
+            };
+            info["product"] = product.clone().into();
+            info["serialNumber"] = serial_number.clone().into();
+
+            let state = match read_features(device) {
+                Ok(features) => {
+                    let bootloader_mode = features.bootloader_mode.unwrap_or(false);
+                    let version = features.version();
+                    info["label"] = features.label.clone().into();
+                    info["version"] = version.clone().into();
+                    info["initialized"] = features.initialized.into();
+                    info["bootloaderMode"] = bootloader_mode.into();
+                    if bootloader_mode {
+                        format!("bootloader {}", version)
+                    } else {
+                        format!(
+                            "firmware {}, {}, label \"{}\"",
+                            version,
+                            match features.initialized {
+                                Some(true) => "initialized",
+                                Some(false) => "not initialized",
+                                None => "initialized?",
+                            },
+                            features.label.as_deref().unwrap_or_default()
+                        )
+                    }
+                }
+                Err(e) => {
+                    let hint = match e.downcast_ref() {
+                        Some(DeviceError::Usb(x)) => open_error_hint(x),
+                        _ => format!("{:#}", e),
+                    };
+                    info["error"] = hint.clone().into();
+                    format!("can't read features: {}", hint)
+                }
+            };
+            if !output::is_json() {
+                println!(
+                    "{}\t\"{}\"\t({})\t{}",
+                    location, product, serial_number, state
+                );
+            }
+            devices.push(info);
+        }
+        if output::is_json() {
+            output::record("devices", &devices)?;
+        }
+        Ok(())
+    }
+}
+
 impl CliCommand for List {
     fn handle(self, _: &mut dyn ProtocolAdapter) -> Result<()> {
         unreachable!();
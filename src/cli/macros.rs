@@ -3,6 +3,9 @@ macro_rules! expect_message {
         match $target {
             Ok(x) => match x {
                 $path(y) => Ok(y),
+                crate::messages::Message::Failure(y) => {
+                    Err(crate::error::DeviceError::from(y).into())
+                }
                 y => Err(::anyhow::anyhow!("unexpected message ({:?})", y)),
             },
             Err(x) => Err(x),
@@ -13,14 +16,30 @@ pub(crate) use expect_message;
 
 macro_rules! expect_success {
     ($target:expr$(,)*) => {
-        crate::cli::expect_message!(crate::messages::Message::Success, $target).map(|x| {
-            println!("Success: {}", x.message());
-            x
+        crate::cli::expect_message!(crate::messages::Message::Success, $target).and_then(|x| {
+            if crate::cli::output::is_json() {
+                crate::cli::output::record("message", &x.message())?;
+            } else {
+                println!("Success: {}", x.message());
+            }
+            Ok(x)
         })
     };
 }
 pub(crate) use expect_success;
 
+/// Prints a line of text output, or records `$value` under `$key` in the result object in `--json` mode.
+macro_rules! output {
+    ($key:expr => $value:expr, $($fmt:tt)+) => {
+        if crate::cli::output::is_json() {
+            crate::cli::output::record($key, &$value)?;
+        } else {
+            println!($($fmt)+);
+        }
+    };
+}
+pub(crate) use output;
+
 macro_rules! expect_field {
     ($target:ident.$field:ident) => {{
         #[derive(Clone, Copy, Default)]
@@ -61,14 +80,20 @@ macro_rules! use_cli_subcommands {
             $($x($x)),*
         }
 
+        impl crate::cli::CliDebugCommand for Subcommand {
+            fn handle_debug(self, protocol_adapter: &mut dyn crate::transport::ProtocolAdapter, debug_protocol_adapter: Option<&mut dyn crate::transport::ProtocolAdapter>) -> ::anyhow::Result<()> {
+                match self {
+                    $(Subcommand::$x(cmd) => crate::cli::CliDebugCommand::handle_debug(cmd, protocol_adapter, debug_protocol_adapter)),*
+                }
+            }
+        }
+
         impl crate::cli::CliDebugCommand for Cli {
             fn handle_debug(self, protocol_adapter: &mut dyn crate::transport::ProtocolAdapter, debug_protocol_adapter: Option<&mut dyn crate::transport::ProtocolAdapter>) -> ::anyhow::Result<()> {
                 protocol_adapter.reset()?;
                 crate::cli::expect_message!(crate::messages::Message::Features, protocol_adapter.handle(crate::messages::Initialize::default().into()))?;
 
-                match self.command {
-                    $(Subcommand::$x(cmd) => crate::cli::CliDebugCommand::handle_debug(cmd, protocol_adapter, debug_protocol_adapter)),*
-                }
+                crate::cli::CliDebugCommand::handle_debug(self.command, protocol_adapter, debug_protocol_adapter)
             }
         }
     };
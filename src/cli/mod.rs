@@ -1,36 +1,63 @@
+pub mod age_plugin;
+pub mod batch;
 pub mod binance;
+pub mod bridge;
 pub mod cosmos;
 pub mod decode;
+pub mod encryption;
 pub mod eos;
 pub mod ethereum;
+pub mod gpg;
+mod http;
 pub mod list;
 mod macros;
 pub mod nano;
+pub mod output;
 pub mod parsers;
+pub mod passwords;
 pub mod ripple;
+pub mod serve;
+pub mod shell;
+pub mod ssh;
 pub mod system;
 pub mod tendermint;
 pub mod thorchain;
 pub mod types;
 pub mod utxo;
+pub mod watch;
 
+use age_plugin::*;
+use batch::*;
 use binance::*;
+use bridge::*;
 use cosmos::*;
 use decode::*;
+use encryption::*;
 use eos::*;
 use ethereum::*;
+use gpg::*;
 use list::*;
 pub(crate) use macros::*;
 use nano::*;
+use passwords::*;
 use ripple::*;
+use serve::*;
+use shell::*;
+use ssh::*;
 use system::*;
 use tendermint::*;
 use thorchain::*;
 use utxo::*;
+use watch::*;
 
-use crate::transport::ProtocolAdapter;
+use crate::{
+    cli::types::{Timeout, TransportType},
+    error::EXIT_CODES_HELP,
+    transport::{DeviceSelector, ProtocolAdapter, SecretSource},
+};
 use anyhow::Result;
 use clap::{ArgAction::SetTrue, Parser};
+use std::panic;
 
 pub trait CliCommand {
     fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()>;
@@ -54,28 +81,62 @@ impl<T: CliCommand> CliDebugCommand for T {
     }
 }
 
+/// Runs `f` on a thread with a bigger stack. clap needs too much memory to parse things for some
+/// reason -- something to do with the code generated by its derive macros I think -- and it's
+/// easier to just give it an extra couple of megs than to fix the problem.
+pub fn with_parser_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let result = std::thread::Builder::new()
+        .stack_size(4 * 1024 * 1024)
+        .spawn(f)
+        .unwrap()
+        .join();
+    match result {
+        Ok(x) => x,
+        Err(x) => panic::resume_unwind(x),
+    }
+}
+
 /// Command line tool for working with KeepKey devices
 #[derive(Parser, Debug, Clone)]
-#[clap(version, about)]
+#[clap(version, about, after_long_help = EXIT_CODES_HELP)]
 pub struct Cli {
     /// show communication with device
     #[clap(short, long, default_value_t = false, action = SetTrue)]
     pub verbose: bool,
+    /// print the result as a JSON object
+    #[clap(short, long, default_value_t = false, action = SetTrue)]
+    pub json: bool,
+    /// which device to use if more than one is connected: a serial number, BUS:ADDRESS (as shown by list), label or device ID; prefix with serial:, usb:, label: or id: to match only that
+    #[clap(long, value_parser)]
+    pub device: Option<DeviceSelector>,
+    /// if no device is connected, wait for one, for up to TIMEOUT (like 30s or 5m) or forever, which is the default; right before the subcommand, give it explicitly, like --wait forever
+    #[clap(
+        long,
+        value_name = "TIMEOUT",
+        value_parser,
+        min_values = 0,
+        max_values = 1,
+        default_missing_value = "forever"
+    )]
+    pub wait: Option<Timeout>,
+    /// where to get the PIN from: prompt, env:VAR, fd:N, file:PATH, askpass[:PROGRAM] or pinentry[:PROGRAM] (the PIN is the positions of its digits in the matrix the device shows)
+    #[clap(long, value_parser, default_value = "prompt")]
+    pub pin_source: SecretSource,
+    /// where to get the BIP-39 passphrase from: prompt, env:VAR, fd:N, file:PATH, askpass[:PROGRAM] or pinentry[:PROGRAM]
+    #[clap(long, value_parser, default_value = "prompt")]
+    pub passphrase_source: SecretSource,
     /// transport used for talking with the device
-    /*#[clap(short, long, value_enum, default_value_t = TransportType::Usb)]
+    #[clap(short, long, value_enum, default_value_t = TransportType::Usb)]
     pub transport: TransportType,
-    /// path used by the transport (usually serial port)
-    #[clap(short, long, requires = "transport")]
+    /// path used by the transport (for bridge, its URL; defaults to http://127.0.0.1:1646/)
+    #[clap(short, long)]
     pub path: Option<String>,
-    /// DEBUG_LINK transport
+    /*/// DEBUG_LINK transport
     #[clap(long, value_enum, default_value_t = TransportType::Usb)]
     pub debuglink_transport: TransportType,
     /// path used by the DEBUG_LINK transport (usually serial port)
     #[clap(long, requires = "debuglink-transport")]
     pub debuglink_path: Option<String>,
-    /// print result as json object
-    #[clap(short, long, default_value_t = false)]
-    pub json: bool,
     /// enable low-level debugging
     #[clap(short, long, default_value_t = false)]
     pub debug: bool,
@@ -88,9 +149,15 @@ pub struct Cli {
 
 use_cli_subcommands! {
     List,
+    Watch,
+    Shell,
+    Batch,
+    Serve,
+    Bridge,
     Decode,
     Ping,
     GetFeatures,
+    VerifyDevice,
     ListCoins,
     ApplySettings,
     ChangePin,
@@ -102,6 +169,7 @@ use_cli_subcommands! {
     LoadDevice,
     ResetDevice,
     FirmwareUpdate,
+    FirmwareInfo,
     CipherKeyValue,
     GetPublicKey,
     GetAddress,
@@ -121,12 +189,22 @@ use_cli_subcommands! {
     ThorchainSignTx,
     EthereumSignMessage,
     EthereumVerifyMessage,
+    EthereumRpc,
     BinanceGetAddress,
     BinanceSignTx,
     DebugLinkGetState,
     DebugLinkFlashDump,
     DebugLinkFillConfig,
     SignIdentity,
+    SshAgent,
+    SshPublicKey,
+    SshKeygen,
+    GpgPublicKey,
+    GpgSign,
+    EncryptFile,
+    DecryptFile,
+    AgePluginKeepkey,
+    Passwords,
     RippleGetAddress,
     RippleSignTx,
     // SignTx,
@@ -135,3 +213,19 @@ use_cli_subcommands! {
     FlashWrite,
     SoftReset,
 }
+
+impl Subcommand {
+    /// Runs the commands that don't need a device, and gives back the ones that do.
+    pub fn handle_without_device(self) -> Result<Option<Self>> {
+        match self {
+            Self::List(x) => x.handle()?,
+            Self::Watch(x) => x.handle()?,
+            Self::Bridge(x) => x.handle()?,
+            Self::Decode(x) => x.handle()?,
+            Self::FirmwareInfo(x) => x.handle()?,
+            Self::SshKeygen(x) if x.is_passthrough() => x.run_ssh_keygen()?,
+            x => return Ok(Some(x)),
+        }
+        Ok(None)
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     cli::{
-        expect_field, expect_message,
+        expect_field, expect_message, output,
         parsers::{Bip32PathParser, HexParser32},
         types::Bip32Path,
         CliCommand,
@@ -75,14 +75,10 @@ impl CliCommand for NanoSignTx {
             )
         )?;
 
-        println!(
-            "Signature:\t{}",
-            hex::encode(expect_field!(resp.signature)?)
-        );
-        println!(
-            "Block Hash:\t{}",
-            hex::encode(expect_field!(resp.block_hash)?)
-        );
+        let signature = hex::encode(expect_field!(resp.signature)?);
+        output!("signature" => signature, "Signature:\t{}", signature);
+        let block_hash = hex::encode(expect_field!(resp.block_hash)?);
+        output!("blockHash" => block_hash, "Block Hash:\t{}", block_hash);
 
         Ok(())
     }
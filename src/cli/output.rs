@@ -0,0 +1,70 @@
+use crate::error::{self, DeviceError};
+use anyhow::{Error, Result};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::sync::{Mutex, RwLock};
+
+lazy_static! {
+    pub static ref JSON: RwLock<bool> = RwLock::new(false);
+    static ref RESULT: Mutex<Map<String, Value>> = Mutex::new(Map::new());
+}
+
+pub fn is_json() -> bool {
+    *JSON.read().unwrap()
+}
+
+/// Adds a field to the result object printed when the command finishes in `--json` mode.
+pub fn record(key: &str, value: &impl Serialize) -> Result<()> {
+    RESULT
+        .lock()
+        .unwrap()
+        .insert(key.to_owned(), serde_json::to_value(value)?);
+    Ok(())
+}
+
+fn print(value: Value) {
+    println!("{}", serde_json::to_string_pretty(&value).unwrap());
+}
+
+/// Takes the fields recorded so far, leaving the result object empty for the next command.
+pub fn take_result() -> Map<String, Value> {
+    std::mem::take(&mut *RESULT.lock().unwrap())
+}
+
+pub fn print_result() {
+    let result = take_result();
+    print(json!({
+        "success": true,
+        "result": result,
+    }));
+}
+
+/// Describes an error as the `error` field of a failed result.
+pub fn error_object(error: &Error) -> Value {
+    let mut out = json!({
+        "message": format!("{:#}", error),
+        "kind": error::find_device_error(error).map_or("other", DeviceError::kind),
+        "exitCode": error::exit_code(error),
+    });
+    if let Some(DeviceError::Failure {
+        code: Some(code), ..
+    }) = error::find_device_error(error)
+    {
+        out["failureType"] = format!("{:?}", code).into();
+    }
+    out
+}
+
+/// Prints the error a command failed with, along with anything it recorded before failing.
+pub fn print_error(error: &Error) {
+    let result = take_result();
+    let mut out = json!({
+        "success": false,
+        "error": error_object(error),
+    });
+    if !result.is_empty() {
+        out["result"] = result.into();
+    }
+    print(out);
+}
@@ -1,8 +1,15 @@
 use super::FromStringParser;
-use anyhow::{Error, Result};
+use anyhow::{bail, Error, Result};
 use kkcli_derive::TypedValueParser;
+use lazy_static::lazy_static;
 use serde::de::DeserializeOwned;
-use std::marker::PhantomData;
+use std::{marker::PhantomData, sync::RwLock};
+
+lazy_static! {
+    /// Whether values that aren't JSON are read as file paths; `serve` turns it off, since its
+    /// clients shouldn't get to have local files read.
+    pub static ref READ_FILES: RwLock<bool> = RwLock::new(true);
+}
 
 #[derive(Default, Debug, Clone, Copy, TypedValueParser)]
 pub struct SerdeJsonFileOrLiteralParser<T: Clone + Send + Sync + 'static + DeserializeOwned>(
@@ -23,6 +30,8 @@ impl<T: Clone + Send + Sync + 'static + DeserializeOwned> FromStringParser
     fn parse_str(&self, value: &str) -> Result<Self::Value> {
         let value = if value.starts_with('{') || value.starts_with('[') {
             value.to_owned()
+        } else if !*READ_FILES.read().unwrap() {
+            bail!("expected JSON starting with {{ or [ (files can't be read here)");
         } else {
             String::from_utf8(std::fs::read(value)?)?
         };
@@ -0,0 +1,55 @@
+use super::{Entry, OpenStore};
+use crate::transport::{ProtocolAdapter, SecretSource};
+use anyhow::{bail, Result};
+use clap::Args;
+use std::path::PathBuf;
+
+/// Add a password to the store
+#[derive(Debug, Clone, Args)]
+pub struct PasswordsAdd {
+    /// what the password is for, like a site or service
+    title: String,
+    /// username that goes with the password
+    #[clap(short, long, default_value = "")]
+    username: String,
+    /// where to get the password from: prompt, env:VAR, fd:N, file:PATH, askpass[:PROGRAM] or pinentry[:PROGRAM]
+    #[clap(long, value_parser, default_value = "prompt")]
+    password_source: SecretSource,
+}
+
+impl PasswordsAdd {
+    pub(super) fn handle(
+        self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        path: PathBuf,
+    ) -> Result<()> {
+        let mut store = OpenStore::open(protocol_adapter, path)?;
+        if store.find(&self.title, Some(&self.username)).is_ok() {
+            bail!(
+                "there's already an entry for {}; remove it first to replace it",
+                Entry::describe(&self.title, &self.username)
+            );
+        }
+
+        let description = format!("Enter the password for {}.", self.title);
+        let password = match self.password_source.read(&description, "Password: ")? {
+            Some(x) => x,
+            None => {
+                eprint!("Password: ");
+                let password = passterm::read_password()?;
+                eprint!("Password again: ");
+                if passterm::read_password()? != password {
+                    bail!("the passwords didn't match");
+                }
+                password
+            }
+        };
+        if password.is_empty() {
+            bail!("the password is empty");
+        }
+
+        let entry = Entry::new(protocol_adapter, self.title, self.username, &password)?;
+        store.store.entries.push(entry);
+        store.save()
+    }
+}
@@ -0,0 +1,31 @@
+use super::OpenStore;
+use crate::{cli::output, transport::ProtocolAdapter};
+use anyhow::Result;
+use clap::Args;
+use std::path::PathBuf;
+
+/// Print a password from the store, once the device has been asked to unlock it
+#[derive(Debug, Clone, Args)]
+pub struct PasswordsGet {
+    /// what the password is for
+    title: String,
+    /// username that goes with the password, if there's more than one entry for the title
+    #[clap(short, long)]
+    username: Option<String>,
+}
+
+impl PasswordsGet {
+    pub(super) fn handle(
+        self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        path: PathBuf,
+    ) -> Result<()> {
+        let store = OpenStore::open(protocol_adapter, path)?;
+        let index = store.find(&self.title, self.username.as_deref())?;
+
+        let password = store.store.entries[index].password(protocol_adapter)?;
+        output!("password" => password, "{}", password);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use super::OpenStore;
+use crate::transport::ProtocolAdapter;
+use anyhow::{bail, Result};
+use clap::{ArgAction::SetTrue, Args};
+use std::path::PathBuf;
+
+/// Make a new, empty password store
+#[derive(Debug, Clone, Args)]
+pub struct PasswordsInit {
+    /// replace the store if there already is one, losing what's in it
+    #[clap(short, long, action = SetTrue)]
+    force: bool,
+}
+
+impl PasswordsInit {
+    pub(super) fn handle(
+        self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        path: PathBuf,
+    ) -> Result<()> {
+        if path.exists() && !self.force {
+            bail!(
+                "there's already a password store at {} (use --force to replace it)",
+                path.display()
+            );
+        }
+        OpenStore::create(protocol_adapter, path.clone())?.save()?;
+        eprintln!("Made a password store at {}", path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use super::OpenStore;
+use crate::{cli::output, transport::ProtocolAdapter};
+use anyhow::Result;
+use clap::Args;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// List the titles and usernames in the store
+#[derive(Debug, Clone, Args)]
+pub struct PasswordsList;
+
+#[derive(Serialize)]
+struct ListedEntry<'a> {
+    title: &'a str,
+    username: &'a str,
+}
+
+impl PasswordsList {
+    pub(super) fn handle(
+        self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        path: PathBuf,
+    ) -> Result<()> {
+        let store = OpenStore::open(protocol_adapter, path)?;
+
+        let entries = store
+            .store
+            .entries
+            .iter()
+            .map(|x| ListedEntry {
+                title: &x.title,
+                username: &x.username,
+            })
+            .collect::<Vec<_>>();
+        if output::is_json() {
+            output::record("entries", &entries)?;
+        } else {
+            for x in entries {
+                println!("{}\t{}", x.title, x.username);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,540 @@
+mod add;
+mod get;
+mod init;
+mod list;
+mod remove;
+
+pub use add::*;
+pub use get::*;
+pub use init::*;
+pub use list::*;
+pub use remove::*;
+
+use crate::{
+    cli::{
+        encryption::{open, seal, KEY_LEN, NONCE_LEN},
+        expect_field, expect_message, CliCommand,
+    },
+    messages::{self, Message},
+    transport::ProtocolAdapter,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Subcommand};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8] = b"KKCLIPWD";
+const VERSION: u8 = 1;
+/// m/10016'/0, the path SLIP-0016 uses.
+const ADDRESS: [u32; 2] = [0x8000_0000 | 10016, 0];
+/// The store key is derived the way SLIP-0016 derives its master key, so it only depends on the
+/// seed and passphrase.
+const MASTER_KEY_NAME: &str = "Activate KeepKey Password Manager?";
+const MASTER_KEY_VALUE: &str = "2d650551248d792eabf628f451200d7f51cb63e46aadcbb1038aacb05e8c8aee2d650551248d792eabf628f451200d7f51cb63e46aadcbb1038aacb05e8c8aee";
+
+/// Keep passwords in a local store that only the device can unlock
+///
+/// Opening the store has the device derive its key, which it asks to confirm. Each password is
+/// also encrypted with a key of its own, which the device has to unwrap (and asks to confirm)
+/// before the password can be read.
+#[derive(Debug, Clone, Args)]
+pub struct Passwords {
+    /// password store; defaults to .kkcli-passwords in the home directory
+    #[clap(short, long, global = true)]
+    store: Option<PathBuf>,
+    #[clap(subcommand)]
+    command: PasswordsCommand,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum PasswordsCommand {
+    Init(PasswordsInit),
+    Add(PasswordsAdd),
+    Get(PasswordsGet),
+    List(PasswordsList),
+    Remove(PasswordsRemove),
+}
+
+impl CliCommand for Passwords {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let path = match self.store {
+            Some(x) => x,
+            None => std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|x| Path::new(&x).join(".kkcli-passwords"))
+                .ok_or_else(|| anyhow!("couldn't find the home directory; use --store"))?,
+        };
+
+        match self.command {
+            PasswordsCommand::Init(x) => x.handle(protocol_adapter, path),
+            PasswordsCommand::Add(x) => x.handle(protocol_adapter, path),
+            PasswordsCommand::Get(x) => x.handle(protocol_adapter, path),
+            PasswordsCommand::List(x) => x.handle(protocol_adapter, path),
+            PasswordsCommand::Remove(x) => x.handle(protocol_adapter, path),
+        }
+    }
+}
+
+fn cipher_key_value(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    key_name: String,
+    value: &[u8],
+    encrypt: bool,
+    ask_on_encrypt: bool,
+    ask_on_decrypt: bool,
+) -> Result<Vec<u8>> {
+    let resp = expect_message!(
+        Message::CipheredKeyValue,
+        protocol_adapter.with_standard_handler().handle(
+            messages::CipherKeyValue {
+                address_n: ADDRESS.to_vec(),
+                key: Some(key_name),
+                value: Some(value.to_vec()),
+                encrypt: Some(encrypt),
+                ask_on_encrypt: Some(ask_on_encrypt),
+                ask_on_decrypt: Some(ask_on_decrypt),
+                iv: None,
+            }
+            .into(),
+        )
+    )?;
+    match expect_field!(resp.value)? {
+        x if x.len() == value.len() => Ok(x.clone()),
+        _ => bail!("unexpected value from device"),
+    }
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    title: String,
+    username: String,
+    /// The entry's key, as the device wrapped it.
+    #[serde_as(as = "Base64")]
+    wrapped_key: Vec<u8>,
+    /// A nonce, then the password sealed with the entry's key.
+    #[serde_as(as = "Base64")]
+    password: Vec<u8>,
+}
+
+impl Entry {
+    /// The title, with the username if there is one.
+    fn describe(title: &str, username: &str) -> String {
+        match username {
+            "" => title.to_owned(),
+            x => format!("{} for user {}", title, x),
+        }
+    }
+
+    /// What the device shows when asked to unwrap the entry's key, as in SLIP-0016.
+    fn key_name(title: &str, username: &str) -> String {
+        format!("Unlock {}?", Self::describe(title, username))
+    }
+
+    /// Encrypts `password` with a new key, which the device wraps without asking.
+    fn new(
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        title: String,
+        username: String,
+        password: &str,
+    ) -> Result<Self> {
+        let mut rng = rand::thread_rng();
+        let mut key = [0; KEY_LEN];
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill(&mut key);
+        rng.fill(&mut nonce);
+        let wrapped_key = cipher_key_value(
+            protocol_adapter,
+            Self::key_name(&title, &username),
+            &key,
+            true,
+            false,
+            true,
+        )?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(seal(&key, &nonce, &[], password.as_bytes()));
+        Ok(Self {
+            title,
+            username,
+            wrapped_key,
+            password: sealed,
+        })
+    }
+
+    /// Has the device unwrap the entry's key, which it asks to confirm, and decrypts the password.
+    fn password(&self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<String> {
+        let key = cipher_key_value(
+            protocol_adapter,
+            Self::key_name(&self.title, &self.username),
+            &self.wrapped_key,
+            false,
+            false,
+            true,
+        )?;
+        let key = key[..]
+            .try_into()
+            .map_err(|_| anyhow!("unexpected value from device"))?;
+        if self.password.len() < NONCE_LEN {
+            bail!("entry is damaged");
+        }
+        let (nonce, sealed) = self.password.split_at(NONCE_LEN);
+        let password = open(&key, nonce.try_into().unwrap(), &[], sealed)
+            .context("couldn't decrypt the password")?;
+        Ok(String::from_utf8(password)?)
+    }
+}
+
+/// The decrypted contents of a store file.
+#[derive(Default, Serialize, Deserialize)]
+struct Store {
+    entries: Vec<Entry>,
+}
+
+/// An open store, with the key it's sealed with.
+struct OpenStore {
+    path: PathBuf,
+    key: [u8; KEY_LEN],
+    store: Store,
+}
+
+impl OpenStore {
+    /// Has the device derive the store key, which it asks to confirm.
+    fn master_key(protocol_adapter: &mut dyn ProtocolAdapter) -> Result<[u8; KEY_LEN]> {
+        let value = cipher_key_value(
+            protocol_adapter,
+            MASTER_KEY_NAME.to_owned(),
+            &hex::decode(MASTER_KEY_VALUE).unwrap(),
+            true,
+            true,
+            true,
+        )?;
+        // SLIP-0016 uses the first half to name its file, and the second to encrypt it.
+        value[KEY_LEN..]
+            .try_into()
+            .map_err(|_| anyhow!("unexpected value from device"))
+    }
+
+    fn create(protocol_adapter: &mut dyn ProtocolAdapter, path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            key: Self::master_key(protocol_adapter)?,
+            path,
+            store: Store::default(),
+        })
+    }
+
+    fn open(protocol_adapter: &mut dyn ProtocolAdapter, path: PathBuf) -> Result<Self> {
+        let data = match fs::read(&path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == ErrorKind::NotFound => bail!(
+                "there's no password store at {}; make one with passwords init",
+                path.display()
+            ),
+            Err(e) => return Err(e).with_context(|| format!("couldn't read {}", path.display())),
+        };
+        let header_len = MAGIC.len() + 1;
+        if data.len() < header_len + NONCE_LEN || &data[..MAGIC.len()] != MAGIC {
+            bail!("{} isn't a password store", path.display());
+        }
+        if data[MAGIC.len()] != VERSION {
+            bail!(
+                "{} is version {} of the store format (this supports {})",
+                path.display(),
+                data[MAGIC.len()],
+                VERSION
+            );
+        }
+        let (header, rest) = data.split_at(header_len);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+
+        let key = Self::master_key(protocol_adapter)?;
+        let json = open(&key, nonce.try_into().unwrap(), header, sealed).context(
+            "couldn't decrypt the store; the device may not have the seed (and passphrase) it was made with",
+        )?;
+        Ok(Self {
+            path,
+            key,
+            store: serde_json::from_slice(&json)?,
+        })
+    }
+
+    /// Seals the store with a new nonce and replaces the file with it.
+    fn save(&self) -> Result<()> {
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill(&mut nonce);
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+        let sealed = seal(&self.key, &nonce, &data, &serde_json::to_vec(&self.store)?);
+        data.extend_from_slice(&nonce);
+        data.extend(sealed);
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        // A leftover from an earlier attempt would keep its permissions, so it's started afresh.
+        match fs::remove_file(&temp) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("couldn't remove {:?}", temp))
+            }
+            _ => {}
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(&temp)
+            .with_context(|| format!("couldn't create {:?}", temp))?;
+        if let Err(e) = file.write_all(&data).and_then(|_| file.sync_all()) {
+            drop(file);
+            let _ = fs::remove_file(&temp);
+            return Err(e).with_context(|| format!("couldn't write {:?}", temp));
+        }
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("couldn't replace {}", self.path.display()))
+    }
+
+    /// Finds the entry for `title`, which needs `username` too if there's more than one.
+    fn find(&self, title: &str, username: Option<&str>) -> Result<usize> {
+        let matches = self
+            .store
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| {
+                x.title == title && (username.is_none() || username == Some(&x.username))
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        match matches[..] {
+            [x] => Ok(x),
+            [] => bail!("there's no entry for {}", title),
+            _ => bail!(
+                "there's more than one entry for {}; pick one with --username",
+                title
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Transport;
+    use core::time::Duration;
+    use serde_json::json;
+    use std::collections::VecDeque;
+
+    /// What the stand-in device wraps values with.
+    const WRAP: u8 = 0x5a;
+
+    /// Answers CipherKeyValue by XORing the value with `WRAP`, keeping the requests.
+    #[derive(Default)]
+    struct StandIn {
+        requests: Vec<messages::CipherKeyValue>,
+        replies: VecDeque<Message>,
+    }
+
+    impl Transport for StandIn {
+        type Error = std::io::Error;
+
+        fn write(&mut self, msg: &[u8], _: Duration) -> Result<usize, Self::Error> {
+            let reply = match Message::decode(&mut &*msg).unwrap() {
+                Message::CipherKeyValue(x) => {
+                    let value = x
+                        .value
+                        .as_ref()
+                        .map(|x| x.iter().map(|x| x ^ WRAP).collect());
+                    self.requests.push(x);
+                    messages::CipheredKeyValue { value }.into()
+                }
+                x => panic!("unexpected message {:?}", x),
+            };
+            self.replies.push_back(reply);
+            Ok(msg.len())
+        }
+
+        fn read(&mut self, buf: &mut Vec<u8>, _: Duration) -> Result<(), Self::Error> {
+            self.replies.pop_front().unwrap().encode(buf).unwrap();
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// A directory of its own for each test, removed first in case an earlier run left it.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kkcli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry(title: &str, username: &str) -> Entry {
+        Entry {
+            title: title.to_owned(),
+            username: username.to_owned(),
+            wrapped_key: Vec::new(),
+            password: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn saves_and_opens() {
+        let dir = temp_dir("passwords-save");
+        let path = dir.join("store");
+        let mut protocol_adapter = StandIn::default();
+        let mut store = OpenStore::create(&mut protocol_adapter, path.clone()).unwrap();
+        let entry = Entry::new(
+            &mut protocol_adapter,
+            "example.com".to_owned(),
+            "alice".to_owned(),
+            "hunter2",
+        )
+        .unwrap();
+        store.store.entries.push(entry);
+        store.save().unwrap();
+        assert!(!dir.join("store.tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let opened = OpenStore::open(&mut protocol_adapter, path.clone()).unwrap();
+        assert_eq!(opened.key, store.key);
+        assert_eq!(opened.store.entries.len(), 1);
+        let entry = &opened.store.entries[0];
+        assert_eq!(entry.title, "example.com");
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.password(&mut protocol_adapter).unwrap(), "hunter2");
+
+        // Only the master key asks on encrypt; entry keys are wrapped without asking.
+        let requests = &protocol_adapter.requests;
+        assert_eq!(requests[0].key.as_deref(), Some(MASTER_KEY_NAME));
+        assert_eq!(requests[0].ask_on_encrypt, Some(true));
+        assert_eq!(
+            requests[1].key.as_deref(),
+            Some("Unlock example.com for user alice?")
+        );
+        assert_eq!(requests[1].ask_on_encrypt, Some(false));
+        assert_eq!(requests.last().unwrap().encrypt, Some(false));
+        assert_eq!(requests.last().unwrap().ask_on_decrypt, Some(true));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_changed_stores() {
+        let dir = temp_dir("passwords-changed");
+        let path = dir.join("store");
+        let mut protocol_adapter = StandIn::default();
+        OpenStore::create(&mut protocol_adapter, path.clone())
+            .unwrap()
+            .save()
+            .unwrap();
+        let data = fs::read(&path).unwrap();
+        let open_changed = |index: usize, protocol_adapter: &mut StandIn| {
+            let mut changed = data.clone();
+            changed[index] ^= 1;
+            fs::write(&path, changed).unwrap();
+            OpenStore::open(protocol_adapter, path.clone())
+                .err()
+                .unwrap()
+                .to_string()
+        };
+
+        assert!(open_changed(0, &mut protocol_adapter).ends_with("isn't a password store"));
+        assert!(open_changed(MAGIC.len(), &mut protocol_adapter)
+            .contains("is version 0 of the store format (this supports 1)"));
+        assert!(open_changed(MAGIC.len() + 1, &mut protocol_adapter)
+            .starts_with("couldn't decrypt the store"));
+        assert!(open_changed(data.len() - 1, &mut protocol_adapter)
+            .starts_with("couldn't decrypt the store"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn round_trips_entries() {
+        let mut protocol_adapter = StandIn::default();
+        let entry = Entry::new(
+            &mut protocol_adapter,
+            "example.com".to_owned(),
+            String::new(),
+            "correct horse battery staple",
+        )
+        .unwrap();
+        assert_eq!(entry.wrapped_key.len(), KEY_LEN);
+        assert_eq!(
+            entry.password(&mut protocol_adapter).unwrap(),
+            "correct horse battery staple"
+        );
+        assert_eq!(
+            protocol_adapter.requests[1].key.as_deref(),
+            Some("Unlock example.com?")
+        );
+
+        let mut damaged = entry;
+        let last = damaged.password.len() - 1;
+        damaged.password[last] ^= 1;
+        assert!(damaged.password(&mut protocol_adapter).is_err());
+        damaged.password.truncate(NONCE_LEN - 1);
+        assert!(damaged.password(&mut protocol_adapter).is_err());
+    }
+
+    #[test]
+    fn finds_entries() {
+        let store = OpenStore {
+            path: PathBuf::new(),
+            key: [0; KEY_LEN],
+            store: Store {
+                entries: vec![
+                    entry("example.com", "alice"),
+                    entry("example.com", "bob"),
+                    entry("example.org", ""),
+                ],
+            },
+        };
+        assert_eq!(store.find("example.org", None).unwrap(), 2);
+        assert_eq!(store.find("example.org", Some("")).unwrap(), 2);
+        assert_eq!(store.find("example.com", Some("bob")).unwrap(), 1);
+        assert_eq!(
+            store.find("example.net", None).unwrap_err().to_string(),
+            "there's no entry for example.net"
+        );
+        assert!(store.find("example.com", Some("carol")).is_err());
+        assert_eq!(
+            store.find("example.com", None).unwrap_err().to_string(),
+            "there's more than one entry for example.com; pick one with --username"
+        );
+    }
+
+    #[test]
+    fn serializes_with_camel_case() {
+        let mut entry = entry("example.com", "alice");
+        entry.wrapped_key = vec![1, 2, 3];
+        entry.password = vec![4, 5, 6];
+        let store = Store {
+            entries: vec![entry],
+        };
+        assert_eq!(
+            serde_json::to_value(&store).unwrap(),
+            json!({
+                "entries": [{
+                    "title": "example.com",
+                    "username": "alice",
+                    "wrappedKey": "AQID",
+                    "password": "BAUG",
+                }],
+            })
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use super::OpenStore;
+use crate::transport::ProtocolAdapter;
+use anyhow::Result;
+use clap::Args;
+use std::path::PathBuf;
+
+/// Remove a password from the store
+#[derive(Debug, Clone, Args)]
+pub struct PasswordsRemove {
+    /// what the password is for
+    title: String,
+    /// username that goes with the password, if there's more than one entry for the title
+    #[clap(short, long)]
+    username: Option<String>,
+}
+
+impl PasswordsRemove {
+    pub(super) fn handle(
+        self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        path: PathBuf,
+    ) -> Result<()> {
+        let mut store = OpenStore::open(protocol_adapter, path)?;
+        let index = store.find(&self.title, self.username.as_deref())?;
+
+        store.store.entries.remove(index);
+        store.save()
+    }
+}
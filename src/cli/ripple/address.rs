@@ -0,0 +1,136 @@
+use anyhow::{anyhow, bail, Result};
+use bitcoin::util::base58;
+
+const RIPPLE_ALPHABET: &[u8; 58] = b"rpshnaf39wBUDNEGHJKLM4PQRST7VWXYZ2bcdeCg65jkm8oFqi1tuvAxyz";
+const BITCOIN_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+const ACCOUNT_ID_PREFIX: u8 = 0x00;
+const X_ADDRESS_MAINNET_PREFIX: [u8; 2] = [0x05, 0x44];
+const X_ADDRESS_TESTNET_PREFIX: [u8; 2] = [0x04, 0x93];
+
+/// Ripple's base58 alphabet is a permutation of Bitcoin's, so we can reuse Bitcoin's base58check
+/// implementation by mapping each character to the one at the same position in the other alphabet.
+fn translate(x: &str, from: &[u8; 58], to: &[u8; 58]) -> Option<String> {
+    x.bytes()
+        .map(|c| from.iter().position(|y| *y == c).map(|i| to[i] as char))
+        .collect()
+}
+
+fn decode_check(x: &str) -> Result<Vec<u8>> {
+    let x = translate(x, RIPPLE_ALPHABET, BITCOIN_ALPHABET)
+        .ok_or_else(|| anyhow!("invalid character in Ripple address ({})", x))?;
+    Ok(base58::from_check(&x)?)
+}
+
+fn encode_check(data: &[u8]) -> String {
+    translate(
+        &base58::check_encode_slice(data),
+        BITCOIN_ALPHABET,
+        RIPPLE_ALPHABET,
+    )
+    .unwrap()
+}
+
+/// Encodes a 20-byte account ID as a classic "r..." address.
+pub fn encode_account_id(account_id: &[u8]) -> String {
+    encode_check(&[&[ACCOUNT_ID_PREFIX], account_id].concat())
+}
+
+/// Decodes an X-address (see <https://xrpaddress.info>) into its classic address and destination tag.
+pub fn decode_x_address(x: &str) -> Result<(String, Option<u32>)> {
+    let data = decode_check(x)?;
+    if data.len() != 31 {
+        bail!("invalid X-address length ({})", x);
+    }
+    if data[0..2] != X_ADDRESS_MAINNET_PREFIX && data[0..2] != X_ADDRESS_TESTNET_PREFIX {
+        bail!("invalid X-address prefix ({})", x);
+    }
+    let account_id = &data[2..22];
+    let tag_bytes = &data[23..31];
+    let tag = match data[22] {
+        0 if tag_bytes.iter().all(|x| *x == 0) => None,
+        1 if tag_bytes[4..].iter().all(|x| *x == 0) => {
+            Some(u32::from_le_bytes(tag_bytes[..4].try_into().unwrap()))
+        }
+        _ => bail!("unsupported X-address tag encoding ({})", x),
+    };
+    Ok((encode_account_id(account_id), tag))
+}
+
+/// Converts a classic address or X-address into the classic address and destination tag
+/// expected by the device, making sure a tag embedded in an X-address doesn't conflict with one
+/// provided separately.
+pub fn resolve_destination(
+    destination: &str,
+    destination_tag: Option<u32>,
+) -> Result<(String, Option<u32>)> {
+    if !(destination.starts_with('X') || destination.starts_with('T')) {
+        return Ok((destination.to_owned(), destination_tag));
+    }
+    let (classic, tag) = decode_x_address(destination)?;
+    match (tag, destination_tag) {
+        (Some(x), Some(y)) if x != y => bail!(
+            "destination tag ({}) conflicts with the one encoded in X-address ({})",
+            y,
+            x
+        ),
+        (None, Some(_)) => bail!("X-address destinations must encode their destination tag"),
+        _ => Ok((classic, tag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // From ripple-address-codec's test cases.
+    const ADDRESS: &str = "r9cZA1mLK5R5Am25ArfXFmqgNwjZgnfk59";
+
+    #[test]
+    fn decodes_x_addresses() {
+        for (x, tag) in [
+            ("X7AcgcsBL6XDcUb289X4mJ8djcdyKaB5hJDWMArnXr61cqZ", None),
+            ("T719a5UwUCnEs54UsxG9CJYYDhwmFCqkr7wxCcNcfZ6p5GZ", None),
+            ("X7AcgcsBL6XDcUb289X4mJ8djcdyKaGZMhc9YTE92ehJ2Fu", Some(1)),
+            (
+                "X7AcgcsBL6XDcUb289X4mJ8djcdyKaLFuhLRuNXPrDeJd9A",
+                Some(11747),
+            ),
+            (
+                "T719a5UwUCnEs54UsxG9CJYYDhwmFCziiNHtUukubF2Mg6t",
+                Some(11747),
+            ),
+        ] {
+            assert_eq!(decode_x_address(x).unwrap(), (ADDRESS.to_owned(), tag));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_x_addresses() {
+        // Last character changed, so the checksum doesn't match.
+        assert!(decode_x_address("X7AcgcsBL6XDcUb289X4mJ8djcdyKaB5hJDWMArnXr61cqY").is_err());
+        assert!(decode_x_address(ADDRESS).is_err());
+    }
+
+    #[test]
+    fn resolves_destinations() {
+        let x = "X7AcgcsBL6XDcUb289X4mJ8djcdyKaGZMhc9YTE92ehJ2Fu";
+        assert_eq!(
+            resolve_destination(x, None).unwrap(),
+            (ADDRESS.to_owned(), Some(1))
+        );
+        assert_eq!(
+            resolve_destination(x, Some(1)).unwrap(),
+            (ADDRESS.to_owned(), Some(1))
+        );
+        assert!(resolve_destination(x, Some(2)).is_err());
+        assert!(
+            resolve_destination("X7AcgcsBL6XDcUb289X4mJ8djcdyKaB5hJDWMArnXr61cqZ", Some(1))
+                .is_err()
+        );
+        assert_eq!(
+            resolve_destination(ADDRESS, Some(5)).unwrap(),
+            (ADDRESS.to_owned(), Some(5))
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use super::address::encode_account_id;
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha512};
+
+const TYPE_UINT16: u8 = 1;
+const TYPE_UINT32: u8 = 2;
+const TYPE_UINT64: u8 = 3;
+const TYPE_HASH128: u8 = 4;
+const TYPE_HASH256: u8 = 5;
+const TYPE_AMOUNT: u8 = 6;
+const TYPE_BLOB: u8 = 7;
+const TYPE_ACCOUNT_ID: u8 = 8;
+const TYPE_UINT8: u8 = 16;
+const TYPE_HASH160: u8 = 17;
+
+/// Prefix mixed into the transaction hash so it can't collide with hashes of other ledger objects
+const TRANSACTION_ID_PREFIX: [u8; 4] = *b"TXN\0";
+
+fn field_name(type_code: u8, field_code: u8) -> String {
+    match (type_code, field_code) {
+        (TYPE_UINT16, 2) => "TransactionType",
+        (TYPE_UINT32, 2) => "Flags",
+        (TYPE_UINT32, 3) => "SourceTag",
+        (TYPE_UINT32, 4) => "Sequence",
+        (TYPE_UINT32, 14) => "DestinationTag",
+        (TYPE_UINT32, 27) => "LastLedgerSequence",
+        (TYPE_UINT32, 41) => "TicketSequence",
+        (TYPE_HASH256, 9) => "AccountTxnID",
+        (TYPE_HASH256, 17) => "InvoiceID",
+        (TYPE_AMOUNT, 1) => "Amount",
+        (TYPE_AMOUNT, 8) => "Fee",
+        (TYPE_AMOUNT, 9) => "SendMax",
+        (TYPE_AMOUNT, 10) => "DeliverMin",
+        (TYPE_BLOB, 3) => "SigningPubKey",
+        (TYPE_BLOB, 4) => "TxnSignature",
+        (TYPE_ACCOUNT_ID, 1) => "Account",
+        (TYPE_ACCOUNT_ID, 3) => "Destination",
+        _ => return format!("Unknown({}, {})", type_code, field_code),
+    }
+    .to_owned()
+}
+
+fn transaction_type_name(x: u16) -> Value {
+    match x {
+        0 => "Payment".into(),
+        x => x.into(),
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!("unexpected end of serialized transaction");
+        }
+        let (out, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(out)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn field_header(&mut self) -> Result<(u8, u8)> {
+        let x = self.byte()?;
+        let type_code = match x >> 4 {
+            0 => self.byte()?,
+            x => x,
+        };
+        let field_code = match x & 0x0f {
+            0 => self.byte()?,
+            x => x,
+        };
+        Ok((type_code, field_code))
+    }
+
+    fn variable_length(&mut self) -> Result<usize> {
+        let b1: usize = self.byte()?.into();
+        Ok(match b1 {
+            0..=192 => b1,
+            193..=240 => 193 + (b1 - 193) * 256 + usize::from(self.byte()?),
+            241..=254 => {
+                12481
+                    + (b1 - 241) * 65536
+                    + usize::from(self.byte()?) * 256
+                    + usize::from(self.byte()?)
+            }
+            _ => bail!("invalid variable-length prefix"),
+        })
+    }
+}
+
+fn format_decimal(mantissa: u64, exponent: i32) -> String {
+    let mut mantissa = mantissa.to_string();
+    let mut exponent = exponent;
+    while mantissa.len() > 1 && mantissa.ends_with('0') {
+        mantissa.pop();
+        exponent += 1;
+    }
+    if exponent >= 0 {
+        return mantissa + &"0".repeat(exponent.try_into().unwrap());
+    }
+    let point = mantissa.len() as i32 + exponent;
+    if point > 0 {
+        let (int, frac) = mantissa.split_at(point.try_into().unwrap());
+        format!("{}.{}", int, frac)
+    } else {
+        format!("0.{}{}", "0".repeat((-point).try_into().unwrap()), mantissa)
+    }
+}
+
+fn decode_currency(x: &[u8]) -> String {
+    let is_standard = x[..12].iter().all(|x| *x == 0)
+        && x[15..].iter().all(|x| *x == 0)
+        && x[12..15].iter().all(|x| x.is_ascii_alphanumeric());
+    if is_standard {
+        String::from_utf8_lossy(&x[12..15]).into_owned()
+    } else {
+        hex::encode_upper(x)
+    }
+}
+
+fn decode_amount(reader: &mut Reader) -> Result<Value> {
+    let value = u64::from_be_bytes(reader.take(8)?.try_into().unwrap());
+    let is_positive = value & (1 << 62) != 0;
+    if value & (1 << 63) == 0 {
+        let drops = value & ((1 << 62) - 1);
+        return Ok(if is_positive || drops == 0 {
+            drops.to_string()
+        } else {
+            format!("-{}", drops)
+        }
+        .into());
+    }
+
+    let mantissa = value & ((1 << 54) - 1);
+    let exponent = ((value >> 54) & 0xff) as i32 - 97;
+    let currency = decode_currency(reader.take(20)?);
+    let issuer = encode_account_id(reader.take(20)?);
+    let value = match mantissa {
+        0 => "0".to_owned(),
+        x if is_positive => format_decimal(x, exponent),
+        x => format!("-{}", format_decimal(x, exponent)),
+    };
+
+    let mut out = Map::new();
+    out.insert("currency".to_owned(), currency.into());
+    out.insert("issuer".to_owned(), issuer.into());
+    out.insert("value".to_owned(), value.into());
+    Ok(out.into())
+}
+
+/// Decodes a transaction in the XRP Ledger's canonical binary format into the usual JSON representation.
+pub fn decode(data: &[u8]) -> Result<Map<String, Value>> {
+    let mut reader = Reader(data);
+    let mut out = Map::new();
+    while !reader.0.is_empty() {
+        let (type_code, field_code) = reader.field_header()?;
+        let value: Value = match type_code {
+            TYPE_UINT8 => reader.byte()?.into(),
+            TYPE_UINT16 => {
+                let x = u16::from_be_bytes(reader.take(2)?.try_into().unwrap());
+                if field_code == 2 {
+                    transaction_type_name(x)
+                } else {
+                    x.into()
+                }
+            }
+            TYPE_UINT32 => u32::from_be_bytes(reader.take(4)?.try_into().unwrap()).into(),
+            TYPE_UINT64 => hex::encode_upper(reader.take(8)?).into(),
+            TYPE_HASH128 => hex::encode_upper(reader.take(16)?).into(),
+            TYPE_HASH160 => hex::encode_upper(reader.take(20)?).into(),
+            TYPE_HASH256 => hex::encode_upper(reader.take(32)?).into(),
+            TYPE_AMOUNT => decode_amount(&mut reader)?,
+            TYPE_BLOB => {
+                let len = reader.variable_length()?;
+                hex::encode_upper(reader.take(len)?).into()
+            }
+            TYPE_ACCOUNT_ID => {
+                let len = reader.variable_length()?;
+                if len != 20 {
+                    bail!("invalid account ID length ({})", len);
+                }
+                encode_account_id(reader.take(len)?).into()
+            }
+            x => bail!("unsupported field type ({}) in serialized transaction", x),
+        };
+        out.insert(field_name(type_code, field_code), value);
+    }
+    Ok(out)
+}
+
+/// Calculates the transaction ID used to look up a signed transaction on the ledger.
+pub fn transaction_hash(data: &[u8]) -> [u8; 32] {
+    let hash = Sha512::new()
+        .chain_update(TRANSACTION_ID_PREFIX)
+        .chain_update(data)
+        .finalize();
+    hash[..32].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Signed transactions from ripple-binary-codec's fixtures.
+    const PAYMENT: &str = "1200002200000000240000003E6140000002540BE40068400000000000000A7321034AADB09CFF4A4804073701EC53C3510CDC95917C2BB0150FB742D0C66E6CEE9E74473045022022EB32AECEF7C644C891C19F87966DF9C62B1F34BABA6BE774325E4BB8E2DD62022100A51437898C28C2B297112DF8131F2BB39EA5FE613487DDD611525F17962646398114550FC62003E785DC231A1058A05E56E3F09CF4E68314D4CC8AB5B21D86A82C3E9E8D0ECF2404B77FECBA";
+    const DELIVER_MIN_PAYMENT: &str = "1200002280020000240000689E201B010BF0E361D4950EA99C657EF800000000000000000000000055534400000000000A20B3C85F482532A9578DBB3950B85CA06594D1684000000000002AF8694000000000003A986AD40485B690F28E8000000000000000000000000055534400000000000A20B3C85F482532A9578DBB3950B85CA06594D173210254D771E2A30552D1F347F5B88EC87513843F8BC1A408E70A4175B2E3C325FD3C7446304402202A4965FCF0571B7308971956864B1949C2BD924B5A41B5E8DAF00C91C64F964502207FD3BEB7C165BD1F10E6E7C443742BD686F8E102A89B502A4A495F4C29EC5C488114EAAA52373B59DCFBFD3476049AA6408AA22EAA898314EAAA52373B59DCFBFD3476049AA6408AA22EAA8901123000000000000000000000000055534400000000000A20B3C85F482532A9578DBB3950B85CA06594D1FF01FDF050193BEDEAA9074764B961405D31E66AC0E9300000000000000000000000005553440000000000FDF050193BEDEAA9074764B961405D31E66AC0E901FDF050193BEDEAA9074764B961405D31E66AC0E9FF300000000000000000000000005553440000000000DD39C650A96EDA48334E70CC4A85B8B2E8502CD301DD39C650A96EDA48334E70CC4A85B8B2E8502CD3017C44F934D7A5FEEBD1530570CDB83D1D8EF1F37E00";
+
+    #[test]
+    fn decodes_payment() {
+        let tx = decode(&hex::decode(PAYMENT).unwrap()).unwrap();
+        assert_eq!(
+            Value::from(tx),
+            json!({
+                "Account": "r3kmLJN5D28dHuH8vZNUZpMC43pEHpaocV",
+                "Destination": "rLQBHVhFnaC5gLEkgr6HgBJJ3bgeZHg9cj",
+                "TransactionType": "Payment",
+                "TxnSignature": "3045022022EB32AECEF7C644C891C19F87966DF9C62B1F34BABA6BE774325E4BB8E2DD62022100A51437898C28C2B297112DF8131F2BB39EA5FE613487DDD611525F1796264639",
+                "SigningPubKey": "034AADB09CFF4A4804073701EC53C3510CDC95917C2BB0150FB742D0C66E6CEE9E",
+                "Amount": "10000000000",
+                "Fee": "10",
+                "Flags": 0,
+                "Sequence": 62,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_transaction() {
+        let data = hex::decode(PAYMENT).unwrap();
+        assert!(decode(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn decodes_issued_amounts() {
+        let mut data = hex::decode("61D4C38D7EA4C68000").unwrap();
+        data.extend(hex::decode("0000000000000000000000005553440000000000").unwrap());
+        data.extend([0x11; 20]);
+        let tx = decode(&data).unwrap();
+        assert_eq!(tx["Amount"]["currency"], "USD");
+        assert_eq!(tx["Amount"]["value"], "10");
+        assert_eq!(format_decimal(5927096147083, -12), "5.927096147083");
+        assert_eq!(format_decimal(12729190692, -12), "0.012729190692");
+        assert_eq!(format_decimal(15, 3), "15000");
+    }
+
+    #[test]
+    fn hashes_transaction() {
+        let data = hex::decode(DELIVER_MIN_PAYMENT).unwrap();
+        assert_eq!(
+            hex::encode_upper(transaction_hash(&data)),
+            "0FB10DF664F33840ABC68A8BBE78178359C55AC1AFC83DB468CE69C4A86E3EAC"
+        );
+    }
+}
@@ -1,4 +1,7 @@
+mod address;
+mod codec;
 mod get_address;
+mod schema;
 mod sign_tx;
 
 pub use get_address::*;
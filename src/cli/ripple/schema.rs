@@ -0,0 +1,30 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_with::DisplayFromStr;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+pub enum TransactionType {
+    Payment,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct Payment {
+    pub transaction_type: TransactionType,
+    /// sending account; if present, must match the address of the signing key
+    pub account: Option<String>,
+    /// classic address or X-address of the recipient
+    pub destination: String,
+    pub destination_tag: Option<u32>,
+    /// amount of XRP to send, in drops
+    #[serde(with = "serde_with::As::<DisplayFromStr>")]
+    #[schemars(with = "String", regex(pattern = r"^\d{1,20}$"))]
+    pub amount: u64,
+    /// fee for the transaction, in drops
+    #[serde(with = "serde_with::As::<DisplayFromStr>")]
+    #[schemars(with = "String", regex(pattern = r"^\d{1,20}$"))]
+    pub fee: u64,
+    pub flags: Option<u32>,
+    pub sequence: Option<u32>,
+    pub last_ledger_sequence: Option<u32>,
+}
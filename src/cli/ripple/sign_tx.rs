@@ -1,10 +1,20 @@
+use super::{address::resolve_destination, codec, schema::Payment};
 use crate::{
-    cli::{expect_field, expect_message, parsers::Bip32PathParser, types::Bip32Path, CliCommand},
+    cli::{
+        expect_field, expect_message, output,
+        parsers::{Bip32PathParser, SerdeJsonFileOrLiteralParser},
+        types::Bip32Path,
+        CliCommand,
+    },
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Args;
+use core::ops::RangeInclusive;
+use schemars::schema_for;
+
+const FEE_RANGE: RangeInclusive<u64> = 10..=1000000;
 
 /// Sign Ripple transaction
 #[derive(Debug, Clone, Args)]
@@ -12,9 +22,12 @@ pub struct RippleSignTx {
     /// BIP-32 path to source address (for compatibility with other wallets, must be m/44'/144'/index')
     #[clap(short = 'n', long, value_parser = Bip32PathParser, default_value = "m/44'/144'/0'")]
     address: Bip32Path,
+    /// JSON-encoded XRPL Payment transaction to sign, or the path to a file containing one
+    #[clap(long, value_parser = SerdeJsonFileOrLiteralParser::<Payment>::new(), conflicts_with_all(&["fee", "flags", "sequence", "last-ledger-sequence", "amount", "destination", "destination-tag"]), long_help(Some(&*Box::leak(serde_json::to_string_pretty(&schema_for!(Payment)).unwrap().into_boxed_str()))))]
+    tx: Option<Payment>,
     /// fee (in drops) for the transaction
-    #[clap(long, value_parser = clap::value_parser!(u64).range(10..=1000000))]
-    fee: u64,
+    #[clap(long, value_parser = clap::value_parser!(u64).range(FEE_RANGE), required_unless_present("tx"))]
+    fee: Option<u64>,
     /// transaction flags
     #[clap(long)]
     flags: Option<u32>,
@@ -27,7 +40,7 @@ pub struct RippleSignTx {
     /// amount of XRP to send
     #[clap(long)]
     amount: Option<u64>,
-    /// destination account address
+    /// destination account address (classic address or X-address)
     #[clap(long)]
     destination: Option<String>,
     /// destination tag to identify payments
@@ -37,33 +50,90 @@ pub struct RippleSignTx {
 
 impl CliCommand for RippleSignTx {
     fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
-        let resp = expect_message!(
-            Message::RippleSignedTx,
-            protocol_adapter.with_standard_handler().handle(
+        let address_n: Vec<u32> = self.address.into();
+
+        let req = match self.tx {
+            Some(tx) => {
+                if !FEE_RANGE.contains(&tx.fee) {
+                    bail!(
+                        "fee ({}) must be between {} and {} drops",
+                        tx.fee,
+                        FEE_RANGE.start(),
+                        FEE_RANGE.end()
+                    );
+                }
+                if let Some(account) = tx.account {
+                    let resp = expect_message!(
+                        Message::RippleAddress,
+                        protocol_adapter.with_standard_handler().handle(
+                            messages::RippleGetAddress {
+                                address_n: address_n.clone(),
+                                show_display: None,
+                            }
+                            .into(),
+                        )
+                    )?;
+                    let signing_account = expect_field!(resp.address)?;
+                    if *signing_account != account {
+                        bail!(
+                            "transaction account ({}) doesn't match signing key's address ({})",
+                            account,
+                            signing_account
+                        );
+                    }
+                }
+                let (destination, destination_tag) =
+                    resolve_destination(&tx.destination, tx.destination_tag)?;
                 messages::RippleSignTx {
-                    address_n: self.address.into(),
-                    fee: Some(self.fee),
+                    address_n,
+                    fee: Some(tx.fee),
+                    flags: tx.flags,
+                    sequence: tx.sequence,
+                    last_ledger_sequence: tx.last_ledger_sequence,
+                    payment: Some(messages::RipplePayment {
+                        amount: Some(tx.amount),
+                        destination: Some(destination),
+                        destination_tag,
+                    }),
+                }
+            }
+            None => {
+                let (destination, destination_tag) = match self.destination {
+                    Some(x) => {
+                        let (x, tag) = resolve_destination(&x, self.destination_tag)?;
+                        (Some(x), tag)
+                    }
+                    None => (None, self.destination_tag),
+                };
+                messages::RippleSignTx {
+                    address_n,
+                    fee: self.fee,
                     flags: self.flags,
                     sequence: self.sequence,
                     last_ledger_sequence: self.last_ledger_sequence,
                     payment: Some(messages::RipplePayment {
                         amount: self.amount,
-                        destination: self.destination,
-                        destination_tag: self.destination_tag,
+                        destination,
+                        destination_tag,
                     }),
                 }
-                .into(),
-            )
+            }
+        };
+
+        let resp = expect_message!(
+            Message::RippleSignedTx,
+            protocol_adapter.with_standard_handler().handle(req.into())
         )?;
 
-        println!(
-            "Serialized Tx:\t{}",
-            hex::encode(expect_field!(resp.serialized_tx)?)
-        );
-        println!(
-            "Signature:\t{}",
-            hex::encode(expect_field!(resp.signature)?)
-        );
+        let serialized_tx = expect_field!(resp.serialized_tx)?;
+        let decoded_tx = codec::decode(serialized_tx)?;
+        let signature = hex::encode(expect_field!(resp.signature)?);
+        let hash = hex::encode_upper(codec::transaction_hash(serialized_tx));
+        let serialized_tx = hex::encode(serialized_tx);
+        output!("serializedTx" => serialized_tx, "Serialized Tx:\t{}", serialized_tx);
+        output!("signature" => signature, "Signature:\t{}", signature);
+        output!("hash" => hash, "Hash:\t\t{}", hash);
+        output!("decodedTx" => decoded_tx, "Decoded Tx:\t{}", serde_json::to_string_pretty(&decoded_tx)?);
 
         Ok(())
     }
@@ -0,0 +1,618 @@
+use super::{
+    output, parsers,
+    shell::{completions, parse_line, run_command},
+    with_parser_stack, CliDebugCommand,
+};
+use crate::{error, transport::ProtocolAdapter};
+use anyhow::{bail, Context, Result};
+use clap::{builder::ArgGroup, Args};
+use serde_json::{json, Map, Value};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener},
+    sync::mpsc::{channel, Sender},
+};
+
+// Error codes defined by JSON-RPC 2.0. Commands that fail get their exit code as the error code instead.
+pub(crate) const PARSE_ERROR: i64 = -32700;
+pub(crate) const INVALID_REQUEST: i64 = -32600;
+pub(crate) const METHOD_NOT_FOUND: i64 = -32601;
+pub(crate) const INVALID_PARAMS: i64 = -32602;
+
+/// Commands that only read from the device or sign with it. Anything that changes its settings,
+/// reveals secrets or touches local files isn't something any local program should get to do. The
+/// sign-tx commands that take a transaction file only take the JSON itself here (see `handle_debug`).
+const ALLOWED_METHODS: &[&str] = &[
+    "ping",
+    "get-features",
+    "list-coins",
+    "get-public-key",
+    "get-address",
+    "sign-message",
+    "verify-message",
+    "sign-identity",
+    "ssh-public-key",
+    "gpg-public-key",
+    "ethereum-get-address",
+    "ethereum-sign-tx",
+    "ethereum-sign-message",
+    "ethereum-verify-message",
+    "eos-get-public-key",
+    "eos-sign-tx",
+    "nano-get-address",
+    "nano-sign-tx",
+    "tendermint-get-address",
+    "tendermint-sign-tx",
+    "cosmos-get-address",
+    "cosmos-sign-tx",
+    "thorchain-get-address",
+    "thorchain-sign-tx",
+    "binance-get-address",
+    "binance-sign-tx",
+    "ripple-get-address",
+    "ripple-sign-tx",
+];
+
+/// Hold the device and let other programs use it through a JSON-RPC 2.0 API, one request at a time
+///
+/// Requests and responses are JSON objects, one per line. Each method is a kkcli subcommand that
+/// reads from the device or signs with it, such as get-features, get-address or ethereum-sign-tx. Its params are either an array of
+/// command-line arguments or an object of options: {"coinName": "Bitcoin", "address":
+/// "m/44'/0'/0'/0/0", "showDisplay": true}, with positional arguments under "args" and one-letter
+/// keys taken as short options. The result is what the command prints with --json. PINs and
+/// passphrases come from --pin-source and --passphrase-source, or are asked for on this terminal.
+/// Connections that start with an HTTP request are dropped, so web pages can't use the server, and
+/// transactions are given as JSON rather than as files to read.
+#[derive(Debug, Clone, Args)]
+#[clap(group(ArgGroup::new("endpoint").required(true)))]
+pub struct Serve {
+    /// listen on a Unix socket at this path, which only the current user can connect to
+    #[clap(long, group = "endpoint")]
+    socket: Option<String>,
+    /// listen on this localhost TCP address, like 127.0.0.1:1646
+    #[clap(long, group = "endpoint", value_parser)]
+    listen: Option<SocketAddr>,
+}
+
+pub(crate) struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    /// A command that failed, with its exit code as the error code.
+    pub fn from_error(e: &anyhow::Error) -> Self {
+        Self {
+            code: error::exit_code(e).into(),
+            message: format!("{:#}", e),
+            data: Some(json!({ "error": output::error_object(e) })),
+        }
+    }
+}
+
+/// A command waiting its turn on the device, and where to send its outcome.
+struct Job {
+    words: Vec<String>,
+    reply: Sender<Result<Map<String, Value>, RpcError>>,
+}
+
+fn kebab_case(x: &str) -> String {
+    let mut out = String::new();
+    for c in x.chars() {
+        if c.is_ascii_uppercase() {
+            out.push('-');
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn arg_string(x: &Value) -> Result<String, RpcError> {
+    match x {
+        Value::String(x) => Ok(x.clone()),
+        Value::Number(x) => Ok(x.to_string()),
+        Value::Bool(x) => Ok(x.to_string()),
+        x => Err(RpcError::new(
+            INVALID_PARAMS,
+            format!("expected a string, number or boolean, not {}", x),
+        )),
+    }
+}
+
+/// Turns the request's params into command-line arguments.
+fn params_to_args(params: Option<&Value>) -> Result<Vec<String>, RpcError> {
+    match params {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(x)) => x.iter().map(arg_string).collect(),
+        Some(Value::Object(x)) => {
+            let mut args = Vec::new();
+            let mut positional = Vec::new();
+            for (key, value) in x {
+                if key == "args" {
+                    match value {
+                        Value::Array(x) => {
+                            positional = x.iter().map(arg_string).collect::<Result<_, _>>()?
+                        }
+                        x => positional.push(arg_string(x)?),
+                    }
+                    continue;
+                }
+                let flag = match key.len() {
+                    1 => format!("-{}", key),
+                    _ => format!("--{}", kebab_case(key)),
+                };
+                match value {
+                    Value::Bool(true) => args.push(flag),
+                    Value::Bool(false) | Value::Null => (),
+                    Value::Array(x) => {
+                        for x in x {
+                            args.push(flag.clone());
+                            args.push(arg_string(x)?);
+                        }
+                    }
+                    x => {
+                        args.push(flag);
+                        args.push(arg_string(x)?);
+                    }
+                }
+            }
+            if !positional.is_empty() {
+                args.push("--".to_owned());
+                args.append(&mut positional);
+            }
+            Ok(args)
+        }
+        Some(_) => Err(RpcError::new(
+            INVALID_PARAMS,
+            "params must be an array or an object",
+        )),
+    }
+}
+
+fn call(request: &Value, methods: &[String], jobs: &Sender<Job>) -> Result<Value, RpcError> {
+    if request.get("jsonrpc") != Some(&json!("2.0")) {
+        return Err(RpcError::new(INVALID_REQUEST, "expected jsonrpc 2.0"));
+    }
+    let method = request
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new(INVALID_REQUEST, "expected a method name"))?;
+    if !methods.iter().any(|x| x == method) {
+        return Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("no such method: {}", method),
+        ));
+    }
+    let mut words = vec![method.to_owned()];
+    words.append(&mut params_to_args(request.get("params"))?);
+
+    let (reply, outcome) = channel();
+    jobs.send(Job { words, reply })
+        .map_err(|_| RpcError::new(INVALID_REQUEST, "server is shutting down"))?;
+    outcome
+        .recv()
+        .map_err(|_| RpcError::new(INVALID_REQUEST, "server is shutting down"))?
+        .map(Value::Object)
+}
+
+/// Handles one line, returning the response to send, if any.
+fn handle_request(line: &str, methods: &[String], jobs: &Sender<Job>) -> Option<Value> {
+    let (id, outcome) = match serde_json::from_str::<Value>(line) {
+        Ok(request) => match request.get("id") {
+            // Notifications get no response, but they're still run.
+            None => {
+                call(&request, methods, jobs).ok();
+                return None;
+            }
+            Some(id) => (id.clone(), call(&request, methods, jobs)),
+        },
+        Err(e) => (Value::Null, Err(RpcError::new(PARSE_ERROR, e))),
+    };
+    Some(rpc_response(id, outcome))
+}
+
+pub(crate) fn rpc_response(id: Value, outcome: Result<Value, RpcError>) -> Value {
+    match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => {
+            let mut error = json!({ "code": e.code, "message": e.message });
+            if let Some(data) = e.data {
+                error["data"] = data;
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": error })
+        }
+    }
+}
+
+/// Whether `line` is an HTTP request line or header. A web page can have a browser POST to a
+/// localhost port with requests in the body, so connections that start like that are dropped.
+fn looks_like_http(line: &str) -> bool {
+    let mut words = line.split(' ');
+    let request_line = match (words.next(), words.next(), words.next()) {
+        (Some(method), Some(_), Some(version)) => {
+            !method.is_empty()
+                && method.bytes().all(|x| x.is_ascii_uppercase())
+                && version.starts_with("HTTP/")
+        }
+        _ => false,
+    };
+    let header = line.split_once(':').is_some_and(|(name, _)| {
+        !name.is_empty() && name.bytes().all(|x| x.is_ascii_alphanumeric() || x == b'-')
+    });
+    request_line || header
+}
+
+fn handle_connection(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    methods: &[String],
+    jobs: &Sender<Job>,
+) -> Result<()> {
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if i == 0 && looks_like_http(&line) {
+            bail!("dropped a connection that sent HTTP, as a web page would");
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_request(&line, methods, jobs) {
+            writeln!(writer, "{}", serde_json::to_string(&response)?)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+type Connection = (Box<dyn BufRead + Send>, Box<dyn Write + Send>);
+
+/// Accepts connections on another thread, each with a thread of its own.
+fn spawn_acceptor(
+    mut accept: impl FnMut() -> std::io::Result<Connection> + Send + 'static,
+    methods: Vec<String>,
+    jobs: Sender<Job>,
+) {
+    std::thread::spawn(move || loop {
+        let (reader, writer) = match accept() {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("Error accepting connection: {}", e);
+                continue;
+            }
+        };
+        let methods = methods.clone();
+        let jobs = jobs.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = handle_connection(reader, writer, &methods, &jobs) {
+                eprintln!("Error: {:?}", e);
+            }
+        });
+    });
+}
+
+/// Listens on a Unix socket that only the current user can connect to.
+#[cfg(unix)]
+pub(crate) fn bind_unix(path: &str) -> Result<std::os::unix::net::UnixListener> {
+    use std::{
+        fs::{self, DirBuilder},
+        os::unix::{
+            fs::{DirBuilderExt, PermissionsExt},
+            net::{UnixListener, UnixStream},
+        },
+        path::Path,
+    };
+
+    if std::path::Path::new(path).exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("something is already listening on {}", path);
+        }
+        // Left behind by a server that didn't get to clean up.
+        fs::remove_file(path).with_context(|| format!("couldn't remove stale socket {}", path))?;
+    }
+    // Sockets are created with the umask's permissions, so make it somewhere nobody else can get
+    // to, and only move it into place once it's been locked down.
+    let private_dir = Path::new(path).with_file_name(format!(
+        ".{}.{}",
+        Path::new(path)
+            .file_name()
+            .context("socket path has no file name")?
+            .to_string_lossy(),
+        std::process::id()
+    ));
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&private_dir)
+        .with_context(|| format!("couldn't create {}", private_dir.display()))?;
+    let temp_path = private_dir.join("socket");
+    let listener = UnixListener::bind(&temp_path)
+        .and_then(|x| {
+            fs::set_permissions(&temp_path, fs::Permissions::from_mode(0o600))?;
+            fs::rename(&temp_path, path)?;
+            Ok(x)
+        })
+        .with_context(|| format!("couldn't listen on {}", path));
+    fs::remove_file(&temp_path).ok();
+    fs::remove_dir(&private_dir).ok();
+    let listener = listener?;
+    eprintln!("Listening on {}", path);
+    Ok(listener)
+}
+
+#[cfg(unix)]
+fn listen_unix(path: &str, methods: Vec<String>, jobs: Sender<Job>) -> Result<()> {
+    let listener = bind_unix(path)?;
+    spawn_acceptor(
+        move || {
+            let (x, _) = listener.accept()?;
+            Ok((Box::new(BufReader::new(x.try_clone()?)), Box::new(x)))
+        },
+        methods,
+        jobs,
+    );
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen_unix(_: &str, _: Vec<String>, _: Sender<Job>) -> Result<()> {
+    bail!("Unix sockets are only supported on Unix; use --listen instead")
+}
+
+fn listen_tcp(addr: SocketAddr, methods: Vec<String>, jobs: Sender<Job>) -> Result<()> {
+    if !addr.ip().is_loopback() {
+        bail!("refusing to listen on {}: anyone who can connect can use the device, so only localhost addresses are allowed", addr);
+    }
+    let listener =
+        TcpListener::bind(addr).with_context(|| format!("couldn't listen on {}", addr))?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+
+    spawn_acceptor(
+        move || {
+            let (x, _) = listener.accept()?;
+            Ok((Box::new(BufReader::new(x.try_clone()?)), Box::new(x)))
+        },
+        methods,
+        jobs,
+    );
+    Ok(())
+}
+
+/// The subcommands clients can call.
+fn allowed_methods() -> Vec<String> {
+    with_parser_stack(completions)
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|x| ALLOWED_METHODS.contains(&x.as_str()))
+        .collect()
+}
+
+/// Runs a command on the device, which only this thread touches.
+fn run_job(
+    words: Vec<String>,
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    debug_protocol_adapter: Option<&mut dyn ProtocolAdapter>,
+) -> Result<Map<String, Value>, RpcError> {
+    let command = match parse_line(words) {
+        Ok(Some(x)) => x,
+        Ok(None) => return Ok(Map::new()),
+        Err(e) => return Err(RpcError::new(INVALID_PARAMS, format!("{:#}", e))),
+    };
+    match run_command(command, protocol_adapter, debug_protocol_adapter) {
+        Ok(()) => Ok(output::take_result()),
+        Err(e) => {
+            let mut error = RpcError::from_error(&e);
+            let result = output::take_result();
+            if !result.is_empty() {
+                error.data.as_mut().unwrap()["result"] = result.into();
+            }
+            Err(error)
+        }
+    }
+}
+
+impl CliDebugCommand for Serve {
+    fn handle_debug(
+        self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        mut debug_protocol_adapter: Option<&mut dyn ProtocolAdapter>,
+    ) -> Result<()> {
+        // Responses are built from what commands record in --json mode.
+        *output::JSON.write().unwrap() = true;
+        *parsers::serde::READ_FILES.write().unwrap() = false;
+
+        let methods = allowed_methods();
+
+        // Connections queue their commands here; running them all on this thread is what keeps
+        // two of them from talking to the device at once.
+        let (jobs, queue) = channel();
+        match (self.socket, self.listen) {
+            (Some(path), _) => listen_unix(&path, methods, jobs)?,
+            (_, Some(addr)) => listen_tcp(addr, methods, jobs)?,
+            (None, None) => unreachable!(),
+        }
+
+        for Job { words, reply } in queue {
+            let outcome = run_job(
+                words,
+                protocol_adapter,
+                debug_protocol_adapter
+                    .as_deref_mut()
+                    .map(|x| -> &mut dyn ProtocolAdapter { x }),
+            );
+            // The client may have hung up while waiting, which is its business.
+            reply.send(outcome).ok();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn args(params: Value) -> Result<Vec<String>, i64> {
+        params_to_args(Some(&params)).map_err(|e| e.code)
+    }
+
+    /// Runs `line` through `handle_request`, answering any job it queues with `result`.
+    fn respond(line: &str, result: Value) -> (Option<Value>, Vec<Vec<String>>) {
+        let methods = vec!["get-address".to_owned()];
+        let (jobs, queue) = channel::<Job>();
+        let worker = std::thread::spawn(move || {
+            let mut words = Vec::new();
+            for job in queue {
+                words.push(job.words);
+                job.reply
+                    .send(Ok(result.as_object().unwrap().clone()))
+                    .unwrap();
+            }
+            words
+        });
+        let response = handle_request(line, &methods, &jobs);
+        drop(jobs);
+        (response, worker.join().unwrap())
+    }
+
+    fn error_code(response: &Value) -> &Value {
+        &response["error"]["code"]
+    }
+
+    #[test]
+    fn turns_params_into_args() {
+        assert_eq!(args(Value::Null), Ok(vec![]));
+        assert_eq!(
+            args(json!(["-n", 5, true])),
+            Ok(vec!["-n".into(), "5".into(), "true".into()])
+        );
+        assert_eq!(
+            args(json!({
+                "coinName": "Bitcoin",
+                "n": "m/44'/0'/0'/0/0",
+                "showDisplay": true,
+                "skip": false,
+                "signingKey": ["aa", "bb"],
+                "args": ["-x"],
+            })),
+            Ok([
+                "--coin-name",
+                "Bitcoin",
+                "-n",
+                "m/44'/0'/0'/0/0",
+                "--show-display",
+                "--signing-key",
+                "aa",
+                "--signing-key",
+                "bb",
+                "--",
+                "-x",
+            ]
+            .map(String::from)
+            .to_vec())
+        );
+        assert_eq!(args(json!("get-address")), Err(INVALID_PARAMS));
+        assert_eq!(args(json!([{ "x": 1 }])), Err(INVALID_PARAMS));
+        assert_eq!(args(json!({ "tx": { "x": 1 } })), Err(INVALID_PARAMS));
+    }
+
+    #[test]
+    fn only_allows_listed_methods() {
+        let methods = allowed_methods();
+        // Every listed method has to be a real command, or it's probably misspelled.
+        assert_eq!(methods.len(), ALLOWED_METHODS.len());
+        for x in ["get-features", "get-address", "ethereum-sign-tx"] {
+            assert!(methods.iter().any(|y| y == x));
+        }
+        for x in [
+            "wipe-device",
+            "load-device",
+            "get-entropy",
+            "firmware-update",
+            "serve",
+        ] {
+            assert!(!methods.iter().any(|y| y == x));
+        }
+    }
+
+    #[test]
+    fn runs_allowed_method() {
+        let (response, words) = respond(
+            r#"{"jsonrpc": "2.0", "id": 7, "method": "get-address", "params": {"coinName": "Bitcoin"}}"#,
+            json!({ "address": "1abc" }),
+        );
+        assert_eq!(
+            response.unwrap(),
+            json!({ "jsonrpc": "2.0", "id": 7, "result": { "address": "1abc" } })
+        );
+        assert_eq!(words, [["get-address", "--coin-name", "Bitcoin"]]);
+    }
+
+    #[test]
+    fn replies_with_errors() {
+        let (response, words) = respond(r#"{"jsonrpc": "2.0", "id": 1,"#, json!({}));
+        let response = response.unwrap();
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(*error_code(&response), json!(PARSE_ERROR));
+        assert!(words.is_empty());
+
+        let (response, _) = respond(r#"{"id": 2, "method": "get-address"}"#, json!({}));
+        let response = response.unwrap();
+        assert_eq!(response["id"], json!(2));
+        assert_eq!(*error_code(&response), json!(INVALID_REQUEST));
+
+        let (response, _) = respond(r#"{"jsonrpc": "2.0", "id": 3}"#, json!({}));
+        assert_eq!(*error_code(&response.unwrap()), json!(INVALID_REQUEST));
+
+        let (response, words) = respond(
+            r#"{"jsonrpc": "2.0", "id": 4, "method": "wipe-device"}"#,
+            json!({}),
+        );
+        assert_eq!(*error_code(&response.unwrap()), json!(METHOD_NOT_FOUND));
+        assert!(words.is_empty());
+
+        let (response, _) = respond(
+            r#"{"jsonrpc": "2.0", "id": 5, "method": "get-address", "params": 1}"#,
+            json!({}),
+        );
+        assert_eq!(*error_code(&response.unwrap()), json!(INVALID_PARAMS));
+    }
+
+    #[test]
+    fn runs_notifications_without_replying() {
+        let (response, words) =
+            respond(r#"{"jsonrpc": "2.0", "method": "get-address"}"#, json!({}));
+        assert!(response.is_none());
+        assert_eq!(words, [["get-address"]]);
+    }
+
+    #[test]
+    fn drops_http_connections() {
+        let (jobs, _queue) = channel();
+        let methods = vec!["get-address".to_owned()];
+        for first in ["POST / HTTP/1.1", "Host: localhost:1646"] {
+            let body = format!(
+                "{}\r\n\r\n{{\"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"get-address\"}}\n",
+                first
+            );
+            let mut out = Vec::new();
+            assert!(handle_connection(Cursor::new(body), &mut out, &methods, &jobs).is_err());
+            assert!(out.is_empty());
+        }
+        assert!(!looks_like_http(r#"{"jsonrpc": "2.0", "id": 1}"#));
+
+        let mut out = Vec::new();
+        handle_connection(Cursor::new("{\n"), &mut out, &methods, &jobs).unwrap();
+        let response: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(*error_code(&response), json!(PARSE_ERROR));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::transport::pin_matrix::RawModeGuard;
+use anyhow::Result;
+use crossterm::{
+    cursor::MoveToColumn,
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    queue,
+    style::Print,
+    terminal::{Clear, ClearType},
+};
+use std::io::{stderr, Stderr, Write};
+
+/// A minimal readline: cursor movement, the usual Emacs-style control keys, history and tab completion.
+pub struct LineEditor<F> {
+    history: Vec<String>,
+    complete: F,
+}
+
+fn draw(out: &mut Stderr, prompt: &str, line: &[char], cursor: usize) -> Result<()> {
+    queue!(
+        out,
+        MoveToColumn(0),
+        Clear(ClearType::CurrentLine),
+        Print(prompt),
+        Print(line.iter().collect::<String>()),
+        MoveToColumn((prompt.chars().count() + cursor) as u16),
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+fn common_prefix(words: &[String]) -> String {
+    let mut prefix = words[0].clone();
+    for word in &words[1..] {
+        let len = prefix
+            .chars()
+            .zip(word.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        prefix.truncate(len);
+    }
+    prefix
+}
+
+impl<F: Fn(&str) -> Vec<String>> LineEditor<F> {
+    /// `complete` gets the line up to the cursor and returns the possible whole words for the word being typed.
+    pub fn new(complete: F) -> Self {
+        Self {
+            history: Vec::new(),
+            complete,
+        }
+    }
+
+    /// Reads a line, or returns `None` if the user pressed Ctrl-D on an empty line.
+    pub fn read_line(&mut self, prompt: &str) -> Result<Option<String>> {
+        let mut out = stderr();
+        let mut line = Vec::<char>::new();
+        let mut cursor = 0;
+        let mut history_index = self.history.len();
+        // What was typed before browsing the history, so going back down past the newest entry restores it.
+        let mut unsaved = Vec::new();
+
+        let _guard = RawModeGuard::new()?;
+        draw(&mut out, prompt, &line, cursor)?;
+        loop {
+            let (code, modifiers) = match crossterm::event::read()? {
+                Event::Key(KeyEvent {
+                    code, modifiers, ..
+                }) => (code, modifiers),
+                _ => continue,
+            };
+            let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+            match code {
+                KeyCode::Enter => {
+                    write!(out, "\r\n")?;
+                    let line = line.into_iter().collect::<String>();
+                    if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                        self.history.push(line.clone());
+                    }
+                    return Ok(Some(line));
+                }
+                KeyCode::Char('c') if ctrl => {
+                    write!(out, "^C\r\n")?;
+                    line.clear();
+                    cursor = 0;
+                    history_index = self.history.len();
+                }
+                KeyCode::Char('d') if ctrl && line.is_empty() => {
+                    write!(out, "\r\n")?;
+                    return Ok(None);
+                }
+                KeyCode::Char('d') if ctrl && cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                KeyCode::Delete if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                KeyCode::Char('a') if ctrl => cursor = 0,
+                KeyCode::Home => cursor = 0,
+                KeyCode::Char('e') if ctrl => cursor = line.len(),
+                KeyCode::End => cursor = line.len(),
+                KeyCode::Char('b') if ctrl => cursor = cursor.saturating_sub(1),
+                KeyCode::Left => cursor = cursor.saturating_sub(1),
+                KeyCode::Char('f') if ctrl => cursor = (cursor + 1).min(line.len()),
+                KeyCode::Right => cursor = (cursor + 1).min(line.len()),
+                KeyCode::Char('u') if ctrl => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                }
+                KeyCode::Char('k') if ctrl => line.truncate(cursor),
+                KeyCode::Char('w') if ctrl => {
+                    let mut start = cursor;
+                    while start > 0 && line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    while start > 0 && !line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    line.drain(start..cursor);
+                    cursor = start;
+                }
+                KeyCode::Char(c) if !ctrl => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                KeyCode::Backspace if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                KeyCode::Up if history_index > 0 => {
+                    if history_index == self.history.len() {
+                        unsaved = line.clone();
+                    }
+                    history_index -= 1;
+                    line = self.history[history_index].chars().collect();
+                    cursor = line.len();
+                }
+                KeyCode::Down if history_index < self.history.len() => {
+                    history_index += 1;
+                    line = match self.history.get(history_index) {
+                        Some(x) => x.chars().collect(),
+                        None => unsaved.clone(),
+                    };
+                    cursor = line.len();
+                }
+                KeyCode::Tab => {
+                    let before = line[..cursor].iter().collect::<String>();
+                    let typed = before
+                        .rsplit(char::is_whitespace)
+                        .next()
+                        .unwrap_or_default()
+                        .chars()
+                        .count();
+                    let candidates = (self.complete)(&before);
+                    let insert = match candidates.len() {
+                        0 => continue,
+                        1 => format!("{} ", candidates[0]),
+                        _ => common_prefix(&candidates),
+                    };
+                    let insert = insert.chars().skip(typed).collect::<Vec<_>>();
+                    if insert.is_empty() {
+                        write!(out, "\r\n{}\r\n", candidates.join("  "))?;
+                    }
+                    let len = insert.len();
+                    line.splice(cursor..cursor, insert);
+                    cursor += len;
+                }
+                _ => continue,
+            }
+            draw(&mut out, prompt, &line, cursor)?;
+        }
+    }
+}
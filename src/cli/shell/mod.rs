@@ -0,0 +1,221 @@
+mod line_editor;
+
+use super::{output, with_parser_stack, CliDebugCommand, Subcommand};
+use crate::transport::ProtocolAdapter;
+use anyhow::{bail, Result};
+use clap::{Args, CommandFactory, ErrorKind, Parser};
+use crossterm::tty::IsTty;
+use line_editor::LineEditor;
+use std::io::{stderr, stdin};
+
+const PROMPT: &str = "kkcli> ";
+
+/// Run commands one after another over a single connection, so the PIN and passphrase only need entering once
+#[derive(Debug, Clone, Args)]
+pub struct Shell;
+
+/// One line typed into the shell.
+#[derive(Parser, Debug)]
+#[clap(no_binary_name = true, disable_version_flag = true)]
+struct Line {
+    #[clap(subcommand)]
+    command: Subcommand,
+}
+
+/// Splits a line into words like a POSIX shell would, minus the expansions.
+pub(crate) fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '#' if word.is_none() => break,
+            '\'' => loop {
+                let word = word.get_or_insert_with(String::new);
+                match chars.next() {
+                    Some('\'') => break,
+                    Some(c) => word.push(c),
+                    None => bail!("unterminated quote"),
+                }
+            },
+            '"' => loop {
+                let word = word.get_or_insert_with(String::new);
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                        Some(c) => {
+                            word.push('\\');
+                            word.push(c);
+                        }
+                        None => bail!("unterminated quote"),
+                    },
+                    Some(c) => word.push(c),
+                    None => bail!("unterminated quote"),
+                }
+            },
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => bail!("nothing to escape at end of line"),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Subcommand names and the long options each one takes, for tab completion.
+pub(crate) fn completions() -> Vec<(String, Vec<String>)> {
+    Line::command()
+        .get_subcommands()
+        .map(|x| {
+            let options = x
+                .get_arguments()
+                .filter_map(|x| x.get_long())
+                .map(|x| format!("--{}", x))
+                .collect();
+            (x.get_name().to_owned(), options)
+        })
+        .collect()
+}
+
+fn complete(commands: &[(String, Vec<String>)], before: &str) -> Vec<String> {
+    let mut words = before.split_whitespace().collect::<Vec<_>>();
+    let typed = match before.chars().last() {
+        Some(x) if !x.is_whitespace() => words.pop().unwrap_or_default(),
+        _ => "",
+    };
+    let names = || commands.iter().map(|(name, _)| name.as_str());
+    let candidates: Vec<&str> = match words[..] {
+        [] => names().chain(["help", "exit"]).collect(),
+        ["help"] => names().collect(),
+        [command, ..] if typed.starts_with('-') => commands
+            .iter()
+            .find(|(name, _)| name == command)
+            .map(|(_, options)| options.iter().map(String::as_str).collect())
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    candidates
+        .into_iter()
+        .filter(|x| x.starts_with(typed))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Reports a failed command without leaving the shell.
+fn report(error: &anyhow::Error) {
+    if output::is_json() {
+        output::print_error(error);
+    } else {
+        eprintln!("Error: {:?}", error);
+    }
+}
+
+/// Parses one command, or returns `None` if it asked for help and that's been printed instead.
+pub(crate) fn parse_line(mut words: Vec<String>) -> Result<Option<Subcommand>> {
+    if words[0] == "help" {
+        words.remove(0);
+        words.push("--help".to_owned());
+    }
+    // clap's errors can't be sent back from the thread with the bigger stack, so help is printed
+    // there and anything else is turned into a message.
+    let line = with_parser_stack(move || {
+        Line::try_parse_from(words).map_err(|e| match e.kind() {
+            ErrorKind::DisplayHelp | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+                e.print().ok();
+                None
+            }
+            _ => Some(e.to_string()),
+        })
+    });
+    match line {
+        Ok(x) => Ok(Some(x.command)),
+        Err(None) => Ok(None),
+        Err(Some(e)) => bail!("{}", e.trim_start_matches("error: ").trim_end()),
+    }
+}
+
+/// Runs one command without the reset and `Initialize` that a fresh invocation does.
+pub(crate) fn run_command(
+    command: Subcommand,
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    debug_protocol_adapter: Option<&mut dyn ProtocolAdapter>,
+) -> Result<()> {
+    match command.handle_without_device()? {
+        None => Ok(()),
+        Some(Subcommand::Shell(_) | Subcommand::Batch(_) | Subcommand::Serve(_)) => {
+            bail!("shell, batch and serve can't be run from inside a shell, batch or server")
+        }
+        Some(x) => x.handle_debug(protocol_adapter, debug_protocol_adapter),
+    }
+}
+
+pub(crate) fn run_line(
+    words: Vec<String>,
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    debug_protocol_adapter: Option<&mut dyn ProtocolAdapter>,
+) -> Result<()> {
+    match parse_line(words)? {
+        Some(x) => run_command(x, protocol_adapter, debug_protocol_adapter),
+        None => Ok(()),
+    }
+}
+
+impl CliDebugCommand for Shell {
+    fn handle_debug(
+        self,
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        mut debug_protocol_adapter: Option<&mut dyn ProtocolAdapter>,
+    ) -> Result<()> {
+        let interactive = stdin().is_tty() && stderr().is_tty();
+        let commands = with_parser_stack(completions);
+        let mut editor = LineEditor::new(|before: &str| complete(&commands, before));
+        if interactive {
+            eprintln!("Type a command, help for a list of them, or exit to quit.");
+        }
+
+        loop {
+            let line = if interactive {
+                match editor.read_line(PROMPT)? {
+                    Some(x) => x,
+                    None => break,
+                }
+            } else {
+                // Not stdin().lines(): holding the lock would block PIN prompts that read from stdin.
+                let mut line = String::new();
+                if stdin().read_line(&mut line)? == 0 {
+                    break;
+                }
+                line
+            };
+
+            let words = match split_words(&line) {
+                Ok(x) => x,
+                Err(e) => {
+                    report(&e);
+                    continue;
+                }
+            };
+            match words.first().map(String::as_str) {
+                None => continue,
+                Some("exit" | "quit") => break,
+                Some(_) => (),
+            }
+            match run_line(
+                words,
+                protocol_adapter,
+                debug_protocol_adapter
+                    .as_deref_mut()
+                    .map(|x| -> &mut dyn ProtocolAdapter { x }),
+            ) {
+                Ok(()) if output::is_json() => output::print_result(),
+                Ok(()) => (),
+                Err(e) => report(&e),
+            }
+        }
+        Ok(())
+    }
+}
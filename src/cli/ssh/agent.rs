@@ -0,0 +1,174 @@
+use super::{put_string, put_u32, Reader, SshCurve, SshIdentity};
+use crate::{
+    cli::{output, CliCommand},
+    transport::ProtocolAdapter,
+};
+use anyhow::{bail, Result};
+use clap::Args;
+use std::sync::mpsc::Sender;
+
+// Message numbers from the SSH agent protocol (draft-miller-ssh-agent).
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+/// OpenSSH's own agent refuses anything bigger.
+const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+/// Act as an SSH agent whose keys are on the device, so ssh and git can use them directly
+///
+/// Each identity is an ssh://user@host URL, and its key is derived from the URL the way
+/// sign-identity does it. The agent prints the SSH_AUTH_SOCK setting to use, like ssh-agent does,
+/// and every signature has to be confirmed on the device.
+#[derive(Debug, Clone, Args)]
+pub struct SshAgent {
+    /// identity URL to offer a key for, like ssh://git@github.com (may be repeated)
+    #[clap(required = true)]
+    urls: Vec<String>,
+    /// identity index
+    #[clap(short, long)]
+    index: Option<u32>,
+    /// curve for the keys (may be repeated to offer a key on each)
+    #[clap(short, long, value_enum, default_value = "nist256p1")]
+    curve: Vec<SshCurve>,
+    /// listen on a Unix socket at this path, which only the current user can connect to
+    #[clap(short = 'a', long)]
+    socket: String,
+}
+
+/// A sign request waiting its turn on the device, and where to send the signature.
+struct Job {
+    key: usize,
+    data: Vec<u8>,
+    reply: Sender<Result<Vec<u8>>>,
+}
+
+fn failure() -> Vec<u8> {
+    vec![SSH_AGENT_FAILURE]
+}
+
+/// Works out the response to one request.
+fn respond(request: &[u8], keys: &[(Vec<u8>, String)], jobs: &Sender<Job>) -> Result<Vec<u8>> {
+    let mut request = Reader(request);
+    let mut out = Vec::new();
+    match request.u8()? {
+        SSH_AGENTC_REQUEST_IDENTITIES => {
+            out.push(SSH_AGENT_IDENTITIES_ANSWER);
+            put_u32(&mut out, keys.len().try_into()?);
+            for (blob, comment) in keys {
+                put_string(&mut out, blob);
+                put_string(&mut out, comment);
+            }
+        }
+        SSH_AGENTC_SIGN_REQUEST => {
+            let blob = request.string()?;
+            let data = request.string()?.to_vec();
+            let key = match keys.iter().position(|(x, _)| x == blob) {
+                Some(x) => x,
+                None => return Ok(failure()),
+            };
+
+            let (reply, signature) = std::sync::mpsc::channel();
+            jobs.send(Job { key, data, reply })?;
+            out.push(SSH_AGENT_SIGN_RESPONSE);
+            put_string(&mut out, signature.recv()??);
+        }
+        // Adding keys, locking and extensions like session-bind aren't supported, and clients
+        // cope with being told so.
+        _ => return Ok(failure()),
+    }
+    Ok(out)
+}
+
+#[cfg(unix)]
+fn spawn_listener(path: &str, keys: Vec<(Vec<u8>, String)>, jobs: Sender<Job>) -> Result<()> {
+    use std::{io::Read, io::Write, sync::Arc};
+
+    let listener = crate::cli::serve::bind_unix(path)?;
+    let keys = Arc::new(keys);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(x) => x,
+                Err(e) => {
+                    eprintln!("Error accepting connection: {}", e);
+                    continue;
+                }
+            };
+            let keys = Arc::clone(&keys);
+            let jobs = jobs.clone();
+            std::thread::spawn(move || -> Result<()> {
+                loop {
+                    let mut len = [0u8; 4];
+                    if stream.read_exact(&mut len).is_err() {
+                        return Ok(());
+                    }
+                    let len = u32::from_be_bytes(len).try_into()?;
+                    if len > MAX_MESSAGE_LEN {
+                        bail!("request too large");
+                    }
+                    let mut request = vec![0u8; len];
+                    stream.read_exact(&mut request)?;
+
+                    let response = respond(&request, &keys, &jobs).unwrap_or_else(|e| {
+                        eprintln!("Error: {:?}", e);
+                        failure()
+                    });
+                    let mut message = Vec::new();
+                    put_string(&mut message, response);
+                    stream.write_all(&message)?;
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn spawn_listener(_: &str, _: Vec<(Vec<u8>, String)>, _: Sender<Job>) -> Result<()> {
+    bail!("the SSH agent needs Unix sockets, which are only supported on Unix")
+}
+
+impl CliCommand for SshAgent {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let mut identities = Vec::new();
+        for url in &self.urls {
+            for curve in &self.curve {
+                identities.push(SshIdentity::fetch(
+                    protocol_adapter,
+                    url,
+                    self.index,
+                    *curve,
+                )?);
+            }
+        }
+        let keys = identities
+            .iter()
+            .map(|x| Ok((x.key_blob()?, x.url.clone())))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Requests queue up here; running them all on this thread is what keeps two of them from
+        // talking to the device at once.
+        let (jobs, queue) = std::sync::mpsc::channel();
+        spawn_listener(&self.socket, keys, jobs)?;
+        let socket = std::env::current_dir()?.join(&self.socket);
+        output!(
+            "socket" => socket,
+            "SSH_AUTH_SOCK={}; export SSH_AUTH_SOCK;",
+            socket.display()
+        );
+        // The agent runs until it's killed, so the result is printed now rather than at exit.
+        if output::is_json() {
+            output::print_result();
+        }
+
+        for Job { key, data, reply } in queue {
+            let signature = identities[key].sign(protocol_adapter, &data);
+            // The client may have hung up while waiting, which is its business.
+            reply.send(signature).ok();
+        }
+        Ok(())
+    }
+}
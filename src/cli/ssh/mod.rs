@@ -0,0 +1,245 @@
+mod agent;
+mod public_key;
+mod sshsig;
+
+pub use agent::*;
+pub use public_key::*;
+pub use sshsig::*;
+
+use crate::{
+    cli::system::{identity_from_url, identity_public_key, sign_with_identity},
+    messages,
+    transport::ProtocolAdapter,
+};
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use p256::{
+    elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint},
+    AffinePoint, EncodedPoint,
+};
+
+/// Curves the device can make SSH keys on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum SshCurve {
+    Nist256p1,
+    Ed25519,
+}
+
+impl SshCurve {
+    /// What the device calls it.
+    pub fn curve_name(self) -> &'static str {
+        match self {
+            Self::Nist256p1 => "nist256p1",
+            Self::Ed25519 => "ed25519",
+        }
+    }
+
+    /// What SSH calls keys on it.
+    pub fn key_type(self) -> &'static str {
+        match self {
+            Self::Nist256p1 => "ecdsa-sha2-nistp256",
+            Self::Ed25519 => "ssh-ed25519",
+        }
+    }
+}
+
+pub(crate) fn put_u32(out: &mut Vec<u8>, x: u32) {
+    out.extend_from_slice(&x.to_be_bytes());
+}
+
+pub(crate) fn put_string(out: &mut Vec<u8>, x: impl AsRef<[u8]>) {
+    let x = x.as_ref();
+    put_u32(out, x.len().try_into().unwrap());
+    out.extend_from_slice(x);
+}
+
+/// Writes a big-endian unsigned integer as an SSH mpint, which is signed.
+pub(crate) fn put_mpint(out: &mut Vec<u8>, x: &[u8]) {
+    let x = &x[x.iter().take_while(|x| **x == 0).count()..];
+    if matches!(x.first(), Some(x) if x & 0x80 != 0) {
+        put_u32(out, (x.len() + 1).try_into().unwrap());
+        out.push(0);
+        out.extend_from_slice(x);
+    } else {
+        put_string(out, x);
+    }
+}
+
+/// Reads SSH's wire encoding.
+pub(crate) struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!("message is truncated");
+        }
+        let (x, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(x)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn string(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()?;
+        self.bytes(len.try_into()?)
+    }
+}
+
+/// Turns a compressed P-256 point, which is what the device gives, into the uncompressed form,
+/// which is the only one OpenSSH accepts.
+pub(crate) fn decompress_p256(point: &[u8]) -> Result<Vec<u8>> {
+    if !matches!(point, [2 | 3, ..]) {
+        bail!("expected a compressed P-256 point");
+    }
+    let point = EncodedPoint::from_bytes(point)
+        .map_err(|_| anyhow!("expected a compressed P-256 point"))?;
+    let point = Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&point))
+        .ok_or_else(|| anyhow!("public key isn't on P-256"))?;
+    Ok(point.to_encoded_point(false).as_bytes().to_vec())
+}
+
+/// Base64 `data` in lines of `width` between BEGIN and END lines for `label`, which is how both
+/// OpenSSH and OpenPGP armor things. OpenPGP's header block and checksum go in `headers` and
+/// `trailer`.
+pub(crate) fn armor(
+    label: &str,
+    width: usize,
+    headers: &str,
+    data: &[u8],
+    trailer: &str,
+) -> String {
+    let mut out = format!("-----BEGIN {}-----\n{}", label, headers);
+    for line in base64::encode(data).as_bytes().chunks(width) {
+        out.push_str(core::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out.push_str(trailer);
+    out.push_str(&format!("-----END {}-----\n", label));
+    out
+}
+
+/// Ed25519 keys come from the device with a prefix byte to make them as long as the others.
+pub(crate) fn ed25519_key(public_key: &[u8]) -> Result<&[u8]> {
+    match public_key {
+        [0 | 1, x @ ..] if x.len() == 32 => Ok(x),
+        _ => Err(anyhow!("expected an Ed25519 public key")),
+    }
+}
+
+/// A key the device holds for an `ssh://` identity.
+pub(crate) struct SshIdentity {
+    pub url: String,
+    pub curve: SshCurve,
+    identity: messages::IdentityType,
+    public_key: Vec<u8>,
+}
+
+impl SshIdentity {
+    /// Fetches the public key for `url`, without anything needing confirming on the device.
+    pub fn fetch(
+        protocol_adapter: &mut dyn ProtocolAdapter,
+        url: &str,
+        index: Option<u32>,
+        curve: SshCurve,
+    ) -> Result<Self> {
+        let identity = identity_from_url(url, index)?;
+        if identity.proto.as_deref() != Some("ssh") {
+            bail!("{} isn't an ssh:// URL", url);
+        }
+
+        let public_key = identity_public_key(protocol_adapter, &identity, curve.curve_name())?;
+
+        Ok(Self {
+            url: url.to_owned(),
+            curve,
+            identity,
+            public_key,
+        })
+    }
+
+    /// The public key in SSH's wire format.
+    pub fn key_blob(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        put_string(&mut out, self.curve.key_type());
+        match self.curve {
+            SshCurve::Nist256p1 => {
+                put_string(&mut out, "nistp256");
+                put_string(&mut out, decompress_p256(&self.public_key)?);
+            }
+            SshCurve::Ed25519 => put_string(&mut out, ed25519_key(&self.public_key)?),
+        }
+        Ok(out)
+    }
+
+    /// The public key as a line for authorized_keys, with the URL as the comment.
+    pub fn authorized_key(&self) -> Result<String> {
+        Ok(format!(
+            "{} {} {}",
+            self.curve.key_type(),
+            base64::encode(self.key_blob()?),
+            self.url
+        ))
+    }
+
+    /// Has the device sign `data`, returning the signature in SSH's wire format.
+    pub fn sign(&self, protocol_adapter: &mut dyn ProtocolAdapter, data: &[u8]) -> Result<Vec<u8>> {
+        let signature = sign_with_identity(
+            protocol_adapter,
+            &self.identity,
+            self.curve.curve_name(),
+            &self.public_key,
+            data,
+        )?;
+
+        let mut out = Vec::new();
+        put_string(&mut out, self.curve.key_type());
+        match self.curve {
+            SshCurve::Nist256p1 => {
+                let mut rs = Vec::new();
+                put_mpint(&mut rs, &signature[..32]);
+                put_mpint(&mut rs, &signature[32..]);
+                put_string(&mut out, rs);
+            }
+            SshCurve::Ed25519 => put_string(&mut out, signature),
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GX: &str = "6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296";
+    const GY: &str = "4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5";
+    /// The field prime minus GY, the y coordinate of -G.
+    const NEG_GY: &str = "b01cbd1c01e58065711814b583f061e9d431cca994cea1313449bf97c840ae0a";
+
+    #[test]
+    fn decompresses_the_generator() {
+        let x = hex::decode(GX).unwrap();
+        let odd = decompress_p256(&[&[3][..], &x].concat()).unwrap();
+        assert_eq!(hex::encode(odd), format!("04{}{}", GX, GY));
+        let even = decompress_p256(&[&[2][..], &x].concat()).unwrap();
+        assert_eq!(hex::encode(even), format!("04{}{}", GX, NEG_GY));
+    }
+
+    #[test]
+    fn rejects_bad_points() {
+        let x = hex::decode(GX).unwrap();
+        assert!(decompress_p256(&[&[4][..], &x].concat()).is_err());
+        assert!(decompress_p256(&[&[3][..], &x[1..]].concat()).is_err());
+        // x = 1 gives x^3 - 3x + b with no square root mod p.
+        let mut not_on_curve = [0; 33];
+        not_on_curve[0] = 2;
+        not_on_curve[32] = 1;
+        assert!(decompress_p256(&not_on_curve).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+use super::{SshCurve, SshIdentity};
+use crate::{
+    cli::{output, CliCommand},
+    transport::ProtocolAdapter,
+};
+use anyhow::Result;
+use clap::Args;
+
+/// Get the SSH public key for an identity URL, as a line for authorized_keys
+#[derive(Debug, Clone, Args)]
+pub struct SshPublicKey {
+    /// identity URL, like ssh://git@github.com
+    url: String,
+    /// identity index
+    #[clap(short, long)]
+    index: Option<u32>,
+    /// curve for the key
+    #[clap(short, long, value_enum, default_value = "nist256p1")]
+    curve: SshCurve,
+}
+
+impl CliCommand for SshPublicKey {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let identity = SshIdentity::fetch(protocol_adapter, &self.url, self.index, self.curve)?;
+
+        let public_key = identity.authorized_key()?;
+        output!("publicKey" => public_key, "{}", public_key);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use super::{armor, put_string, put_u32, SshCurve, SshIdentity};
+use crate::{cli::CliCommand, transport::ProtocolAdapter};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{ArgAction::SetTrue, Args};
+use sha2::{Digest, Sha512};
+use std::{
+    fs,
+    io::{stdin, stdout, Read, Write},
+    process::Command,
+};
+
+const MAGIC_PREAMBLE: &[u8] = b"SSHSIG";
+const SIG_VERSION: u32 = 1;
+const HASH_ALGORITHM: &str = "sha512";
+
+/// Sign files with an SSH identity's key the way ssh-keygen -Y sign does, for git commit signing
+///
+/// This takes the arguments git gives its gpg.ssh.program, so set that to a script that runs
+/// `kkcli ssh-keygen "$@"`, set gpg.format to ssh, and set user.signingKey to the line
+/// ssh-public-key printed for the key (prefixed with key::) or a file holding it. The identity URL
+/// at the end of that line is how the key is found on the device; for a key made with an identity
+/// index, pass it as -O index=N (with git, in gpg.ssh.program's script). Anything other than -Y sign,
+/// like the checks git does when verifying signatures, is handed to the real ssh-keygen.
+#[derive(Debug, Clone, Args)]
+pub struct SshKeygen {
+    /// operation, as for ssh-keygen; only sign is done here
+    #[clap(short = 'Y', value_name = "OPERATION")]
+    operation: String,
+    /// namespace the signature is for, like git or file
+    #[clap(short = 'n', value_name = "NAMESPACE")]
+    namespace: Option<String>,
+    /// public key to sign with, as printed by ssh-public-key (or, for anything but sign, the allowed signers file)
+    #[clap(short = 'f', value_name = "FILE")]
+    key_file: Option<String>,
+    /// principal, passed through to ssh-keygen
+    #[clap(short = 'I', value_name = "PRINCIPAL")]
+    principal: Option<String>,
+    /// signature file, passed through to ssh-keygen
+    #[clap(short = 's', value_name = "FILE")]
+    signature_file: Option<String>,
+    /// option; when signing, index=N is the identity index, otherwise it's passed through to ssh-keygen
+    #[clap(short = 'O', value_name = "OPTION")]
+    options: Vec<String>,
+    /// the key is in an agent; ignored, since it's on the device
+    #[clap(short = 'U', action = SetTrue)]
+    agent: bool,
+    /// files to sign, each getting its signature written next to it with .sig added; without any, stdin is signed to stdout
+    files: Vec<String>,
+}
+
+impl SshKeygen {
+    /// Everything but signing is left to ssh-keygen, and doesn't need the device.
+    pub fn is_passthrough(&self) -> bool {
+        self.operation != "sign"
+    }
+
+    /// Runs ssh-keygen with the same arguments.
+    pub fn run_ssh_keygen(self) -> Result<()> {
+        let mut command = Command::new("ssh-keygen");
+        command.arg("-Y").arg(&self.operation);
+        for (flag, value) in [
+            ("-n", &self.namespace),
+            ("-f", &self.key_file),
+            ("-I", &self.principal),
+            ("-s", &self.signature_file),
+        ] {
+            if let Some(x) = value {
+                command.arg(flag).arg(x);
+            }
+        }
+        for x in &self.options {
+            command.arg("-O").arg(x);
+        }
+        if self.agent {
+            command.arg("-U");
+        }
+        let status = command
+            .args(&self.files)
+            .status()
+            .context("couldn't run ssh-keygen")?;
+        if !status.success() {
+            bail!("ssh-keygen failed ({})", status);
+        }
+        Ok(())
+    }
+
+    /// Takes the identity index from the -O options, which are otherwise only ssh-keygen's.
+    fn sign_index(&self) -> Result<Option<u32>> {
+        let mut index = None;
+        for option in &self.options {
+            match option.split_once('=') {
+                Some(("index", x)) => {
+                    index = Some(
+                        x.parse()
+                            .with_context(|| format!("invalid identity index in -O {}", option))?,
+                    )
+                }
+                Some(("hashalg", HASH_ALGORITHM)) => {}
+                _ => bail!("unsupported signing option -O {}", option),
+            }
+        }
+        Ok(index)
+    }
+}
+
+/// Reads a public key line, giving the curve, the key and the identity URL from its comment.
+fn read_key_file(path: &str) -> Result<(SshCurve, String, String)> {
+    let contents = fs::read_to_string(path).with_context(|| format!("couldn't read {}", path))?;
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|x| !x.is_empty() && !x.starts_with('#'))
+        .ok_or_else(|| anyhow!("no public key in {}", path))?;
+    let mut words = line.splitn(3, ' ');
+    let curve = match words.next() {
+        Some("ecdsa-sha2-nistp256") => SshCurve::Nist256p1,
+        Some("ssh-ed25519") => SshCurve::Ed25519,
+        _ => bail!(
+            "{} isn't an ecdsa-sha2-nistp256 or ssh-ed25519 public key",
+            path
+        ),
+    };
+    let key = words.next().unwrap_or_default().to_owned();
+    let url = match words.next().map(str::trim) {
+        Some(x) if x.starts_with("ssh://") => x.to_owned(),
+        _ => bail!(
+            "the key in {} doesn't end with its identity URL; use the line ssh-public-key prints",
+            path
+        ),
+    };
+    Ok((curve, key, url))
+}
+
+/// Makes an armored SSHSIG signature over `message`, as described in OpenSSH's PROTOCOL.sshsig.
+fn sign(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    identity: &SshIdentity,
+    namespace: &str,
+    message: &[u8],
+) -> Result<String> {
+    let hash = Sha512::digest(message);
+    let mut signed_data = MAGIC_PREAMBLE.to_vec();
+    put_string(&mut signed_data, namespace);
+    put_string(&mut signed_data, "");
+    put_string(&mut signed_data, HASH_ALGORITHM);
+    put_string(&mut signed_data, hash);
+    let signature = identity.sign(protocol_adapter, &signed_data)?;
+
+    let mut out = MAGIC_PREAMBLE.to_vec();
+    put_u32(&mut out, SIG_VERSION);
+    put_string(&mut out, identity.key_blob()?);
+    put_string(&mut out, namespace);
+    put_string(&mut out, "");
+    put_string(&mut out, HASH_ALGORITHM);
+    put_string(&mut out, signature);
+    Ok(armor("SSH SIGNATURE", 70, "", &out, ""))
+}
+
+impl CliCommand for SshKeygen {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let namespace = self
+            .namespace
+            .as_deref()
+            .ok_or_else(|| anyhow!("-n is required to sign"))?;
+        let key_file = self
+            .key_file
+            .as_deref()
+            .ok_or_else(|| anyhow!("-f is required to sign"))?;
+        let index = self.sign_index()?;
+        let (curve, key, url) = read_key_file(key_file)?;
+        let identity = SshIdentity::fetch(protocol_adapter, &url, index, curve)?;
+        if base64::encode(identity.key_blob()?) != key {
+            bail!("the key in {} isn't the device's key for {}", key_file, url);
+        }
+
+        if self.files.is_empty() || self.files == ["-"] {
+            let mut message = Vec::new();
+            stdin().read_to_end(&mut message)?;
+            let signature = sign(protocol_adapter, &identity, namespace, &message)?;
+            stdout().write_all(signature.as_bytes())?;
+            return Ok(());
+        }
+        for file in &self.files {
+            let message = fs::read(file).with_context(|| format!("couldn't read {}", file))?;
+            let signature = sign(protocol_adapter, &identity, namespace, &message)?;
+            let path = format!("{}.sig", file);
+            fs::write(&path, signature).with_context(|| format!("couldn't write {}", path))?;
+            eprintln!("Wrote signature to {}", path);
+        }
+        Ok(())
+    }
+}
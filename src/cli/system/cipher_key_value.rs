@@ -1,6 +1,6 @@
 use crate::{
     cli::{
-        expect_field, expect_message,
+        expect_field, expect_message, output,
         parsers::{Base64Parser, Bip32PathParser, HexParser16},
         types::{Bip32Path, ByteVec},
         CliCommand,
@@ -54,7 +54,8 @@ impl CliCommand for CipherKeyValue {
             )
         )?;
 
-        println!("{}", base64::encode(expect_field!(resp.value)?));
+        let value = base64::encode(expect_field!(resp.value)?);
+        output!("value" => value, "{}", value);
 
         Ok(())
     }
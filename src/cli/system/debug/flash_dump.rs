@@ -1,5 +1,5 @@
 use crate::{
-    cli::{expect_field, expect_message, CliDebugCommand},
+    cli::{expect_field, expect_message, output, CliDebugCommand},
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
@@ -33,7 +33,8 @@ impl CliDebugCommand for DebugLinkFlashDump {
             )
         )?;
 
-        println!("{}", hex::encode(expect_field!(resp.data)?));
+        let data = hex::encode(expect_field!(resp.data)?);
+        output!("data" => data, "{}", data);
 
         Ok(())
     }
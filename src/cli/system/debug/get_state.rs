@@ -1,5 +1,5 @@
 use crate::{
-    cli::{expect_message, CliDebugCommand},
+    cli::{expect_message, output, CliDebugCommand},
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
@@ -24,7 +24,7 @@ impl CliDebugCommand for DebugLinkGetState {
             debug_protocol_adapter.handle(messages::DebugLinkGetState {}.into())
         )?;
 
-        println!("{:#?}", resp);
+        output!("state" => resp, "{:#?}", resp);
 
         Ok(())
     }
@@ -0,0 +1,121 @@
+use crate::{
+    cli::{output, parsers::HexParser, types::ByteVec, CliCommand},
+    firmware::FirmwareImage,
+    releases::Manifest,
+    transport::ProtocolAdapter,
+};
+use anyhow::{bail, Result};
+use bitcoin::secp256k1::PublicKey;
+use clap::Args;
+use serde_json::json;
+
+/// Show information about a firmware image file (doesn't need a device)
+#[derive(Debug, Clone, Args)]
+pub struct FirmwareInfo {
+    /// trusted firmware signing public key to check signatures against, instead of the
+    /// manifest's; repeat it for each key in the bootloader's order, since a signature's index
+    /// picks which key it's checked with
+    #[clap(long = "signing-key", value_parser = HexParser)]
+    signing_keys: Vec<ByteVec>,
+    /// release manifest to identify the firmware hash with, instead of the bundled one
+    #[clap(long)]
+    manifest: Option<String>,
+    file_path: String,
+}
+
+/// The keys given with --signing-key, or the manifest's if there aren't any.
+pub(super) fn trusted_signing_keys(
+    signing_keys: &[ByteVec],
+    manifest: &Manifest,
+) -> Result<Vec<PublicKey>> {
+    let signing_keys = match signing_keys {
+        [] => &manifest.signing_keys[..],
+        x => x,
+    };
+    Ok(signing_keys
+        .iter()
+        .map(|x| PublicKey::from_slice(x))
+        .collect::<Result<_, _>>()?)
+}
+
+pub(super) fn print_firmware_info(
+    image: &FirmwareImage,
+    signing_keys: &[PublicKey],
+    manifest: &Manifest,
+) -> Result<()> {
+    let release = manifest.firmware_status(&image.firmware_hash());
+    if output::is_json() {
+        return output::record(
+            "image",
+            &json!({
+                "magic": hex::encode(image.magic),
+                "version": release.release().map(|x| &x.version),
+                "codeLength": image.code_length,
+                "flags": image.flags,
+                "firmwareHash": hex::encode(image.firmware_hash()),
+                "firmwareRelease": release,
+                "payloadHash": hex::encode(image.payload_hash()),
+                "signatures": image
+                    .signature_status(signing_keys)
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>(),
+                "signed": image.is_signed(),
+            }),
+        );
+    }
+    println!(
+        "magic:\t\t\t{}",
+        std::str::from_utf8(&image.magic)
+            .map_or_else(|_| hex::encode(image.magic), |x| x.to_owned())
+    );
+    match release.release() {
+        Some(x) => println!("version:\t\t{}", x.version),
+        None => println!("version:\t\tunknown (the header doesn't record it)"),
+    }
+    println!("code length:\t\t{} bytes", image.code_length);
+    println!("flags:\t\t\t{:#04x}", image.flags);
+    println!("firmware hash:\t\t{}", hex::encode(image.firmware_hash()));
+    println!("\t\t\t({})", release);
+    println!("payload hash:\t\t{}", hex::encode(image.payload_hash()));
+    for (i, status) in image.signature_status(signing_keys).iter().enumerate() {
+        println!("signature {}:\t\t{}", i + 1, status);
+    }
+    if !image.is_signed() {
+        println!("\t\t\t(image is unsigned; the device will warn that it's unofficial)");
+    } else if signing_keys.is_empty() {
+        println!("\t\t\t(no trusted signing keys to check with; pass --signing-key or a --manifest that lists them)");
+    }
+    Ok(())
+}
+
+impl FirmwareInfo {
+    pub fn handle(self) -> Result<()> {
+        let manifest = Manifest::load(self.manifest.as_deref())?;
+        let signing_keys = trusted_signing_keys(&self.signing_keys, &manifest)?;
+        let image = FirmwareImage::parse(std::fs::read(self.file_path)?)?;
+        print_firmware_info(&image, &signing_keys, &manifest)?;
+
+        let problems = image.problems(&signing_keys);
+        if !problems.is_empty() {
+            if output::is_json() {
+                output::record("problems", &problems)?;
+                bail!("firmware image failed validation");
+            }
+            println!();
+            println!("problems:");
+            for problem in problems.iter() {
+                println!("\t{}", problem);
+            }
+            bail!("firmware image failed validation");
+        }
+
+        Ok(())
+    }
+}
+
+impl CliCommand for FirmwareInfo {
+    fn handle(self, _: &mut dyn ProtocolAdapter) -> Result<()> {
+        unreachable!();
+    }
+}
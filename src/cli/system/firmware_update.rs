@@ -1,11 +1,24 @@
+use super::firmware_info::{print_firmware_info, trusted_signing_keys};
 use crate::{
-    cli::{expect_success, CliCommand},
-    messages,
-    transport::ProtocolAdapter,
+    cli::{expect_message, expect_success, output, parsers::HexParser, types::ByteVec, CliCommand},
+    firmware::FirmwareImage,
+    messages::{self, Message},
+    releases::Manifest,
+    transport::{
+        list_devices, read_features, read_serial_number, ProtocolAdapter, UsbTransport,
+        SELECTED_DEVICE,
+    },
 };
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use clap::{ArgAction::SetTrue, Args};
-use sha2::{Digest, Sha256};
+use core::time::Duration;
+use rusb::{Device, GlobalContext};
+use std::{io::Write, time::Instant};
+
+/// How long to wait for the device to come back after the upload, including time for the user to replug it.
+const REENUMERATION_TIMEOUT: Duration = Duration::from_secs(120);
+const REENUMERATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const PROGRESS_BAR_WIDTH: usize = 40;
 
 /// Upload new firmware to device (must be in bootloader mode)
 #[derive(Debug, Clone, Args)]
@@ -13,28 +26,217 @@ pub struct FirmwareUpdate {
     /// Don't send the usual firmware erase command before uploading the new firmware.
     #[clap(short, long, action = SetTrue)]
     skip_erase: bool,
+    /// Upload the image even if it looks corrupt or isn't meant for a KeepKey.
+    #[clap(long, action = SetTrue)]
+    force: bool,
+    /// Don't wait for the device to restart and check that it's running the new firmware.
+    #[clap(long, action = SetTrue)]
+    no_verify: bool,
+    /// trusted firmware signing public key to check signatures against, instead of the
+    /// manifest's; repeat it for each key in the bootloader's order, since a signature's index
+    /// picks which key it's checked with
+    #[clap(long = "signing-key", value_parser = HexParser)]
+    signing_keys: Vec<ByteVec>,
+    /// release manifest to identify the firmware hash with, instead of the bundled one
+    #[clap(long)]
+    manifest: Option<String>,
     file_path: String,
 }
 
+fn print_progress(done: usize, total: usize) {
+    let total = total.max(1);
+    let filled = done * PROGRESS_BAR_WIDTH / total;
+    eprint!(
+        "\r[{}{}] {:>3}%",
+        "#".repeat(filled),
+        " ".repeat(PROGRESS_BAR_WIDTH - filled),
+        done * 100 / total
+    );
+    if done >= total {
+        eprintln!();
+    }
+    std::io::stderr().flush().ok();
+}
+
+/// What tells the device being updated apart from others once it restarts.
+struct UpdatedDevice {
+    /// Where devices were before the update. Re-enumeration always gives the device a new address.
+    old_locations: Vec<(u8, u8)>,
+    /// USB serial number of the device being updated, if it has one.
+    serial_number: Option<String>,
+    /// Reported by the bootloader, if it does.
+    device_id: Option<String>,
+    label: Option<String>,
+}
+
+impl UpdatedDevice {
+    fn new(features: &messages::Features) -> Result<Self> {
+        let selected = SELECTED_DEVICE.read().unwrap().ok_or_else(|| {
+            anyhow!("the device can only be found again after the update over USB (use --no-verify to skip checking)")
+        })?;
+        let devices = list_devices()?;
+        let out = Self {
+            old_locations: devices
+                .iter()
+                .map(|x| (x.bus_number(), x.address()))
+                .collect(),
+            serial_number: devices
+                .iter()
+                .find(|x| (x.bus_number(), x.address()) == selected)
+                .and_then(|x| read_serial_number(x).ok()),
+            device_id: features.device_id.clone(),
+            label: features.label.clone().filter(|x| !x.is_empty()),
+        };
+        if out.serial_number.is_none() && out.device_id.is_none() && out.label.is_none() {
+            bail!("the device doesn't report a serial number, device ID or label, so it can't be found again after the update (use --no-verify to skip checking)");
+        }
+        Ok(out)
+    }
+
+    fn matches(&self, device: &Device<GlobalContext>) -> bool {
+        if self
+            .old_locations
+            .contains(&(device.bus_number(), device.address()))
+        {
+            return false;
+        }
+        if let Some(serial_number) = &self.serial_number {
+            match read_serial_number(device) {
+                Ok(x) if x == *serial_number => {}
+                _ => return false,
+            }
+        }
+        if self.device_id.is_none() && self.label.is_none() {
+            return true;
+        }
+        let features = match read_features(device) {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+        (self.device_id.is_none() || features.device_id == self.device_id)
+            && (self.label.is_none() || features.label == self.label)
+    }
+}
+
+fn wait_for_updated_device(
+    updated_device: &UpdatedDevice,
+    timeout: Duration,
+) -> Result<Device<GlobalContext>> {
+    let started = Instant::now();
+    loop {
+        if let Some(x) = list_devices()?.iter().find(|x| updated_device.matches(x)) {
+            return Ok(x.to_owned());
+        }
+        if started.elapsed() >= timeout {
+            bail!(
+                "timed out waiting for the device to reconnect after {}",
+                humantime::format_duration(timeout)
+            );
+        }
+        std::thread::sleep(REENUMERATION_POLL_INTERVAL);
+    }
+}
+
 impl CliCommand for FirmwareUpdate {
     fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
-        let payload = std::fs::read(self.file_path)?;
+        let manifest = Manifest::load(self.manifest.as_deref())?;
+        let signing_keys = trusted_signing_keys(&self.signing_keys, &manifest)?;
+        let image = FirmwareImage::parse(std::fs::read(self.file_path)?)?;
+        print_firmware_info(&image, &signing_keys, &manifest)?;
+        eprintln!();
+
+        let problems = image.problems(&signing_keys);
+        for problem in problems.iter() {
+            eprintln!("Warning: {}", problem);
+        }
+        if !problems.is_empty() && !self.force {
+            bail!("refusing to upload a bad firmware image (use --force to upload anyway)");
+        }
+
+        let features = expect_message!(
+            Message::Features,
+            protocol_adapter.handle(messages::Initialize::default().into())
+        )?;
+        if !features.bootloader_mode.unwrap_or(false) {
+            bail!("device is not in bootloader mode (unplug it, then hold down its button while plugging it back in)");
+        }
+        let bootloader_version = features.version();
+        output!("bootloaderVersion" => bootloader_version, "bootloader version:\t{}", bootloader_version);
+        eprintln!();
+
+        let updated_device = match self.no_verify {
+            false => Some(UpdatedDevice::new(&features)?),
+            true => None,
+        };
+        let firmware_hash = image.firmware_hash();
+        let image_version = manifest
+            .firmware_status(&firmware_hash)
+            .release()
+            .map(|x| x.version.clone());
 
         if !self.skip_erase {
-            println!("Erasing firmware...");
+            eprintln!("Erasing firmware...");
             expect_success!(protocol_adapter
                 .with_standard_handler()
                 .handle(messages::FirmwareErase::default().into()),)?;
         }
 
-        println!("Uploading firmware...");
-        expect_success!(protocol_adapter.with_standard_handler().handle(
-            messages::FirmwareUpload {
-                payload_hash: Sha256::digest(&payload).to_vec(),
-                payload,
-            }
-            .into()
-        ),)?;
+        eprintln!("Uploading firmware...");
+        expect_success!(protocol_adapter
+            .with_standard_handler()
+            .handle_with_progress(
+                messages::FirmwareUpload {
+                    payload_hash: image.payload_hash().to_vec(),
+                    payload: image.into_payload(),
+                }
+                .into(),
+                &mut print_progress,
+            ),)?;
+
+        let updated_device = match updated_device {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        eprintln!();
+        eprintln!(
+            "Waiting for device to restart (if it doesn't, unplug it and plug it back in)..."
+        );
+        let device = wait_for_updated_device(&updated_device, REENUMERATION_TIMEOUT)?;
+        let (mut transport, _, _) = UsbTransport::new(&device, 0)?;
+        transport.reset()?;
+        let features = expect_message!(
+            Message::Features,
+            transport.handle(messages::Initialize::default().into())
+        )?;
+
+        if features.bootloader_mode.unwrap_or(false) {
+            bail!("device restarted in bootloader mode; the new firmware isn't running");
+        }
+        let reported_hash = features
+            .firmware_hash
+            .as_ref()
+            .ok_or_else(|| anyhow!("device didn't report its firmware hash"))?;
+        if reported_hash[..] != firmware_hash[..] {
+            bail!(
+                "device reports firmware hash {}, but the uploaded image's is {}",
+                hex::encode(reported_hash),
+                hex::encode(firmware_hash)
+            );
+        }
+        let firmware_version = features.version();
+        if let Some(x) = image_version.filter(|x| x.trim_start_matches('v') != firmware_version) {
+            bail!(
+                "device reports firmware version {}, but the uploaded image is version {}",
+                firmware_version,
+                x
+            );
+        }
+        output!("firmwareVersion" => firmware_version, "firmware version:\t{}", firmware_version);
+        let reported_hash = hex::encode(reported_hash);
+        output!("firmwareHash" => reported_hash, "firmware hash:\t\t{} (matches)", reported_hash);
+        let firmware_release = manifest.firmware_status(&firmware_hash);
+        output!("firmwareRelease" => firmware_release, "\t\t\t({})", firmware_release);
 
         Ok(())
     }
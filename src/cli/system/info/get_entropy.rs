@@ -1,5 +1,5 @@
 use crate::{
-    cli::{expect_message, CliCommand},
+    cli::{expect_message, output, CliCommand},
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
@@ -20,7 +20,8 @@ impl CliCommand for GetEntropy {
                 .with_standard_handler()
                 .handle(messages::GetEntropy { size: self.size }.into())
         )?;
-        println!("{}", hex::encode(resp.entropy));
+        let entropy = hex::encode(resp.entropy);
+        output!("entropy" => entropy, "{}", entropy);
 
         Ok(())
     }
@@ -1,6 +1,7 @@
 use crate::{
-    cli::{expect_message, CliCommand},
+    cli::{expect_message, output, CliCommand},
     messages::{self, Message},
+    releases::Manifest,
     transport::ProtocolAdapter,
 };
 use anyhow::Result;
@@ -9,15 +10,34 @@ use core::time::Duration;
 
 /// Retrieve device features and settings
 #[derive(Debug, Clone, Args)]
-pub struct GetFeatures;
+pub struct GetFeatures {
+    /// release manifest to identify the firmware and bootloader hashes with, instead of the bundled one
+    #[clap(long)]
+    manifest: Option<String>,
+}
 
 impl CliCommand for GetFeatures {
     fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let manifest = Manifest::load(self.manifest.as_deref())?;
         let features = expect_message!(
             Message::Features,
             protocol_adapter.handle(messages::GetFeatures::default().into())
         )?;
 
+        if output::is_json() {
+            if let Some(firmware_hash) = &features.firmware_hash {
+                output::record("firmwareRelease", &manifest.firmware_status(firmware_hash))?;
+            }
+            if let Some(bootloader_hash) = &features.bootloader_hash {
+                output::record(
+                    "bootloaderRelease",
+                    &manifest.bootloader_status(bootloader_hash),
+                )?;
+            }
+            output::record("features", &features)?;
+            return Ok(());
+        }
+
         if let Some(label) = features.label {
             println!("label:\t{}", label);
         }
@@ -68,10 +88,12 @@ impl CliCommand for GetFeatures {
             );
         }
         if let Some(firmware_hash) = features.firmware_hash {
-            println!("firmware hash:\t\t{}", hex::encode(firmware_hash));
+            println!("firmware hash:\t\t{}", hex::encode(&firmware_hash));
+            println!("\t\t\t({})", manifest.firmware_status(&firmware_hash));
         }
         if let Some(bootloader_hash) = features.bootloader_hash {
-            println!("bootloader hash:\t{}", hex::encode(bootloader_hash));
+            println!("bootloader hash:\t{}", hex::encode(&bootloader_hash));
+            println!("\t\t\t({})", manifest.bootloader_status(&bootloader_hash));
         }
 
         println!();
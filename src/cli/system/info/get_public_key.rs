@@ -1,6 +1,6 @@
 use crate::{
     cli::{
-        expect_field, expect_message,
+        expect_field, expect_message, output,
         parsers::Bip32PathParser,
         types::{Bip32Path, ScriptType},
         CliCommand,
@@ -44,7 +44,8 @@ impl CliCommand for GetPublicKey {
             )
         )?;
 
-        println!("{}", expect_field!(resp.xpub)?);
+        let xpub = expect_field!(resp.xpub)?;
+        output!("xpub" => xpub, "{}", xpub);
 
         Ok(())
     }
@@ -1,5 +1,5 @@
 use crate::{
-    cli::{expect_field, expect_message, CliCommand},
+    cli::{expect_field, expect_message, output, CliCommand},
     messages::{self, CoinType, Message},
     transport::ProtocolAdapter,
 };
@@ -44,10 +44,8 @@ impl CliCommand for ListCoins {
                 resp.table
             });
 
-        println!(
-            "{}",
-            serde_json::to_string_pretty(&coin_table.collect::<Vec<CoinType>>())?
-        );
+        let coins = coin_table.collect::<Vec<CoinType>>();
+        output!("coins" => coins, "{}", serde_json::to_string_pretty(&coins)?);
 
         Ok(())
     }
@@ -3,9 +3,11 @@ mod get_features;
 mod get_public_key;
 mod list_coins;
 mod ping;
+mod verify_device;
 
 pub use get_entropy::*;
 pub use get_features::*;
 pub use get_public_key::*;
 pub use list_coins::*;
 pub use ping::*;
+pub use verify_device::*;
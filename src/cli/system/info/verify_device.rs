@@ -0,0 +1,66 @@
+use crate::{
+    cli::{expect_message, output, CliCommand},
+    messages::{self, Message},
+    releases::{Manifest, ReleaseStatus},
+    transport::ProtocolAdapter,
+};
+use anyhow::{bail, Result};
+use clap::Args;
+use serde_json::json;
+
+/// Check the device's bootloader and firmware against the release manifest
+///
+/// Each is labelled as a known release, unknown (not in the manifest, which may just be older than
+/// the software), or known-vulnerable. Only a known-vulnerable one makes the command fail.
+#[derive(Debug, Clone, Args)]
+pub struct VerifyDevice {
+    /// release manifest to check against, instead of the bundled one
+    #[clap(long)]
+    manifest: Option<String>,
+}
+
+fn print_status(label: &str, hash: Option<&Vec<u8>>, status: ReleaseStatus) -> Result<()> {
+    if output::is_json() {
+        return output::record(
+            label,
+            &json!({
+                "hash": hash.map(hex::encode),
+                "release": status,
+            }),
+        );
+    }
+    match hash {
+        Some(x) => println!("{}:\t\t{}", label, hex::encode(x)),
+        None => println!("{}:\t\t(hash not reported)", label),
+    }
+    println!("\t\t\t({})", status);
+    Ok(())
+}
+
+impl CliCommand for VerifyDevice {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
+        let manifest = Manifest::load(self.manifest.as_deref())?;
+        let features = expect_message!(
+            Message::Features,
+            protocol_adapter.handle(messages::GetFeatures::default().into())
+        )?;
+
+        let bootloader_hash = features.bootloader_hash.as_ref();
+        let bootloader_status =
+            bootloader_hash.map_or(ReleaseStatus::Unknown, |x| manifest.bootloader_status(x));
+        let firmware_hash = features.firmware_hash.as_ref();
+        let firmware_status =
+            firmware_hash.map_or(ReleaseStatus::Unknown, |x| manifest.firmware_status(x));
+
+        print_status("bootloader", bootloader_hash, bootloader_status)?;
+        print_status("firmware", firmware_hash, firmware_status)?;
+
+        if matches!(bootloader_status, ReleaseStatus::Vulnerable(_))
+            || matches!(firmware_status, ReleaseStatus::Vulnerable(_))
+        {
+            bail!("device is running software with known security problems");
+        }
+
+        Ok(())
+    }
+}
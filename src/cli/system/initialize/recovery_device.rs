@@ -9,7 +9,7 @@ use clap::{
     Args,
 };
 use crossterm::event::{Event, KeyCode, KeyEvent};
-use std::io::{stdout, Write};
+use std::io::{stderr, Write};
 
 /// Start safe recovery workflow
 #[derive(Debug, Clone, Args)]
@@ -45,10 +45,10 @@ impl CliCommand for RecoveryDevice {
             .with_mut_handler(&mut |msg| match msg {
                 Message::CharacterRequest(messages::CharacterRequest { character_pos, .. }) => {
                     if !printed_char_req_msg {
-                        print!(
+                        eprint!(
                             "Enter your mnemonic using the cipher shown on your device screen: "
                         );
-                        stdout().flush().unwrap();
+                        stderr().flush().unwrap();
                         printed_char_req_msg = true;
                     }
                     Ok(Some((|| -> crossterm::Result<Message> {
@@ -96,7 +96,7 @@ impl CliCommand for RecoveryDevice {
                 }
                 _ => {
                     if printed_char_req_msg {
-                        println!();
+                        eprintln!();
                     }
                     Ok(None)
                 }
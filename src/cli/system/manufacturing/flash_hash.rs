@@ -1,5 +1,5 @@
 use crate::{
-    cli::{expect_field, expect_message, parsers::HexParser, types::ByteVec, CliCommand},
+    cli::{expect_field, expect_message, output, parsers::HexParser, types::ByteVec, CliCommand},
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
@@ -29,7 +29,8 @@ impl CliCommand for FlashHash {
             )
         )?;
 
-        println!("{}", hex::encode(expect_field!(resp.data)?));
+        let hash = hex::encode(expect_field!(resp.data)?);
+        output!("hash" => hash, "{}", hash);
 
         Ok(())
     }
@@ -1,5 +1,5 @@
 use crate::{
-    cli::{expect_field, expect_message, parsers::HexParser, types::ByteVec, CliCommand},
+    cli::{expect_field, expect_message, output, parsers::HexParser, types::ByteVec, CliCommand},
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
@@ -31,7 +31,8 @@ impl CliCommand for FlashWrite {
             )
         )?;
 
-        println!("{}", hex::encode(expect_field!(resp.data)?));
+        let data = hex::encode(expect_field!(resp.data)?);
+        output!("data" => data, "{}", data);
 
         Ok(())
     }
@@ -5,6 +5,7 @@ mod change_wipe_code;
 mod cipher_key_value;
 mod clear_session;
 pub mod debug;
+mod firmware_info;
 mod firmware_update;
 pub mod info;
 pub mod initialize;
@@ -19,6 +20,7 @@ pub use change_wipe_code::*;
 pub use cipher_key_value::*;
 pub use clear_session::*;
 pub use debug::*;
+pub use firmware_info::*;
 pub use firmware_update::*;
 pub use info::*;
 pub use initialize::*;
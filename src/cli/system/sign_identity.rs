@@ -1,10 +1,11 @@
 use crate::{
-    cli::{expect_field, expect_message, parsers::HexParser, types::ByteVec, CliCommand},
+    cli::{expect_field, expect_message, output, parsers::HexParser, types::ByteVec, CliCommand},
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Args;
+use sha2::{Digest, Sha256};
 use url::Url;
 
 /// Ask device to sign an identity challenge.
@@ -28,36 +29,140 @@ pub struct SignIdentity {
     ecdsa_curve_name: Option<String>,
 }
 
-impl CliCommand for SignIdentity {
-    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
-        let url = Some(self.url)
+/// Splits an identity URL into the parts the device knows it by.
+pub(crate) fn identity_from_url(url: &str, index: Option<u32>) -> Result<messages::IdentityType> {
+    let url = Some(url)
+        .filter(|x| !(*x).is_empty())
+        .map(Url::parse)
+        .transpose()?;
+
+    Ok(messages::IdentityType {
+        proto: url.as_ref().map(|x| x.scheme().to_string()),
+        user: url
+            .as_ref()
+            .map(|x| x.username())
+            .filter(|x| !(*x).is_empty())
+            .map(|x| x.to_string()),
+        host: url
+            .as_ref()
+            .and_then(|x| x.host_str())
+            .map(|x| x.to_string()),
+        port: url.as_ref().and_then(|x| x.port()).map(|x| x.to_string()),
+        path: url
+            .as_ref()
+            .map(|x| x.path())
             .filter(|x| !(*x).is_empty())
-            .map(|x| Url::parse(&x))
-            .transpose()?;
+            .map(|x| x.to_string()),
+        index,
+    })
+}
+
+/// Puts an identity back together as the URL the firmware hashes for it.
+fn identity_url(identity: &messages::IdentityType) -> String {
+    fn part(x: &Option<String>) -> &str {
+        x.as_deref().unwrap_or_default()
+    }
+    let mut out = String::new();
+    if !part(&identity.proto).is_empty() {
+        out.push_str(part(&identity.proto));
+        out.push_str("://");
+    }
+    if !part(&identity.user).is_empty() {
+        out.push_str(part(&identity.user));
+        out.push('@');
+    }
+    out.push_str(part(&identity.host));
+    if !part(&identity.port).is_empty() {
+        out.push(':');
+        out.push_str(part(&identity.port));
+    }
+    out.push_str(part(&identity.path));
+    out
+}
+
+/// BIP-32 path of the key the device signs with for an identity, worked out the way the firmware
+/// does it (SLIP-0013), so its public key can be fetched without signing anything.
+pub(crate) fn identity_path(identity: &messages::IdentityType) -> Vec<u32> {
+    let mut hasher = Sha256::new();
+    hasher.update(identity.index.unwrap_or(0).to_le_bytes());
+    hasher.update(identity_url(identity));
+    let hash = hasher.finalize();
+
+    let mut path = vec![0x8000_000d];
+    path.extend(
+        hash[..16]
+            .chunks(4)
+            .map(|x| 0x8000_0000 | u32::from_le_bytes(x.try_into().unwrap())),
+    );
+    path
+}
+
+/// Fetches the public key the device signs with for an identity, without anything needing
+/// confirming on the device.
+pub(crate) fn identity_public_key(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    identity: &messages::IdentityType,
+    curve_name: &str,
+) -> Result<Vec<u8>> {
+    let resp = expect_message!(
+        Message::PublicKey,
+        protocol_adapter.with_standard_handler().handle(
+            messages::GetPublicKey {
+                address_n: identity_path(identity),
+                ecdsa_curve_name: Some(curve_name.to_owned()),
+                show_display: None,
+                coin_name: None,
+                script_type: None,
+            }
+            .into(),
+        )
+    )?;
+    let node = resp.node;
+    Ok(expect_field!(node.public_key)?.clone())
+}
+
+/// Has the device sign `challenge` for an identity, checking it used `public_key`, the one
+/// [`identity_public_key`] gave. Gives the signature as 64 bytes.
+pub(crate) fn sign_with_identity(
+    protocol_adapter: &mut dyn ProtocolAdapter,
+    identity: &messages::IdentityType,
+    curve_name: &str,
+    public_key: &[u8],
+    challenge: &[u8],
+) -> Result<Vec<u8>> {
+    let resp = expect_message!(
+        Message::SignedIdentity,
+        protocol_adapter.with_standard_handler().handle(
+            messages::SignIdentity {
+                identity: Some(identity.clone()),
+                challenge_hidden: Some(challenge.to_vec()),
+                challenge_visual: None,
+                ecdsa_curve_name: Some(curve_name.to_owned()),
+            }
+            .into(),
+        )
+    )?;
 
+    if expect_field!(resp.public_key)?.get(1..) != public_key.get(1..) {
+        bail!(
+            "device signed with a different key than it gave for {}",
+            identity_url(identity)
+        );
+    }
+    // The device pads signatures to 65 bytes with a zero at the front.
+    match &expect_field!(resp.signature)?[..] {
+        [0, x @ ..] if x.len() == 64 => Ok(x.to_vec()),
+        _ => bail!("unexpected signature from device"),
+    }
+}
+
+impl CliCommand for SignIdentity {
+    fn handle(self, protocol_adapter: &mut dyn ProtocolAdapter) -> Result<()> {
         let resp = expect_message!(
             Message::SignedIdentity,
             protocol_adapter.with_standard_handler().handle(
                 messages::SignIdentity {
-                    identity: Some(messages::IdentityType {
-                        proto: url.as_ref().map(|x| x.scheme().to_string()),
-                        user: url
-                            .as_ref()
-                            .map(|x| x.username())
-                            .filter(|x| !(*x).is_empty())
-                            .map(|x| x.to_string()),
-                        host: url
-                            .as_ref()
-                            .and_then(|x| x.host_str())
-                            .map(|x| x.to_string()),
-                        port: url.as_ref().and_then(|x| x.port()).map(|x| x.to_string()),
-                        path: url
-                            .as_ref()
-                            .map(|x| x.path())
-                            .filter(|x| !(*x).is_empty())
-                            .map(|x| x.to_string()),
-                        index: self.index,
-                    }),
+                    identity: Some(identity_from_url(&self.url, self.index)?),
                     challenge_hidden: Some(self.challenge_hidden),
                     challenge_visual: self.challenge_visual,
                     ecdsa_curve_name: self.ecdsa_curve_name,
@@ -67,17 +172,28 @@ impl CliCommand for SignIdentity {
         )?;
 
         if let Some(ref address) = resp.address {
-            println!("Address:\t{}", address);
+            output!("address" => address, "Address:\t{}", address);
         }
-        println!(
-            "Public Key:\t{}",
-            hex::encode(expect_field!(resp.public_key)?)
-        );
-        println!(
-            "Signature:\t{}",
-            hex::encode(expect_field!(resp.signature)?)
-        );
+        let public_key = hex::encode(expect_field!(resp.public_key)?);
+        output!("publicKey" => public_key, "Public Key:\t{}", public_key);
+        let signature = hex::encode(expect_field!(resp.signature)?);
+        output!("signature" => signature, "Signature:\t{}", signature);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_slip_0013_path() {
+        // The example from SLIP-0013.
+        let identity = identity_from_url("https://satoshi@bitcoin.org/login", None).unwrap();
+        assert_eq!(
+            identity_path(&identity),
+            [2147483661, 2637750992, 2845082444, 3761103859, 4005495825]
+        );
+    }
+}
@@ -3,12 +3,33 @@ mod bip32;
 pub use bip32::Bip32Path;
 
 use crate::messages;
+use anyhow::{Context, Result};
 use clap::ValueEnum;
+use core::{str::FromStr, time::Duration};
 use primitive_types::U256;
 
 /// This type alias keeps clap's derive macro from misinterpreting an arg which takes many bytes as a repeated arg where each instance takes one byte.
 pub type ByteVec = Vec<u8>;
 
+/// A timeout like 30s or 5m, or forever, which is `None`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Timeout(pub Option<Duration>);
+
+impl FromStr for Timeout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self(match s {
+            "forever" => None,
+            x => Some(
+                x.parse::<humantime::Duration>()
+                    .context("expected a timeout like 30s or 5m, or forever")?
+                    .into(),
+            ),
+        }))
+    }
+}
+
 pub trait IntoBigEndian {
     fn into_big_endian(self) -> Vec<u8>;
 }
@@ -90,13 +111,13 @@ impl From<OutputAddressType> for i32 {
     }
 }
 
-/*#[derive(Debug, Copy, Clone, ValueEnum)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
 pub enum TransportType {
     Usb,
-    Serial,
+    /*Serial,
     Pipe,
-    Socket,
+    Socket,*/
     Bridge,
-    Udp,
-    WebUsb,
-}*/
+    /*Udp,
+    WebUsb,*/
+}
@@ -1,6 +1,6 @@
 use crate::{
     cli::{
-        expect_message,
+        expect_message, output,
         parsers::Bip32PathParser,
         types::{Bip32Path, ScriptType},
         CliCommand,
@@ -42,7 +42,7 @@ impl CliCommand for GetAddress {
             ),
         )?;
 
-        println!("{}", resp.address);
+        output!("address" => resp.address, "{}", resp.address);
 
         Ok(())
     }
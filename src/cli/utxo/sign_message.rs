@@ -1,6 +1,6 @@
 use crate::{
     cli::{
-        expect_field, expect_message,
+        expect_field, expect_message, output,
         parsers::Bip32PathParser,
         types::{Bip32Path, ScriptType},
         CliCommand,
@@ -39,11 +39,10 @@ impl CliCommand for SignMessage {
             )
         )?;
 
-        println!("Address: {}", expect_field!(resp.address)?);
-        println!(
-            "Signature: {}",
-            base64::encode(expect_field!(resp.signature)?)
-        );
+        let address = expect_field!(resp.address)?;
+        output!("address" => address, "Address: {}", address);
+        let signature = base64::encode(expect_field!(resp.signature)?);
+        output!("signature" => signature, "Signature: {}", signature);
 
         Ok(())
     }
@@ -1,5 +1,5 @@
 use crate::{
-    cli::{expect_message, expect_field, parsers::{Bip32PathParser, HexParser32}, types::Bip32Path, CliCommand},
+    cli::{expect_message, expect_field, output, parsers::{Bip32PathParser, HexParser32}, types::Bip32Path, CliCommand},
     messages::{self, Message},
     transport::ProtocolAdapter,
 };
@@ -119,8 +119,10 @@ impl CliCommand for SignTx {
             )
         )?;
         
-        println!("Signature:\t{}", hex::encode(expect_field!(resp.signature)?));
-        println!("Block Hash:\t{}", hex::encode(expect_field!(resp.block_hash)?));
+        let signature = hex::encode(expect_field!(resp.signature)?);
+        output!("signature" => signature, "Signature:\t{}", signature);
+        let block_hash = hex::encode(expect_field!(resp.block_hash)?);
+        output!("blockHash" => block_hash, "Block Hash:\t{}", block_hash);
 
         Ok(())
     }
@@ -0,0 +1,162 @@
+use crate::{
+    cli::{output, CliCommand},
+    transport::{list_devices, read_serial_number, ProtocolAdapter},
+};
+use anyhow::Result;
+use clap::Args;
+use core::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, io::Write};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Print a line each time a KeepKey is connected or disconnected, until interrupted
+#[derive(Debug, Clone, Args)]
+pub struct Watch;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceInfo {
+    bus: u8,
+    address: u8,
+    product_id: u16,
+    serial_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "event")]
+enum Event<'a> {
+    Connected(&'a DeviceInfo),
+    Disconnected(&'a DeviceInfo),
+    /// The same device came back with the other product ID, which happens when it switches between the bootloader and the firmware.
+    #[serde(rename_all = "camelCase")]
+    Switched {
+        #[serde(flatten)]
+        device: &'a DeviceInfo,
+        old_product_id: u16,
+    },
+}
+
+/// Product IDs as the device uses them: the bootloader (and older firmware) enumerates as a HID device,
+/// current firmware as a WebUSB one.
+fn describe_product_id(product_id: u16) -> &'static str {
+    match product_id {
+        0x0001 => "HID; bootloader or older firmware",
+        0x0002 => "WebUSB firmware",
+        _ => "unknown",
+    }
+}
+
+impl Display for DeviceInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Bus {:03} Device {:03} ID 2b24:{:04x} ({})",
+            self.bus,
+            self.address,
+            self.product_id,
+            describe_product_id(self.product_id)
+        )?;
+        if let Some(x) = &self.serial_number {
+            write!(f, "\t({})", x)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Event<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connected(x) => write!(f, "connected:\t{}", x),
+            Self::Disconnected(x) => write!(f, "disconnected:\t{}", x),
+            Self::Switched {
+                device,
+                old_product_id,
+            } => write!(f, "switched:\t{} (was 2b24:{:04x})", device, old_product_id),
+        }
+    }
+}
+
+fn print_event(event: &Event) -> Result<()> {
+    // There's no final result to wait for, so in --json mode each event is printed as its own line.
+    if output::is_json() {
+        println!("{}", serde_json::to_string(event)?);
+    } else {
+        println!("{}", event);
+    }
+    std::io::stdout().flush()?;
+    Ok(())
+}
+
+fn scan(known: &BTreeMap<(u8, u8), DeviceInfo>) -> Result<BTreeMap<(u8, u8), DeviceInfo>> {
+    let mut out = BTreeMap::new();
+    for device in list_devices()?.iter() {
+        let location = (device.bus_number(), device.address());
+        let info = match known.get(&location) {
+            Some(x) => x.clone(),
+            None => DeviceInfo {
+                bus: device.bus_number(),
+                address: device.address(),
+                product_id: device.device_descriptor()?.product_id(),
+                serial_number: read_serial_number(device).ok(),
+            },
+        };
+        out.insert(location, info);
+    }
+    Ok(out)
+}
+
+impl Watch {
+    pub fn handle(self) -> Result<()> {
+        let mut known = BTreeMap::new();
+        loop {
+            let current = scan(&known)?;
+            let removed: Vec<_> = known
+                .iter()
+                .filter(|(k, _)| !current.contains_key(k))
+                .map(|(_, v)| v)
+                .collect();
+            let added: Vec<_> = current
+                .iter()
+                .filter(|(k, _)| !known.contains_key(k))
+                .map(|(_, v)| v)
+                .collect();
+
+            let mut switched = Vec::new();
+            for device in added.iter() {
+                let old = removed.iter().find(|x| {
+                    x.serial_number.is_some()
+                        && x.serial_number == device.serial_number
+                        && x.product_id != device.product_id
+                });
+                match old {
+                    Some(old) => {
+                        switched.push(old.serial_number.clone());
+                        print_event(&Event::Switched {
+                            device,
+                            old_product_id: old.product_id,
+                        })?;
+                    }
+                    None => print_event(&Event::Connected(device))?,
+                }
+            }
+            for device in removed {
+                if device.serial_number.is_none() || !switched.contains(&device.serial_number) {
+                    print_event(&Event::Disconnected(device))?;
+                }
+            }
+
+            known = current;
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl CliCommand for Watch {
+    fn handle(self, _: &mut dyn ProtocolAdapter) -> Result<()> {
+        unreachable!();
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{
+    messages::{self, FailureType},
+    transport::interrupt,
+};
+use thiserror::Error;
+
+/// Shown at the end of `--help`.
+pub const EXIT_CODES_HELP: &str = "EXIT CODES:
+    0     success
+    1     other error
+    2     invalid command line
+    3     no device found
+    4     device disconnected
+    5     timed out waiting for the device
+    6     malformed message from the device
+    7     other USB error
+    8     more than one device matches --device
+    9     error from the bridge
+    10    device reported a failure without a reason
+    11    device failure: unexpected message
+    12    device failure: button expected
+    13    device failure: syntax error
+    14    device failure: action cancelled
+    15    device failure: PIN expected
+    16    device failure: PIN cancelled
+    17    device failure: PIN invalid
+    18    device failure: invalid signature
+    19    device failure: other
+    20    device failure: not enough funds
+    21    device failure: not initialized
+    22    device failure: PIN mismatch
+    23    device failure: firmware error
+    130   interrupted by Ctrl-C (the operation is cancelled on the device first)";
+
+pub const EXIT_OTHER: i32 = 1;
+
+#[derive(Error, Debug)]
+pub enum DeviceError {
+    #[error("Failure: {message}")]
+    Failure {
+        code: Option<FailureType>,
+        message: String,
+    },
+    #[error("no device found")]
+    NoDevice,
+    #[error("device disconnected")]
+    Disconnected,
+    #[error("timed out waiting for device")]
+    Timeout,
+    #[error("malformed message from device ({0})")]
+    BadFraming(String),
+    #[error("USB error: {0}")]
+    Usb(rusb::Error),
+    #[error("bridge error: {0}")]
+    Bridge(String),
+    #[error("{0} devices match")]
+    Ambiguous(usize),
+    #[error("interrupted; cancelled the operation on the device")]
+    Interrupted,
+}
+
+impl From<messages::Failure> for DeviceError {
+    fn from(x: messages::Failure) -> Self {
+        Self::Failure {
+            code: x.code.and_then(FailureType::from_i32),
+            message: x.message.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<rusb::Error> for DeviceError {
+    fn from(x: rusb::Error) -> Self {
+        match x {
+            rusb::Error::Timeout => Self::Timeout,
+            rusb::Error::NoDevice => Self::Disconnected,
+            x => Self::Usb(x),
+        }
+    }
+}
+
+impl DeviceError {
+    /// Short machine-readable name for the kind of error, used in `--json` output.
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Failure { .. } => "failure",
+            Self::NoDevice => "noDevice",
+            Self::Disconnected => "disconnected",
+            Self::Timeout => "timeout",
+            Self::BadFraming(_) => "badFraming",
+            Self::Usb(_) => "usb",
+            Self::Ambiguous(_) => "ambiguous",
+            Self::Bridge(_) => "bridge",
+            Self::Interrupted => "interrupted",
+        }
+    }
+
+    /// Process exit code for this error; see `EXIT_CODES_HELP`.
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::NoDevice => 3,
+            Self::Disconnected => 4,
+            Self::Timeout => 5,
+            Self::BadFraming(_) => 6,
+            Self::Usb(_) => 7,
+            Self::Ambiguous(_) => 8,
+            Self::Bridge(_) => 9,
+            Self::Interrupted => interrupt::EXIT_INTERRUPTED,
+            Self::Failure { code: None, .. } => 10,
+            Self::Failure {
+                code: Some(code), ..
+            } => match code {
+                FailureType::FailureUnexpectedMessage => 11,
+                FailureType::FailureButtonExpected => 12,
+                FailureType::FailureSyntaxError => 13,
+                FailureType::FailureActionCancelled => 14,
+                FailureType::FailurePinExpected => 15,
+                FailureType::FailurePinCancelled => 16,
+                FailureType::FailurePinInvalid => 17,
+                FailureType::FailureInvalidSignature => 18,
+                FailureType::FailureOther => 19,
+                FailureType::FailureNotEnoughFunds => 20,
+                FailureType::FailureNotInitialized => 21,
+                FailureType::FailurePinMismatch => 22,
+                FailureType::FailureFirmwareError => 23,
+            },
+        }
+    }
+}
+
+/// Finds the `DeviceError` behind an error, if there is one.
+pub fn find_device_error(error: &anyhow::Error) -> Option<&DeviceError> {
+    error.chain().find_map(|x| x.downcast_ref())
+}
+
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    find_device_error(error).map_or(EXIT_OTHER, DeviceError::exit_code)
+}
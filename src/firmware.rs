@@ -0,0 +1,344 @@
+use anyhow::{bail, Result};
+use bitcoin::secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1};
+use core::fmt::{self, Display, Formatter};
+use sha2::{Digest, Sha256};
+
+pub const HEADER_LEN: usize = 256;
+pub const MAGIC: [u8; 4] = *b"KPKY";
+
+/// The bootloader only knows about this many signing keys; signature indexes are 1-based.
+const SIGNING_KEY_COUNT: u8 = 5;
+
+/// Firmware image as produced by KeepKey's signing tools: a 256-byte header followed by the code.
+///
+/// ```text
+/// magic (4) | code length (4, LE) | signature indexes (3) | flags (1) | reserved (52) | signatures (3 * 64)
+/// ```
+#[derive(Debug, Clone)]
+pub struct FirmwareImage {
+    pub magic: [u8; 4],
+    pub code_length: u32,
+    pub signature_indexes: [u8; 3],
+    pub flags: u8,
+    pub signatures: [[u8; 64]; 3],
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// index is zero, which is what unsigned images have
+    Missing,
+    /// index doesn't refer to one of the bootloader's signing keys
+    BadIndex(u8),
+    /// same index is used by an earlier signature slot
+    DuplicateIndex(u8),
+    /// no trusted keys were available to check the signature with
+    Unchecked(u8),
+    /// trusted keys were given, but not the one at this index
+    KeyNotSupplied(u8),
+    Valid(u8),
+    Invalid(u8),
+}
+
+impl Display for SignatureStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "missing"),
+            Self::BadIndex(x) => write!(f, "invalid key index ({})", x),
+            Self::DuplicateIndex(x) => write!(f, "duplicate key index ({})", x),
+            Self::Unchecked(x) => write!(f, "key #{} (not checked)", x),
+            Self::KeyNotSupplied(x) => write!(f, "key #{} (not checked; that key wasn't given)", x),
+            Self::Valid(x) => write!(f, "key #{} (valid)", x),
+            Self::Invalid(x) => write!(f, "key #{} (INVALID)", x),
+        }
+    }
+}
+
+impl FirmwareImage {
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        if data.len() < HEADER_LEN {
+            bail!(
+                "firmware image is too short to contain a header ({} bytes)",
+                data.len()
+            );
+        }
+        let mut signatures = [[0u8; 64]; 3];
+        for (i, x) in signatures.iter_mut().enumerate() {
+            let offset = 64 + i * 64;
+            x.copy_from_slice(&data[offset..(offset + 64)]);
+        }
+        Ok(Self {
+            magic: data[0..4].try_into().unwrap(),
+            code_length: u32::from_le_bytes(data[4..8].try_into().unwrap()),
+            signature_indexes: data[8..11].try_into().unwrap(),
+            flags: data[11],
+            signatures,
+            data,
+        })
+    }
+
+    /// The code covered by the header's signatures, truncated if the image is shorter than the header claims.
+    pub fn code(&self) -> &[u8] {
+        let end = HEADER_LEN.saturating_add(self.code_length.try_into().unwrap_or(usize::MAX));
+        &self.data[HEADER_LEN..self.data.len().min(end)]
+    }
+
+    /// SHA-256 of the code, which is what the signatures cover and what the device reports as its firmware hash.
+    pub fn firmware_hash(&self) -> [u8; 32] {
+        Sha256::digest(self.code()).into()
+    }
+
+    /// SHA-256 of the whole image, as sent along with the image in `FirmwareUpload`.
+    pub fn payload_hash(&self) -> [u8; 32] {
+        Sha256::digest(&self.data).into()
+    }
+
+    pub fn into_payload(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.signature_indexes.iter().any(|x| *x != 0)
+    }
+
+    /// Checks each signature slot against the key its index picks out of `signing_keys`, which are
+    /// in the bootloader's order, as the bootloader does. When `signing_keys` is empty, only the
+    /// indexes are checked.
+    pub fn signature_status(&self, signing_keys: &[PublicKey]) -> [SignatureStatus; 3] {
+        let secp = Secp256k1::verification_only();
+        let digest = Message::from_slice(&self.firmware_hash()).unwrap();
+        let mut out = [SignatureStatus::Missing; 3];
+        for (i, status) in out.iter_mut().enumerate() {
+            let index = self.signature_indexes[i];
+            *status = if index == 0 {
+                SignatureStatus::Missing
+            } else if index > SIGNING_KEY_COUNT {
+                SignatureStatus::BadIndex(index)
+            } else if self.signature_indexes[..i].contains(&index) {
+                SignatureStatus::DuplicateIndex(index)
+            } else if signing_keys.is_empty() {
+                SignatureStatus::Unchecked(index)
+            } else if let Some(key) = signing_keys.get(usize::from(index) - 1) {
+                let verified = Signature::from_compact(&self.signatures[i])
+                    .map(|mut x| {
+                        x.normalize_s();
+                        secp.verify_ecdsa(&digest, &x, key).is_ok()
+                    })
+                    .unwrap_or(false);
+                if verified {
+                    SignatureStatus::Valid(index)
+                } else {
+                    SignatureStatus::Invalid(index)
+                }
+            } else {
+                SignatureStatus::KeyNotSupplied(index)
+            };
+        }
+        out
+    }
+
+    /// Describes anything that makes this obviously not a usable KeepKey firmware image.
+    pub fn problems(&self, signing_keys: &[PublicKey]) -> Vec<String> {
+        let mut out = Vec::new();
+        if self.magic != MAGIC {
+            out.push(match &self.magic {
+                b"TRZR" | b"TRZF" | b"TRZV" => {
+                    "this is a Trezor firmware image, not a KeepKey one".to_owned()
+                }
+                x => format!(
+                    "bad magic bytes ({}); this is not a KeepKey firmware image",
+                    hex::encode(x)
+                ),
+            });
+            return out;
+        }
+        let actual_code_length = self.data.len() - HEADER_LEN;
+        if usize::try_from(self.code_length) != Ok(actual_code_length) {
+            out.push(format!(
+                "header says the code is {} bytes long, but the image contains {} bytes of code",
+                self.code_length, actual_code_length
+            ));
+        }
+        for (i, status) in self.signature_status(signing_keys).iter().enumerate() {
+            match status {
+                SignatureStatus::BadIndex(_)
+                | SignatureStatus::DuplicateIndex(_)
+                | SignatureStatus::Invalid(_) => {
+                    out.push(format!("signature {}: {}", i + 1, status))
+                }
+                _ => (),
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::SecretKey;
+
+    fn signing_key(x: u8) -> SecretKey {
+        SecretKey::from_slice(&[x; 32]).unwrap()
+    }
+
+    /// An image with `code`, signed in order by `signers` as (index, key).
+    fn image(code: &[u8], signers: &[(u8, SecretKey)]) -> Vec<u8> {
+        let mut out = vec![0; HEADER_LEN];
+        out[0..4].copy_from_slice(&MAGIC);
+        out[4..8].copy_from_slice(&(code.len() as u32).to_le_bytes());
+        let secp = Secp256k1::new();
+        let digest = Message::from_slice(&Sha256::digest(code)).unwrap();
+        for (i, (index, key)) in signers.iter().enumerate() {
+            out[8 + i] = *index;
+            let signature = secp.sign_ecdsa(&digest, key).serialize_compact();
+            out[64 + i * 64..][..64].copy_from_slice(&signature);
+        }
+        out.extend_from_slice(code);
+        out
+    }
+
+    fn public_key(x: u8) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::new(), &signing_key(x))
+    }
+
+    #[test]
+    fn parses_valid_image() {
+        let signers = [
+            (1, signing_key(1)),
+            (2, signing_key(2)),
+            (3, signing_key(3)),
+        ];
+        let image = FirmwareImage::parse(image(b"code", &signers)).unwrap();
+        assert_eq!(image.magic, MAGIC);
+        assert_eq!(image.code_length, 4);
+        assert_eq!(image.signature_indexes, [1, 2, 3]);
+        assert_eq!(image.code(), b"code");
+        assert_eq!(
+            image.firmware_hash(),
+            <[u8; 32]>::from(Sha256::digest(b"code"))
+        );
+        assert!(image.is_signed());
+
+        let keys = [public_key(1), public_key(2), public_key(3)];
+        assert_eq!(
+            image.signature_status(&keys),
+            [
+                SignatureStatus::Valid(1),
+                SignatureStatus::Valid(2),
+                SignatureStatus::Valid(3)
+            ]
+        );
+        assert!(image.problems(&keys).is_empty());
+        assert_eq!(
+            image.signature_status(&[]),
+            [
+                SignatureStatus::Unchecked(1),
+                SignatureStatus::Unchecked(2),
+                SignatureStatus::Unchecked(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_untrusted_signature() {
+        let image = FirmwareImage::parse(image(b"code", &[(1, signing_key(1))])).unwrap();
+        assert_eq!(
+            image.signature_status(&[public_key(2)]),
+            [
+                SignatureStatus::Invalid(1),
+                SignatureStatus::Missing,
+                SignatureStatus::Missing
+            ]
+        );
+        assert_eq!(image.problems(&[public_key(2)]).len(), 1);
+    }
+
+    #[test]
+    fn rejects_short_image() {
+        assert!(FirmwareImage::parse(vec![0; HEADER_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn reports_bad_magic() {
+        let mut data = image(b"code", &[]);
+        data[0..4].copy_from_slice(b"TRZR");
+        let problems = FirmwareImage::parse(data).unwrap().problems(&[]);
+        assert_eq!(
+            problems,
+            ["this is a Trezor firmware image, not a KeepKey one"]
+        );
+
+        let mut data = image(b"code", &[]);
+        data[0] = 0;
+        let problems = FirmwareImage::parse(data).unwrap().problems(&[]);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("bad magic bytes"));
+    }
+
+    #[test]
+    fn reports_bad_length() {
+        let mut data = image(b"code", &[]);
+        data.pop();
+        let image = FirmwareImage::parse(data).unwrap();
+        assert_eq!(image.code(), b"cod");
+        assert_eq!(
+            image.problems(&[]),
+            ["header says the code is 4 bytes long, but the image contains 3 bytes of code"]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_signature_indexes() {
+        let signers = [
+            (2, signing_key(1)),
+            (2, signing_key(1)),
+            (6, signing_key(1)),
+        ];
+        let image = FirmwareImage::parse(image(b"code", &signers)).unwrap();
+        let keys = [public_key(9), public_key(1)];
+        assert_eq!(
+            image.signature_status(&keys),
+            [
+                SignatureStatus::Valid(2),
+                SignatureStatus::DuplicateIndex(2),
+                SignatureStatus::BadIndex(6)
+            ]
+        );
+        assert_eq!(
+            image.problems(&keys),
+            [
+                "signature 2: duplicate key index (2)",
+                "signature 3: invalid key index (6)"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_each_slot_against_its_own_key() {
+        let signers = [
+            (1, signing_key(1)),
+            (2, signing_key(1)),
+            (3, signing_key(1)),
+        ];
+        let image = FirmwareImage::parse(image(b"code", &signers)).unwrap();
+        let keys = [public_key(1), public_key(2), public_key(3)];
+        assert_eq!(
+            image.signature_status(&keys),
+            [
+                SignatureStatus::Valid(1),
+                SignatureStatus::Invalid(2),
+                SignatureStatus::Invalid(3)
+            ]
+        );
+        assert_eq!(image.problems(&keys).len(), 2);
+        assert_eq!(
+            image.signature_status(&keys[..1]),
+            [
+                SignatureStatus::Valid(1),
+                SignatureStatus::KeyNotSupplied(2),
+                SignatureStatus::KeyNotSupplied(3)
+            ]
+        );
+    }
+}
@@ -1,77 +1,41 @@
 pub mod cli;
+pub mod error;
+pub mod firmware;
 pub mod messages;
+pub mod releases;
 pub mod transport;
 
 use crate::{
-    cli::{Cli, CliDebugCommand, Subcommand},
-    transport::{ProtocolAdapter, UsbTransport},
+    cli::{output, types::TransportType, Cli, CliDebugCommand},
+    transport::{
+        select_device, wait_for_device, BridgeTransport, ProtocolAdapter, UsbTransport,
+        DEFAULT_BRIDGE_URL,
+    },
 };
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
 use clap::Parser;
-use rusb::{Device, GlobalContext};
-use std::panic;
+use std::{ffi::OsString, path::Path};
 
-const DEVICE_IDS: &[(u16, u16)] = &[(0x2b24, 0x0001), (0x2b24, 0x0002)];
-
-fn list_devices() -> Box<[Device<GlobalContext>]> {
-    rusb::devices()
-        .unwrap()
-        .iter()
-        .filter(|device| {
-            let device_desc = device.device_descriptor().unwrap();
-            DEVICE_IDS.contains(&(device_desc.vendor_id(), device_desc.product_id()))
-        })
-        .collect()
-}
-
-fn get_device() -> Result<Device<GlobalContext>> {
-    Ok(list_devices()
-        .iter()
-        .next()
-        .ok_or_else(|| anyhow!("no device found"))?
-        .to_owned())
-}
-
-fn main() -> Result<()> {
-    // clap needs too much memory to parse things for some reason -- something
-    // to do with the code generated by its derive macros I think -- and it's
-    // easier to just give it an extra couple of megs than to fix the problem.
-    let cli = std::thread::Builder::new()
-        .stack_size(4 * 1024 * 1024)
-        .spawn(Cli::parse)
-        .unwrap()
-        .join();
-    let cli = match cli {
-        Ok(x) => x,
-        Err(x) => panic::resume_unwind(x),
+fn run(mut cli: Cli) -> Result<()> {
+    cli.command = match cli.command.handle_without_device()? {
+        Some(x) => x,
+        None => return Ok(()),
     };
 
-    match cli.command {
-        Subcommand::List(_) => {
-            for device in list_devices().iter() {
-                let device_desc = device.device_descriptor()?;
-                let device_handle = device.open()?;
-                println!(
-                    "Bus {:03} Device {:03} ID {:04x}:{:04x}\t\"{}\"\t({})",
-                    device.bus_number(),
-                    device.address(),
-                    device_desc.vendor_id(),
-                    device_desc.product_id(),
-                    device_handle.read_product_string_ascii(&device_desc)?,
-                    device_handle.read_serial_number_string_ascii(&device_desc)?,
-                );
-            }
-            return Ok(());
+    if cli.transport == TransportType::Bridge {
+        if cli.wait.is_some() {
+            bail!("--wait only works with the USB transport");
         }
-        Subcommand::Decode(x) => {
-            x.handle()?;
-            return Ok(());
-        }
-        _ => (),
+        let url = cli.path.as_deref().unwrap_or(DEFAULT_BRIDGE_URL);
+        let mut transport = BridgeTransport::open(url, cli.device.as_ref())?;
+        return cli.handle_debug(&mut transport, None);
     }
-    *transport::protocol_adapter::VERBOSE.write().unwrap() = cli.verbose;
 
-    let device = get_device()?;
+    let device = match cli.wait {
+        Some(timeout) => wait_for_device(cli.device.as_ref(), timeout.0)?,
+        None => select_device(cli.device.as_ref())?,
+    };
+    *transport::SELECTED_DEVICE.write().unwrap() = Some((device.bus_number(), device.address()));
     let (mut transport, config_descriptor, handle) = UsbTransport::new(&device, 0)?;
     let mut debug_transport =
         UsbTransport::new_from_descriptor_and_handle(&config_descriptor, handle, 1).ok();
@@ -83,3 +47,38 @@ fn main() -> Result<()> {
             .map(|x| -> &mut dyn ProtocolAdapter { x }),
     )
 }
+
+/// age runs its plugins by name, so kkcli linked as age-plugin-keepkey acts as that subcommand.
+fn args() -> Vec<OsString> {
+    let mut args = std::env::args_os().collect::<Vec<_>>();
+    if args.first().map(Path::new).and_then(Path::file_stem) == Some("age-plugin-keepkey".as_ref())
+    {
+        args.splice(..1, ["kkcli".into(), "age-plugin-keepkey".into()]);
+    }
+    args
+}
+
+fn main() {
+    let cli = cli::with_parser_stack(|| Cli::parse_from(args()));
+    *transport::protocol_adapter::VERBOSE.write().unwrap() = cli.verbose;
+    *output::JSON.write().unwrap() = cli.json;
+    transport::interrupt::install_handler().unwrap();
+    *transport::PIN_SOURCE.write().unwrap() = cli.pin_source.clone();
+    *transport::PASSPHRASE_SOURCE.write().unwrap() = cli.passphrase_source.clone();
+
+    match run(cli) {
+        Ok(()) => {
+            if output::is_json() {
+                output::print_result();
+            }
+        }
+        Err(x) => {
+            if output::is_json() {
+                output::print_error(&x);
+            } else {
+                eprintln!("Error: {:?}", x);
+            }
+            std::process::exit(error::exit_code(&x));
+        }
+    }
+}
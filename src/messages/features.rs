@@ -0,0 +1,12 @@
+use super::Features;
+
+impl Features {
+    /// The firmware version, or the bootloader's in bootloader mode, with ? for missing parts.
+    pub fn version(&self) -> String {
+        [self.major_version, self.minor_version, self.patch_version]
+            .iter()
+            .map(|x| x.map_or_else(|| "?".to_owned(), |x| x.to_string()))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
@@ -1,6 +1,6 @@
 macro_rules! kk_message {
     ($($x:ident),*) => {
-        #[derive(Debug, Clone)]
+        #[derive(Debug, Clone, ::serde::Serialize)]
         pub enum Message {
             $($x(protos::$x)),*
         }
@@ -1,4 +1,5 @@
 mod encoding;
+mod features;
 mod macros;
 mod protos;
 mod timeouts;
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use core::fmt::{self, Display, Formatter};
+use serde::{Deserialize, Serialize};
+use serde_with::{hex::Hex, serde_as};
+
+/// Manifest compiled into the binary; pass `--manifest` to commands that use it to check against a newer one.
+/// It only lists bootloaders so far, so firmware hashes are only identified with `--manifest`.
+const BUNDLED_MANIFEST: &str = include_str!("releases.json");
+
+/// List of known official bootloader and firmware builds, identified by the hashes the device reports in `Features`,
+/// and the public keys official firmware is signed with, in the bootloader's order (signature index 1 is the first).
+///
+/// ```json
+/// {
+///   "bootloaders": [{ "hash": "<hex>", "version": "1.0.0", "vulnerable": "why not to use it" }],
+///   "firmware": [{ "hash": "<hex>", "version": "7.0.0" }],
+///   "signingKeys": ["<hex SEC1 public key>"]
+/// }
+/// ```
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct Manifest {
+    pub bootloaders: Vec<Release>,
+    pub firmware: Vec<Release>,
+    #[serde_as(as = "Vec<Hex>")]
+    #[serde(default)]
+    pub signing_keys: Vec<Vec<u8>>,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Release {
+    #[serde_as(as = "Hex")]
+    pub hash: [u8; 32],
+    pub version: String,
+    /// reason the release shouldn't be trusted, if it has known security problems
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vulnerable: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum ReleaseStatus<'a> {
+    Known(&'a Release),
+    Vulnerable(&'a Release),
+    Unknown,
+}
+
+impl Display for ReleaseStatus<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Known(x) => write!(f, "known release v{}", x.version),
+            Self::Vulnerable(x) => write!(
+                f,
+                "KNOWN VULNERABLE release v{}: {}",
+                x.version,
+                x.vulnerable.as_deref().unwrap_or_default()
+            ),
+            Self::Unknown => write!(f, "unknown; not in the release manifest"),
+        }
+    }
+}
+
+impl<'a> ReleaseStatus<'a> {
+    /// The release the hash belongs to, if it's in the manifest.
+    pub fn release(&self) -> Option<&'a Release> {
+        match self {
+            Self::Known(x) | Self::Vulnerable(x) => Some(x),
+            Self::Unknown => None,
+        }
+    }
+}
+
+fn lookup<'a>(releases: &'a [Release], hash: &[u8]) -> ReleaseStatus<'a> {
+    match releases.iter().find(|x| x.hash[..] == *hash) {
+        Some(x) if x.vulnerable.is_some() => ReleaseStatus::Vulnerable(x),
+        Some(x) => ReleaseStatus::Known(x),
+        None => ReleaseStatus::Unknown,
+    }
+}
+
+impl Manifest {
+    fn bundled() -> Self {
+        serde_json::from_str(BUNDLED_MANIFEST).expect("bundled release manifest is invalid")
+    }
+
+    /// Loads the manifest at `path`, or the bundled one if there isn't one.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let path = match path {
+            Some(x) => x,
+            None => return Ok(Self::bundled()),
+        };
+        serde_json::from_slice(&std::fs::read(path)?)
+            .with_context(|| format!("invalid release manifest ({})", path))
+    }
+
+    pub fn bootloader_status(&self, hash: &[u8]) -> ReleaseStatus<'_> {
+        lookup(&self.bootloaders, hash)
+    }
+
+    pub fn firmware_status(&self, hash: &[u8]) -> ReleaseStatus<'_> {
+        lookup(&self.firmware, hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> Manifest {
+        serde_json::from_str(
+            r#"{
+                "bootloaders": [
+                    { "hash": "1111111111111111111111111111111111111111111111111111111111111111", "version": "1.0.0", "vulnerable": "old" },
+                    { "hash": "2222222222222222222222222222222222222222222222222222222222222222", "version": "2.0.0" }
+                ],
+                "firmware": [
+                    { "hash": "3333333333333333333333333333333333333333333333333333333333333333", "version": "7.0.0" }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_bundled_manifest() {
+        let manifest = Manifest::bundled();
+        assert!(!manifest.bootloaders.is_empty());
+        let mut hashes = manifest
+            .bootloaders
+            .iter()
+            .chain(&manifest.firmware)
+            .map(|x| x.hash)
+            .collect::<Vec<_>>();
+        let len = hashes.len();
+        hashes.sort_unstable();
+        hashes.dedup();
+        assert_eq!(hashes.len(), len, "a hash is listed twice");
+    }
+
+    #[test]
+    fn looks_up_releases() {
+        let manifest = manifest();
+        assert!(matches!(
+            manifest.bootloader_status(&[0x22; 32]),
+            ReleaseStatus::Known(Release { version, .. }) if version == "2.0.0"
+        ));
+        assert!(matches!(
+            manifest.bootloader_status(&[0x11; 32]),
+            ReleaseStatus::Vulnerable(Release { version, .. }) if version == "1.0.0"
+        ));
+        assert!(matches!(
+            manifest.bootloader_status(&[0x33; 32]),
+            ReleaseStatus::Unknown
+        ));
+        assert!(matches!(
+            manifest.firmware_status(&[0x33; 32]),
+            ReleaseStatus::Known(_)
+        ));
+        assert_eq!(
+            manifest.bootloader_status(&[0x11; 32]).to_string(),
+            "KNOWN VULNERABLE release v1.0.0: old"
+        );
+        assert_eq!(
+            serde_json::to_value(manifest.firmware_status(&[0x44; 32])).unwrap(),
+            serde_json::json!({ "status": "unknown" })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(serde_json::from_str::<Manifest>(
+            r#"{ "bootloaders": [], "firmware": [], "firmwares": [] }"#
+        )
+        .is_err());
+    }
+}
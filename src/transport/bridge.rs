@@ -0,0 +1,447 @@
+use super::{interrupt, DeviceSelector, Transport};
+use crate::error::DeviceError;
+use anyhow::{bail, Context};
+use core::{fmt::Display, time::Duration};
+use serde::Deserialize;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::TcpStream,
+    time::Instant,
+};
+use url::{Position, Url};
+
+pub const DEFAULT_BRIDGE_URL: &str = "http://127.0.0.1:1646/";
+/// For requests that don't wait on the device.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BridgeDevice {
+    pub path: String,
+    pub session: Option<String>,
+}
+
+/// Talks to a device through a KeepKey Bridge-style HTTP server, like the one `kkcli bridge` runs.
+pub struct BridgeTransport {
+    url: Url,
+    session: String,
+}
+
+fn bridge_error(x: impl Display) -> DeviceError {
+    DeviceError::Bridge(x.to_string())
+}
+
+fn io_error(x: io::Error) -> DeviceError {
+    match x.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => DeviceError::Timeout,
+        _ => bridge_error(x),
+    }
+}
+
+fn read_body(reader: &mut impl BufRead, headers: &[(String, String)]) -> io::Result<Vec<u8>> {
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, x)| x.as_str())
+    };
+    let mut body = Vec::new();
+    if matches!(header("transfer-encoding"), Some(x) if x.eq_ignore_ascii_case("chunked")) {
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let size = line.trim().split(';').next().unwrap_or_default();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|x| io::Error::new(io::ErrorKind::InvalidData, x))?;
+            if size == 0 {
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            reader.read_line(&mut line)?;
+        }
+    } else if let Some(len) = header("content-length") {
+        let len = len
+            .parse()
+            .map_err(|x| io::Error::new(io::ErrorKind::InvalidData, x))?;
+        body.resize(len, 0);
+        reader.read_exact(&mut body)?;
+    } else {
+        reader.read_to_end(&mut body)?;
+    }
+    Ok(body)
+}
+
+/// Waits for the start of the response a little at a time, so that Ctrl-C can interrupt us.
+fn wait_for_response(
+    reader: &mut BufReader<TcpStream>,
+    timeout: Duration,
+) -> Result<(), DeviceError> {
+    let started = Instant::now();
+    loop {
+        let remaining = timeout
+            .checked_sub(started.elapsed())
+            .filter(|x| !x.is_zero())
+            .ok_or(DeviceError::Timeout)?;
+        reader
+            .get_ref()
+            .set_read_timeout(Some(remaining.min(interrupt::POLL_INTERVAL)))
+            .map_err(io_error)?;
+        match reader.fill_buf().map_err(io_error) {
+            Err(DeviceError::Timeout) if interrupt::is_interrupted() => {
+                return Err(DeviceError::Interrupted)
+            }
+            Err(DeviceError::Timeout) => (),
+            x => break x.map(|_| ())?,
+        }
+    }
+    reader
+        .get_ref()
+        .set_read_timeout(Some(timeout))
+        .map_err(io_error)
+}
+
+fn post(url: &Url, endpoint: &str, body: &str, timeout: Duration) -> Result<String, DeviceError> {
+    request(url, endpoint, body, timeout, false)
+}
+
+/// Like `post()`, but gives up when interrupted instead of waiting out `timeout`.
+fn post_interruptible(
+    url: &Url,
+    endpoint: &str,
+    body: &str,
+    timeout: Duration,
+) -> Result<String, DeviceError> {
+    request(url, endpoint, body, timeout, true)
+}
+
+fn request(
+    url: &Url,
+    endpoint: &str,
+    body: &str,
+    timeout: Duration,
+    interruptible: bool,
+) -> Result<String, DeviceError> {
+    let url = url.join(endpoint).map_err(bridge_error)?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| bridge_error("bridge URL has no host"))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let mut stream = TcpStream::connect((host, port))
+        .map_err(|x| bridge_error(format!("couldn't connect to {}: {}", url, x)))?;
+    stream.set_read_timeout(Some(timeout)).map_err(io_error)?;
+    stream.set_write_timeout(Some(timeout)).map_err(io_error)?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        &url[Position::BeforePath..Position::AfterQuery],
+        &url[Position::BeforeHost..Position::AfterPort],
+        body.len(),
+        body
+    )
+    .map_err(io_error)?;
+
+    let mut reader = BufReader::new(stream);
+    if interruptible {
+        wait_for_response(&mut reader, timeout)?;
+    }
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).map_err(io_error)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|x| x.parse::<u16>().ok())
+        .ok_or_else(|| bridge_error(format!("bad response from bridge: {:?}", status_line)))?;
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(io_error)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+        }
+    }
+    let body = String::from_utf8(read_body(&mut reader, &headers).map_err(io_error)?)
+        .map_err(bridge_error)?;
+
+    if status != 200 {
+        #[derive(Deserialize)]
+        struct ErrorBody {
+            error: String,
+        }
+        let message = serde_json::from_str::<ErrorBody>(&body).map_or(body, |x| x.error);
+        return Err(bridge_error(format!("{} ({})", message, status)));
+    }
+    Ok(body)
+}
+
+impl BridgeTransport {
+    fn parse_url(url: &str) -> Result<Url, DeviceError> {
+        let mut url = Url::parse(url).map_err(bridge_error)?;
+        if url.scheme() != "http" {
+            return Err(bridge_error("only http:// bridge URLs are supported"));
+        }
+        // So that endpoints are joined onto the path instead of replacing its last part.
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        Ok(url)
+    }
+
+    pub fn enumerate(url: &str) -> Result<Vec<BridgeDevice>, DeviceError> {
+        let body = post(&Self::parse_url(url)?, "enumerate", "", REQUEST_TIMEOUT)?;
+        serde_json::from_str(&body).map_err(bridge_error)
+    }
+
+    /// Acquires the device at the path `selector` names, or the first one, taking it over from
+    /// whoever had it.
+    pub fn open(url: &str, selector: Option<&DeviceSelector>) -> anyhow::Result<Self> {
+        let devices = Self::enumerate(url)?;
+        let device = match selector {
+            None => devices.first().ok_or(DeviceError::NoDevice)?,
+            Some(x @ (DeviceSelector::Location(..) | DeviceSelector::Any(_))) => {
+                let path = x.to_string();
+                devices
+                    .iter()
+                    .find(|x| x.path == path)
+                    .ok_or(DeviceError::NoDevice)
+                    .with_context(|| format!("the bridge has no device at {}", path))?
+            }
+            Some(_) => bail!("devices behind a bridge can only be selected by path"),
+        };
+
+        #[derive(Deserialize)]
+        struct Acquired {
+            session: String,
+        }
+        let url = Self::parse_url(url)?;
+        let body = post(
+            &url,
+            &format!(
+                "acquire/{}/{}",
+                device.path,
+                device.session.as_deref().unwrap_or("null")
+            ),
+            "",
+            REQUEST_TIMEOUT,
+        )?;
+        let session = serde_json::from_str::<Acquired>(&body)
+            .map_err(bridge_error)?
+            .session;
+        Ok(Self { url, session })
+    }
+}
+
+impl Drop for BridgeTransport {
+    fn drop(&mut self) {
+        post(
+            &self.url,
+            &format!("release/{}", self.session),
+            "",
+            REQUEST_TIMEOUT,
+        )
+        .ok();
+    }
+}
+
+/// The bridge's messages are ours without the leading "##".
+impl Transport for BridgeTransport {
+    type Error = DeviceError;
+    fn write(&mut self, msg: &[u8], timeout: Duration) -> Result<usize, Self::Error> {
+        let body = hex::encode(msg.strip_prefix(b"##").unwrap_or(msg));
+        post(&self.url, &format!("post/{}", self.session), &body, timeout)?;
+        Ok(msg.len())
+    }
+    fn read(&mut self, buf: &mut Vec<u8>, timeout: Duration) -> Result<(), Self::Error> {
+        let body = post_interruptible(&self.url, &format!("read/{}", self.session), "", timeout)?;
+        buf.extend_from_slice(b"##");
+        buf.extend(hex::decode(body.trim()).map_err(bridge_error)?);
+        Ok(())
+    }
+    fn reset(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        messages::{self, Message},
+        transport::ProtocolAdapter,
+    };
+    use serde_json::json;
+    use std::{
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    /// Answers Pings the way a device would, and otherwise says what it's been asked.
+    #[derive(Default)]
+    struct StandIn {
+        session: Option<String>,
+        pending: Option<Vec<u8>>,
+        requests: Vec<String>,
+    }
+
+    impl StandIn {
+        /// Like the device's reply to `body`, in the bridge's hex format.
+        fn reply(body: &str) -> String {
+            let mut msg = b"##".to_vec();
+            msg.extend(hex::decode(body.trim()).unwrap());
+            let reply: Message = match Message::decode(&mut &*msg).unwrap() {
+                Message::Ping(x) => messages::Success { message: x.message }.into(),
+                x => panic!("unexpected message {:?}", x),
+            };
+            let mut out = Vec::new();
+            reply.encode(&mut out).unwrap();
+            hex::encode(&out[2..])
+        }
+
+        fn route(&mut self, path: &str, body: &str) -> (u16, String) {
+            self.requests.push(path.to_owned());
+            let session = self.session.clone().unwrap_or_default();
+            match path.trim_matches('/').split('/').collect::<Vec<_>>()[..] {
+                ["enumerate"] => (
+                    200,
+                    json!([{ "path": "001:004", "session": self.session }]).to_string(),
+                ),
+                ["acquire", "001:004", _] => {
+                    self.session = Some("1".to_owned());
+                    (200, json!({ "session": "1" }).to_string())
+                }
+                ["post", x] if x == session => {
+                    self.pending = Some(hex::decode(Self::reply(body)).unwrap());
+                    (200, "{}".to_owned())
+                }
+                ["read", x] if x == session => match self.pending.take() {
+                    Some(x) => (200, hex::encode(x)),
+                    None => (400, json!({ "error": "nothing to read" }).to_string()),
+                },
+                ["release", x] if x == session => {
+                    self.session = None;
+                    (200, "{}".to_owned())
+                }
+                _ => (400, json!({ "error": "bad request" }).to_string()),
+            }
+        }
+    }
+
+    fn serve(stand_in: Arc<Mutex<StandIn>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line.split_whitespace().nth(1).unwrap().to_owned();
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((name, value)) => headers
+                            .push((name.trim().to_ascii_lowercase(), value.trim().to_owned())),
+                        None => break,
+                    }
+                }
+                let body = read_body(&mut reader, &headers).unwrap();
+                let (status, body) = stand_in
+                    .lock()
+                    .unwrap()
+                    .route(&path, std::str::from_utf8(&body).unwrap());
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    fn ping(protocol_adapter: &mut dyn ProtocolAdapter, message: &str) -> Message {
+        protocol_adapter
+            .handle(
+                messages::Ping {
+                    message: Some(message.to_owned()),
+                    ..Default::default()
+                }
+                .into(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_bridge() {
+        let stand_in = Arc::new(Mutex::new(StandIn::default()));
+        let url = serve(stand_in.clone());
+
+        let devices = BridgeTransport::enumerate(&url).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].path, "001:004");
+        assert_eq!(devices[0].session, None);
+
+        let mut transport = BridgeTransport::open(&url, None).unwrap();
+        for message in ["hello", ""] {
+            match ping(&mut transport, message) {
+                Message::Success(x) => assert_eq!(x.message.as_deref(), Some(message)),
+                x => panic!("unexpected response {:?}", x),
+            }
+        }
+        drop(transport);
+        // Dropping the transport releases the session, so the device can be opened again.
+        assert_eq!(stand_in.lock().unwrap().session, None);
+        let mut transport = BridgeTransport::open(&url, None).unwrap();
+        assert!(matches!(ping(&mut transport, "again"), Message::Success(_)));
+        drop(transport);
+
+        assert_eq!(
+            stand_in.lock().unwrap().requests,
+            [
+                "/enumerate",
+                "/enumerate",
+                "/acquire/001:004/null",
+                "/post/1",
+                "/read/1",
+                "/post/1",
+                "/read/1",
+                "/release/1",
+                "/enumerate",
+                "/acquire/001:004/null",
+                "/post/1",
+                "/read/1",
+                "/release/1",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_bridge_errors() {
+        let url = serve(Arc::new(Mutex::new(StandIn::default())));
+        let e = BridgeTransport::open(&url, Some(&"002:001".parse().unwrap()))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            crate::error::find_device_error(&e),
+            Some(DeviceError::NoDevice)
+        ));
+
+        let mut transport = BridgeTransport::open(&url, None).unwrap();
+        let e = transport
+            .read(&mut Vec::new(), REQUEST_TIMEOUT)
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            DeviceError::Bridge("nothing to read (400)".to_owned()).to_string()
+        );
+    }
+}
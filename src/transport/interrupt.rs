@@ -0,0 +1,63 @@
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// Set while we're waiting on the device, when Ctrl-C should cancel the operation instead of killing us.
+static WAITING: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// How often transports waiting on the device check whether they've been interrupted.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Exit status for being killed by SIGINT, by the usual shell convention.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Makes Ctrl-C set a flag while we're waiting on the device, so the operation can be cancelled
+/// on the device before exiting. At any other time, or if it's pressed twice, Ctrl-C exits right away.
+#[cfg(unix)]
+pub fn install_handler() -> std::io::Result<()> {
+    use signal_hook::{consts::SIGINT, low_level};
+    // Safety: the handler only touches atomics and calls _exit, which are async-signal-safe.
+    unsafe {
+        low_level::register(SIGINT, || {
+            if WAITING.load(Ordering::SeqCst) && !INTERRUPTED.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            low_level::exit(EXIT_INTERRUPTED);
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install_handler() -> std::io::Result<()> {
+    Ok(())
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Forgets an interruption that's been dealt with, so that the next wait isn't cut short by it.
+/// Ctrl-C still only sets the flag again, rather than exiting, while we're waiting.
+pub(crate) fn clear_interrupted() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Marks that we're waiting on the device for as long as it's alive.
+pub(crate) struct WaitGuard(());
+
+impl WaitGuard {
+    pub(crate) fn new() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        WAITING.store(true, Ordering::SeqCst);
+        Self(())
+    }
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        WAITING.store(false, Ordering::SeqCst);
+    }
+}
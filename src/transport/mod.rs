@@ -1,10 +1,21 @@
+pub mod bridge;
+pub mod interrupt;
+pub mod pin_matrix;
 pub mod protocol_adapter;
+pub mod secret_source;
+pub mod select;
 pub mod usb;
 
+pub use bridge::*;
 pub use protocol_adapter::*;
+pub use secret_source::*;
+pub use select::*;
 pub use usb::*;
 
-use crate::messages::{self, Message};
+use crate::{
+    error::DeviceError,
+    messages::{self, Message},
+};
 use anyhow::{anyhow, bail, Result};
 use core::time::Duration;
 use std::io::{stdin, stdout, Write};
@@ -12,6 +23,17 @@ use std::io::{stdin, stdout, Write};
 pub trait Transport {
     type Error: std::error::Error;
     fn write(&mut self, msg: &[u8], timeout: Duration) -> Result<usize, Self::Error>;
+    /// Like `write()`, but calls `progress` with the number of bytes written so far and the total as the write proceeds.
+    fn write_with_progress(
+        &mut self,
+        msg: &[u8],
+        timeout: Duration,
+        progress: &mut ProgressHandler,
+    ) -> Result<usize, Self::Error> {
+        let len = self.write(msg, timeout)?;
+        progress(len, msg.len());
+        Ok(len)
+    }
     fn read(&mut self, buf: &mut Vec<u8>, timeout: Duration) -> Result<(), Self::Error>;
     fn reset(&mut self) -> Result<(), Self::Error>;
 }
@@ -23,38 +45,49 @@ pub fn standard_message_handler(msg: &Message) -> Result<Option<Message>> {
             Some(messages::ButtonAck::default().into())
         }
         Message::PinMatrixRequest(x) => {
-            match x.r#type {
-                Some(t) => match messages::PinMatrixRequestType::from_i32(t)
-                    .ok_or_else(|| anyhow!("unrecognized PinMatrixRequestType ({})", t))?
-                {
-                    messages::PinMatrixRequestType::Current => {
-                        eprint!("Enter current PIN: ")
-                    }
-                    messages::PinMatrixRequestType::NewFirst => eprint!("Enter new PIN: "),
-                    messages::PinMatrixRequestType::NewSecond => {
-                        eprint!("Re-enter new PIN: ")
+            let request_type = match x.r#type {
+                Some(t) => messages::PinMatrixRequestType::from_i32(t)
+                    .ok_or_else(|| anyhow!("unrecognized PinMatrixRequestType ({})", t))?,
+                None => bail!("expected PinMatrixRequestType"),
+            };
+            let prompt = format!("{}: ", pin_matrix::prompt_for(request_type));
+            let pin = match PIN_SOURCE.read().unwrap().read(
+                "Enter the positions of your PIN digits in the matrix shown on your KeepKey.",
+                &prompt,
+            )? {
+                Some(x) => x,
+                None => match pin_matrix::read_pin(request_type)? {
+                    pin_matrix::PinEntry::Pin(x) => x,
+                    // The device answers with a Failure, which ends up as the error.
+                    pin_matrix::PinEntry::Cancelled => return Ok(Some(messages::Cancel {}.into())),
+                    pin_matrix::PinEntry::NoTerminal => {
+                        eprint!("{}", prompt);
+                        stdout().flush().unwrap();
+                        let mut pin = String::new();
+                        stdin().read_line(&mut pin)?;
+                        pin.trim().to_owned()
                     }
                 },
-                None => bail!("expected PinMatrixRequestType"),
-            }
-            stdout().flush().unwrap();
-            let mut pin = String::new();
-            stdin().read_line(&mut pin)?;
-            let pin = pin.trim();
-            Some(
-                messages::PinMatrixAck {
-                    pin: pin.to_owned(),
-                }
-                .into(),
-            )
+            };
+            Some(messages::PinMatrixAck { pin }.into())
         }
         Message::PassphraseRequest(_) => {
-            eprint!("Enter BIP-39 passphrase: ");
-            stdout().flush().unwrap();
-            let passphrase = passterm::read_password()?;
+            let prompt = "Enter BIP-39 passphrase: ";
+            let passphrase = match PASSPHRASE_SOURCE
+                .read()
+                .unwrap()
+                .read("Enter the BIP-39 passphrase for your KeepKey.", prompt)?
+            {
+                Some(x) => x,
+                None => {
+                    eprint!("{}", prompt);
+                    stdout().flush().unwrap();
+                    passterm::read_password()?
+                }
+            };
             Some(messages::PassphraseAck { passphrase }.into())
         }
-        Message::Failure(x) => bail!("Failure: {}", x.message()),
+        Message::Failure(x) => return Err(DeviceError::from(x.clone()).into()),
         _ => None,
     })
 }
@@ -62,7 +95,14 @@ pub fn standard_message_handler(msg: &Message) -> Result<Option<Message>> {
 pub trait ProtocolAdapter {
     fn reset(&mut self) -> Result<()>;
     fn send(&mut self, msg: Message) -> Result<()>;
-    fn handle(&mut self, msg: Message) -> Result<Message>;
+    fn handle_with_progress(
+        &mut self,
+        msg: Message,
+        progress: &mut ProgressHandler,
+    ) -> Result<Message>;
+    fn handle(&mut self, msg: Message) -> Result<Message> {
+        self.handle_with_progress(msg, &mut |_, _| ())
+    }
     fn as_mut_dyn(&mut self) -> &mut dyn ProtocolAdapter;
     fn with_handler<'a: 'b, 'b>(
         &'a mut self,
@@ -89,6 +129,7 @@ pub trait ProtocolAdapter {
 
 pub type MessageHandler<'a> = dyn Fn(&Message) -> Result<Option<Message>> + 'a;
 pub type MessageHandlerMut<'a> = dyn FnMut(&Message) -> Result<Option<Message>> + 'a;
+pub type ProgressHandler<'a> = dyn FnMut(usize, usize) + 'a;
 
 pub struct MessageHandlerStack<'a, 'b> {
     parent_adapter: &'a mut dyn ProtocolAdapter,
@@ -107,12 +148,15 @@ impl ProtocolAdapter for MessageHandlerStack<'_, '_> {
     fn send(&mut self, msg: Message) -> Result<()> {
         self.parent_adapter.send(msg)
     }
-    fn handle(&mut self, msg: Message) -> Result<Message> {
-        let mut msg = msg;
+    fn handle_with_progress(
+        &mut self,
+        msg: Message,
+        progress: &mut ProgressHandler,
+    ) -> Result<Message> {
+        let mut msg_out = self.parent_adapter.handle_with_progress(msg, progress)?;
         loop {
-            let msg_out = self.parent_adapter.handle(msg)?;
             match (self.handler)(&msg_out)? {
-                Some(x) => msg = x,
+                Some(x) => msg_out = self.parent_adapter.handle(x)?,
                 None => return Ok(msg_out),
             }
         }
@@ -129,12 +173,15 @@ impl ProtocolAdapter for MessageHandlerMutStack<'_, '_> {
     fn send(&mut self, msg: Message) -> Result<()> {
         self.parent_adapter.send(msg)
     }
-    fn handle(&mut self, msg: Message) -> Result<Message> {
-        let mut msg = msg;
+    fn handle_with_progress(
+        &mut self,
+        msg: Message,
+        progress: &mut ProgressHandler,
+    ) -> Result<Message> {
+        let mut msg_out = self.parent_adapter.handle_with_progress(msg, progress)?;
         loop {
-            let msg_out = self.parent_adapter.handle(msg)?;
             match (self.handler)(&msg_out)? {
-                Some(x) => msg = x,
+                Some(x) => msg_out = self.parent_adapter.handle(x)?,
                 None => return Ok(msg_out),
             }
         }
@@ -0,0 +1,127 @@
+use crate::messages::PinMatrixRequestType;
+use anyhow::Result;
+use crossterm::{
+    cursor::MoveToColumn,
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    queue,
+    style::Print,
+    terminal::{self, Clear, ClearType},
+    tty::IsTty,
+};
+use std::io::{stderr, stdin, Write};
+
+/// KeepKey PINs are at most this many digits long.
+const MAX_PIN_LEN: usize = 9;
+
+/// Positions are numbered like a numeric keypad, which is how the device expects them.
+const KEYPAD: &str = "
+    +---+---+---+
+    | 7 | 8 | 9 |
+    +---+---+---+
+    | 4 | 5 | 6 |
+    +---+---+---+
+    | 1 | 2 | 3 |
+    +---+---+---+
+";
+
+pub fn prompt_for(request_type: PinMatrixRequestType) -> &'static str {
+    match request_type {
+        PinMatrixRequestType::Current => "Enter your current PIN",
+        PinMatrixRequestType::NewFirst => "Choose a new PIN",
+        PinMatrixRequestType::NewSecond => "Re-enter your new PIN to confirm it",
+    }
+}
+
+/// What came of asking for the PIN on the keypad.
+pub enum PinEntry {
+    Pin(String),
+    /// Esc or Ctrl-C was pressed, so the device should be told to cancel
+    Cancelled,
+    /// there's no terminal to draw the keypad on
+    NoTerminal,
+}
+
+/// Puts the terminal back the way it was, even if we bail out early.
+pub(crate) struct RawModeGuard;
+
+impl RawModeGuard {
+    pub(crate) fn new() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+fn draw_pin(out: &mut impl Write, len: usize) -> Result<()> {
+    queue!(
+        out,
+        MoveToColumn(0),
+        Clear(ClearType::CurrentLine),
+        Print(format!("PIN: {}", "*".repeat(len))),
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Asks for the PIN on a keypad laid out like the scrambled matrix on the device's screen.
+pub fn read_pin(request_type: PinMatrixRequestType) -> Result<PinEntry> {
+    if !(stdin().is_tty() && stderr().is_tty()) {
+        return Ok(PinEntry::NoTerminal);
+    }
+
+    let mut out = stderr();
+    write!(
+        out,
+        "{}.\nYour KeepKey is showing its digits in a scrambled order; press the keys below that are\nin the same positions as your PIN's digits on the device.\n{}\nEnter to confirm, Backspace to delete, Esc to cancel.\n",
+        prompt_for(request_type),
+        KEYPAD
+    )?;
+
+    let mut pin = String::new();
+    {
+        let _guard = RawModeGuard::new()?;
+        draw_pin(&mut out, 0)?;
+        loop {
+            match crossterm::event::read()? {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers,
+                    ..
+                }) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    write!(out, "\r\n")?;
+                    return Ok(PinEntry::Cancelled);
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                }) => {
+                    write!(out, "\r\n")?;
+                    return Ok(PinEntry::Cancelled);
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Enter,
+                    ..
+                }) if !pin.is_empty() => break,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Backspace,
+                    ..
+                }) => {
+                    pin.pop();
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c @ '1'..='9'),
+                    ..
+                }) if pin.len() < MAX_PIN_LEN => pin.push(c),
+                _ => continue,
+            }
+            draw_pin(&mut out, pin.len())?;
+        }
+        write!(out, "\r\n")?;
+    }
+
+    Ok(PinEntry::Pin(pin))
+}
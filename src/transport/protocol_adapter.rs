@@ -1,6 +1,13 @@
-use super::{ProtocolAdapter, Transport};
-use crate::messages::Message;
-use anyhow::{anyhow, Result};
+use super::{
+    interrupt::{self, WaitGuard},
+    ProgressHandler, ProtocolAdapter, Transport,
+};
+use crate::{
+    error::DeviceError,
+    messages::{self, Message},
+};
+use anyhow::Result;
+use core::time::Duration;
 use lazy_static::lazy_static;
 use std::sync::RwLock;
 
@@ -8,6 +15,56 @@ lazy_static! {
     pub static ref VERBOSE: RwLock<bool> = RwLock::new(false);
 }
 
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn send_with_progress<T, E>(
+    transport: &mut T,
+    msg: Message,
+    progress: &mut ProgressHandler,
+) -> Result<()>
+where
+    T: Transport<Error = E>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    if *VERBOSE.read().unwrap() {
+        eprintln!("-> {:?}", msg);
+    }
+    let mut out_buf = Vec::<u8>::with_capacity(msg.encoded_len());
+    msg.encode(&mut out_buf)?;
+    transport.write_with_progress(&out_buf, msg.write_timeout(), progress)?;
+
+    Ok(())
+}
+
+fn decode(buf: &[u8]) -> Result<Message> {
+    let out = Message::decode(&mut &*buf).map_err(|x| DeviceError::BadFraming(x.to_string()))?;
+    if *VERBOSE.read().unwrap() {
+        eprintln!("<- {:?}", out);
+    }
+    Ok(out)
+}
+
+/// Tells the device to abandon whatever it's doing, and throws away its response.
+fn cancel<T, E>(transport: &mut T)
+where
+    T: Transport<Error = E>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    // Reads give up as soon as they see the flag, which would leave the device's Failure unread
+    // and taken for the response to the next command. Another Ctrl-C still stops the wait.
+    interrupt::clear_interrupted();
+    if send_with_progress(transport, messages::Cancel {}.into(), &mut |_, _| ()).is_err() {
+        return;
+    }
+    let mut in_buf = Vec::<u8>::new();
+    while transport.read(&mut in_buf, CANCEL_TIMEOUT).is_ok() {
+        if let Ok(Message::Failure(_)) = decode(&in_buf) {
+            return;
+        }
+        in_buf.clear();
+    }
+}
+
 impl<T, E> ProtocolAdapter for T
 where
     T: Transport<Error = E>,
@@ -18,31 +75,31 @@ where
     }
 
     fn send(&mut self, msg: Message) -> Result<()> {
-        if *VERBOSE.read().unwrap() {
-            println!("-> {:?}", msg);
-        }
-        let mut out_buf = Vec::<u8>::with_capacity(msg.encoded_len());
-        msg.encode(&mut out_buf)?;
-        self.write(&out_buf, msg.write_timeout())?;
-
-        Ok(())
+        send_with_progress(self, msg, &mut |_, _| ())
     }
 
     fn as_mut_dyn(&mut self) -> &mut dyn ProtocolAdapter {
         self
     }
 
-    fn handle(&mut self, msg: Message) -> Result<Message> {
+    fn handle_with_progress(
+        &mut self,
+        msg: Message,
+        progress: &mut ProgressHandler,
+    ) -> Result<Message> {
+        let _waiting = WaitGuard::new();
         let read_timeout = msg.read_timeout();
-        self.send(msg)?;
+        send_with_progress(self, msg, progress)?;
 
         let mut in_buf = Vec::<u8>::new();
-        self.read(&mut in_buf, read_timeout)?;
-
-        let out = Message::decode(&mut in_buf.as_slice()).map_err(|x| anyhow!(x))?;
-        if *VERBOSE.read().unwrap() {
-            println!("<- {:?}", out);
+        if let Err(x) = self.read(&mut in_buf, read_timeout) {
+            if !interrupt::is_interrupted() {
+                return Err(x.into());
+            }
+            cancel(self);
+            return Err(DeviceError::Interrupted.into());
         }
-        Ok(out)
+
+        decode(&in_buf)
     }
 }
@@ -0,0 +1,236 @@
+use anyhow::{anyhow, bail, Context, Result};
+use core::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+use lazy_static::lazy_static;
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    process::{Command, Stdio},
+    sync::RwLock,
+};
+
+lazy_static! {
+    pub static ref PIN_SOURCE: RwLock<SecretSource> = RwLock::new(SecretSource::Prompt);
+    pub static ref PASSPHRASE_SOURCE: RwLock<SecretSource> = RwLock::new(SecretSource::Prompt);
+}
+
+/// Where to get a PIN or passphrase from when the device asks for one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    /// ask on the terminal
+    Prompt,
+    /// read the value of an environment variable
+    Env(String),
+    /// read a line from an already-open file descriptor
+    Fd(i32),
+    /// read the first line of a file
+    File(String),
+    /// run a program with the prompt as its argument and read a line from its output, like `SSH_ASKPASS`
+    Askpass(String),
+    /// ask a `pinentry` program using the Assuan protocol
+    Pinentry(String),
+}
+
+impl FromStr for SecretSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s, None),
+        };
+        Ok(match (kind, arg) {
+            ("prompt", None) => Self::Prompt,
+            ("env", Some(x)) => Self::Env(x.to_owned()),
+            ("fd", Some(x)) => match x.parse() {
+                Ok(x) if x >= 0 => Self::Fd(x),
+                _ => bail!("invalid file descriptor ({})", x),
+            },
+            ("file", Some(x)) => Self::File(x.to_owned()),
+            ("askpass", Some(x)) => Self::Askpass(x.to_owned()),
+            ("askpass", None) => Self::Askpass(
+                std::env::var("SSH_ASKPASS")
+                    .context("askpass needs a program or the SSH_ASKPASS variable")?,
+            ),
+            ("pinentry", Some(x)) => Self::Pinentry(x.to_owned()),
+            ("pinentry", None) => Self::Pinentry("pinentry".to_owned()),
+            _ => bail!("expected prompt, env:VAR, fd:N, file:PATH, askpass[:PROGRAM] or pinentry[:PROGRAM]"),
+        })
+    }
+}
+
+impl Display for SecretSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prompt => write!(f, "prompt"),
+            Self::Env(x) => write!(f, "env:{}", x),
+            Self::Fd(x) => write!(f, "fd:{}", x),
+            Self::File(x) => write!(f, "file:{}", x),
+            Self::Askpass(x) => write!(f, "askpass:{}", x),
+            Self::Pinentry(x) => write!(f, "pinentry:{}", x),
+        }
+    }
+}
+
+fn trim_line_ending(mut x: String) -> String {
+    while x.ends_with('\n') || x.ends_with('\r') {
+        x.pop();
+    }
+    x
+}
+
+/// Reads one line a byte at a time, so that nothing past it is consumed and the next request can read the next line.
+fn read_line_unbuffered(reader: &mut impl Read) -> Result<String> {
+    let mut out = Vec::new();
+    let mut byte = [0u8];
+    while reader.read(&mut byte)? == 1 && byte[0] != b'\n' {
+        out.push(byte[0]);
+    }
+    Ok(trim_line_ending(String::from_utf8(out)?))
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> Result<String> {
+    use std::{fs::File, mem::ManuallyDrop, os::unix::io::FromRawFd};
+    // The descriptor belongs to whoever opened it, so don't close it when we're done.
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    read_line_unbuffered(&mut *file)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_: i32) -> Result<String> {
+    bail!("reading secrets from a file descriptor is only supported on Unix")
+}
+
+fn askpass(program: &str, prompt: &str) -> Result<String> {
+    let output = Command::new(program)
+        .arg(prompt)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("couldn't run {}", program))?;
+    if !output.status.success() {
+        bail!("{} exited with {}", program, output.status);
+    }
+    Ok(String::from_utf8(output.stdout)?
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned())
+}
+
+fn assuan_escape(x: &str) -> String {
+    x.replace('%', "%25")
+        .replace('\n', "%0A")
+        .replace('\r', "%0D")
+}
+
+fn assuan_unescape(x: &str) -> Result<String> {
+    let mut out = Vec::with_capacity(x.len());
+    let mut bytes = x.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [
+                bytes
+                    .next()
+                    .ok_or_else(|| anyhow!("bad escape from pinentry"))?,
+                bytes
+                    .next()
+                    .ok_or_else(|| anyhow!("bad escape from pinentry"))?,
+            ];
+            out.push(u8::from_str_radix(std::str::from_utf8(&hex)?, 16)?);
+        } else {
+            out.push(b);
+        }
+    }
+    Ok(String::from_utf8(out)?)
+}
+
+/// The terminal curses pinentries should draw on: `GPG_TTY`, as with gpg-agent, or the one stdin
+/// is connected to.
+fn tty_name() -> Option<String> {
+    if let Ok(x) = std::env::var("GPG_TTY") {
+        return Some(x);
+    }
+    let output = Command::new("tty")
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    match output.status.success() {
+        true => Some(trim_line_ending(String::from_utf8(output.stdout).ok()?)),
+        false => None,
+    }
+}
+
+fn pinentry(program: &str, description: &str, prompt: &str) -> Result<String> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("couldn't run {}", program))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    let mut read_response = || -> Result<Option<String>> {
+        let mut data = None;
+        loop {
+            let mut line = String::new();
+            if stdout.read_line(&mut line)? == 0 {
+                bail!("{} exited unexpectedly", program);
+            }
+            let line = trim_line_ending(line);
+            if line == "OK" || line.starts_with("OK ") {
+                return Ok(data);
+            } else if let Some(x) = line.strip_prefix("D ") {
+                data = Some(assuan_unescape(x)?);
+            } else if let Some(x) = line.strip_prefix("ERR ") {
+                bail!("{}: {}", program, x);
+            }
+        }
+    };
+
+    read_response()?;
+    let options = [
+        tty_name().map(|x| format!("OPTION ttyname={}", assuan_escape(&x))),
+        std::env::var("TERM")
+            .ok()
+            .map(|x| format!("OPTION ttytype={}", assuan_escape(&x))),
+    ];
+    for command in options.into_iter().flatten().chain([
+        format!("SETDESC {}", assuan_escape(description)),
+        format!("SETPROMPT {}", assuan_escape(prompt)),
+        "GETPIN".to_owned(),
+    ]) {
+        writeln!(stdin, "{}", command)?;
+        if let Some(x) = read_response()? {
+            writeln!(stdin, "BYE").ok();
+            child.wait()?;
+            return Ok(x);
+        }
+    }
+    writeln!(stdin, "BYE").ok();
+    child.wait()?;
+    Ok(String::new())
+}
+
+impl SecretSource {
+    /// Gets the secret, or returns `None` if it should be asked for interactively.
+    pub fn read(&self, description: &str, prompt: &str) -> Result<Option<String>> {
+        Ok(Some(match self {
+            Self::Prompt => return Ok(None),
+            Self::Env(x) => std::env::var(x).with_context(|| format!("couldn't read ${}", x))?,
+            Self::Fd(x) => read_fd(*x)?,
+            Self::File(x) => std::fs::read_to_string(x)
+                .with_context(|| format!("couldn't read {}", x))?
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_owned(),
+            Self::Askpass(x) => askpass(x, &format!("{} {}", description, prompt))?,
+            Self::Pinentry(x) => pinentry(x, description, prompt)?,
+        }))
+    }
+}
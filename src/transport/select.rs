@@ -0,0 +1,201 @@
+use super::{list_devices, ProtocolAdapter, UsbTransport};
+use crate::{
+    error::{find_device_error, DeviceError},
+    messages::{self, Message},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use core::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+    time::Duration,
+};
+use lazy_static::lazy_static;
+use rusb::{Device, GlobalContext};
+use std::{sync::RwLock, time::Instant};
+
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+lazy_static! {
+    /// USB bus number and address of the device commands are talking to, once it's been picked.
+    pub static ref SELECTED_DEVICE: RwLock<Option<(u8, u8)>> = RwLock::new(None);
+}
+
+/// Which device to talk to when more than one is plugged in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// USB serial number
+    Serial(String),
+    /// USB bus number and device address, as shown by `list`
+    Location(u8, u8),
+    /// label set with `apply-settings`
+    Label(String),
+    /// `device_id` reported in the device's features
+    DeviceId(String),
+    /// any of the serial number, label or device ID
+    Any(String),
+}
+
+fn parse_location(s: &str) -> Option<(u8, u8)> {
+    let (bus, address) = s.split_once(':')?;
+    Some((bus.parse().ok()?, address.parse().ok()?))
+}
+
+impl FromStr for DeviceSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((bus, address)) = parse_location(s) {
+            return Ok(Self::Location(bus, address));
+        }
+        Ok(match s.split_once(':') {
+            Some(("serial", x)) => Self::Serial(x.to_owned()),
+            Some(("usb", x)) => {
+                let (bus, address) =
+                    parse_location(x).context("expected usb:BUS:ADDRESS, like usb:001:004")?;
+                Self::Location(bus, address)
+            }
+            Some(("label", x)) => Self::Label(x.to_owned()),
+            Some(("id", x)) => Self::DeviceId(x.to_owned()),
+            _ if s.is_empty() => bail!("expected a serial number, BUS:ADDRESS, label or device ID"),
+            _ => Self::Any(s.to_owned()),
+        })
+    }
+}
+
+impl Display for DeviceSelector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Serial(x) => write!(f, "serial:{}", x),
+            Self::Location(bus, address) => write!(f, "{:03}:{:03}", bus, address),
+            Self::Label(x) => write!(f, "label:{}", x),
+            Self::DeviceId(x) => write!(f, "id:{}", x),
+            Self::Any(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+pub fn read_serial_number(device: &Device<GlobalContext>) -> Result<String> {
+    let device_desc = device.device_descriptor()?;
+    Ok(device
+        .open()?
+        .read_serial_number_string_ascii(&device_desc)?)
+}
+
+/// Opens the device just long enough to ask for its features.
+pub fn read_features(device: &Device<GlobalContext>) -> Result<messages::Features> {
+    let (mut transport, _, _) = UsbTransport::new(device, 0)?;
+    match transport.handle(messages::Initialize::default().into())? {
+        Message::Features(x) => Ok(x),
+        Message::Failure(x) => Err(DeviceError::from(x).into()),
+        x => Err(anyhow!("unexpected message ({:?})", x)),
+    }
+}
+
+impl DeviceSelector {
+    fn needs_features(&self) -> bool {
+        !matches!(self, Self::Serial(_) | Self::Location(..))
+    }
+
+    fn matches(
+        &self,
+        device: &Device<GlobalContext>,
+        serial_number: Option<&str>,
+        features: Option<&messages::Features>,
+    ) -> bool {
+        let label = features.and_then(|x| x.label.as_deref());
+        let device_id = features.and_then(|x| x.device_id.as_deref());
+        match self {
+            Self::Serial(x) => serial_number == Some(x),
+            Self::Location(bus, address) => {
+                (device.bus_number(), device.address()) == (*bus, *address)
+            }
+            Self::Label(x) => label == Some(x),
+            Self::DeviceId(x) => device_id == Some(x),
+            Self::Any(x) => [serial_number, label, device_id].contains(&Some(x)),
+        }
+    }
+}
+
+/// Finds the device to use: the only one matching `selector`, or the first one if there's no selector.
+pub fn select_device(selector: Option<&DeviceSelector>) -> Result<Device<GlobalContext>> {
+    let devices = list_devices()?;
+    let selector = match selector {
+        Some(x) => x,
+        None => return Ok(devices.first().ok_or(DeviceError::NoDevice)?.to_owned()),
+    };
+
+    let mut matches = Vec::new();
+    let mut errors = Vec::new();
+    for device in devices.iter() {
+        let location = format!("{:03}:{:03}", device.bus_number(), device.address());
+        let serial_number = match selector {
+            DeviceSelector::Location(..) => None,
+            _ => read_serial_number(device)
+                .map_err(|e| errors.push(format!("{}: {}", location, e)))
+                .ok(),
+        };
+        let features = if selector.needs_features() {
+            read_features(device)
+                .map_err(|e| errors.push(format!("{}: {}", location, e)))
+                .ok()
+        } else {
+            None
+        };
+        if selector.matches(device, serial_number.as_deref(), features.as_ref()) {
+            matches.push((device.to_owned(), location));
+        }
+    }
+
+    match matches.len() {
+        1 => Ok(matches.pop().unwrap().0),
+        0 if errors.is_empty() => {
+            Err(DeviceError::NoDevice).with_context(|| format!("no device matches {}", selector))
+        }
+        0 => Err(DeviceError::NoDevice).with_context(|| {
+            format!(
+                "no device matches {} (couldn't check {})",
+                selector,
+                errors.join("; ")
+            )
+        }),
+        n => Err(DeviceError::Ambiguous(n)).with_context(|| {
+            format!(
+                "{} matches devices at {}; use --device BUS:ADDRESS to pick one",
+                selector,
+                matches
+                    .iter()
+                    .map(|(_, x)| x.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }),
+    }
+}
+
+/// Like `select_device()`, but if there's no matching device, keeps looking until one is connected
+/// or `timeout` runs out.
+pub fn wait_for_device(
+    selector: Option<&DeviceSelector>,
+    timeout: Option<Duration>,
+) -> Result<Device<GlobalContext>> {
+    let started = Instant::now();
+    let mut waiting = false;
+    loop {
+        match select_device(selector) {
+            Err(e) if matches!(find_device_error(&e), Some(DeviceError::NoDevice)) => {
+                if let Some(timeout) = timeout.filter(|x| started.elapsed() >= *x) {
+                    return Err(e.context(format!(
+                        "gave up waiting after {}",
+                        humantime::format_duration(timeout)
+                    )));
+                }
+                if !waiting {
+                    eprintln!("Waiting for device...");
+                    waiting = true;
+                }
+                std::thread::sleep(WAIT_POLL_INTERVAL);
+            }
+            x => return x,
+        }
+    }
+}
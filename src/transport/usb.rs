@@ -1,11 +1,24 @@
-use super::Transport;
+use super::{interrupt, ProgressHandler, Transport};
+use crate::error::DeviceError;
 use core::{cmp::min, iter::repeat, time::Duration};
-use rusb::{ConfigDescriptor, Device, DeviceHandle, UsbContext};
+use rusb::{ConfigDescriptor, Device, DeviceHandle, GlobalContext, UsbContext};
 use std::{
     sync::{Arc, Mutex},
     time::Instant,
 };
 
+pub const DEVICE_IDS: &[(u16, u16)] = &[(0x2b24, 0x0001), (0x2b24, 0x0002)];
+
+pub fn list_devices() -> Result<Box<[Device<GlobalContext>]>, DeviceError> {
+    Ok(rusb::devices()?
+        .iter()
+        .filter(|device| {
+            let device_desc = device.device_descriptor().unwrap();
+            DEVICE_IDS.contains(&(device_desc.vendor_id(), device_desc.product_id()))
+        })
+        .collect())
+}
+
 pub struct UsbTransport<T: UsbContext> {
     handle: Arc<Mutex<DeviceHandle<T>>>,
     in_endpoint_address: u8,
@@ -19,7 +32,7 @@ impl<T: UsbContext> UsbTransport<T> {
     pub fn new(
         device: &Device<T>,
         interface_index: usize,
-    ) -> Result<(Self, ConfigDescriptor, Arc<Mutex<DeviceHandle<T>>>), rusb::Error> {
+    ) -> Result<(Self, ConfigDescriptor, Arc<Mutex<DeviceHandle<T>>>), DeviceError> {
         let config_descriptor = device.active_config_descriptor().unwrap();
         let mut handle = Arc::new(Mutex::new(device.open()?));
 
@@ -47,14 +60,14 @@ impl<T: UsbContext> UsbTransport<T> {
         config_descriptor: &ConfigDescriptor,
         handle: Arc<Mutex<DeviceHandle<T>>>,
         interface_index: usize,
-    ) -> Result<Self, rusb::Error> {
+    ) -> Result<Self, DeviceError> {
         let mut handle_mut = handle.lock().unwrap();
 
         let interface = config_descriptor
             .interfaces()
             .nth(interface_index)
             .ok_or(rusb::Error::NotFound)?;
-        handle_mut.claim_interface(interface.number()).unwrap();
+        handle_mut.claim_interface(interface.number())?;
 
         let mut interface_descriptors = interface.descriptors();
         let interface_descriptor = interface_descriptors.next().ok_or(rusb::Error::NotFound)?;
@@ -87,21 +100,23 @@ impl<T: UsbContext> UsbTransport<T> {
         })
     }
 
-    fn read_packet(&self, buf: &mut Vec<u8>, timeout: Duration) -> Result<(), rusb::Error> {
+    fn read_packet(&self, buf: &mut Vec<u8>, timeout: Duration) -> Result<(), DeviceError> {
         let mut packet = vec![0u8; self.in_packet_size];
         let len = self.handle.lock().unwrap().read_interrupt(
             self.in_endpoint_address,
             &mut packet,
             timeout,
         )?;
-        assert_eq!(
-            len,
-            self.in_packet_size,
-            "packet: {:?}",
-            hex::encode(packet)
-        );
-        if !(len >= 1 && packet[0] == b'?') {
-            return Err(rusb::Error::Other);
+        if len != self.in_packet_size {
+            return Err(DeviceError::BadFraming(format!(
+                "short packet: {}",
+                hex::encode(&packet[..len])
+            )));
+        }
+        if packet[0] != b'?' {
+            return Err(DeviceError::BadFraming(
+                "packet doesn't start with '?'".to_owned(),
+            ));
         }
         buf.extend_from_slice(&packet[1..]);
         Ok(())
@@ -118,9 +133,18 @@ macro_rules! since {
 }
 
 impl<T: UsbContext> Transport for UsbTransport<T> {
-    type Error = rusb::Error;
+    type Error = DeviceError;
     fn write(&mut self, msg: &[u8], timeout: Duration) -> Result<usize, Self::Error> {
+        self.write_with_progress(msg, timeout, &mut |_, _| ())
+    }
+    fn write_with_progress(
+        &mut self,
+        msg: &[u8],
+        timeout: Duration,
+        progress: &mut ProgressHandler,
+    ) -> Result<usize, Self::Error> {
         let started = Instant::now();
+        let mut written = 0;
         let mut packet = Vec::<u8>::with_capacity(self.out_packet_size);
         for chunk in msg.chunks(self.out_packet_size - 1) {
             packet.clear();
@@ -135,16 +159,32 @@ impl<T: UsbContext> Transport for UsbTransport<T> {
                 since!(started, timeout)?,
             )?;
             assert_eq!(written_len, packet.len());
+            written += chunk.len();
+            progress(written, msg.len());
         }
         Ok(msg.len())
     }
     fn read(&mut self, buf: &mut Vec<u8>, timeout: Duration) -> Result<(), Self::Error> {
         let mut packet = Vec::<u8>::with_capacity(self.in_packet_size);
         let started = Instant::now();
-        self.read_packet(&mut packet, timeout)?;
+        // Wait for the first packet a little at a time so that Ctrl-C can interrupt us.
+        loop {
+            match self.read_packet(
+                &mut packet,
+                min(since!(started, timeout)?, interrupt::POLL_INTERVAL),
+            ) {
+                Err(DeviceError::Timeout) if interrupt::is_interrupted() => {
+                    return Err(DeviceError::Interrupted)
+                }
+                Err(DeviceError::Timeout) => (),
+                x => break x?,
+            }
+        }
 
         if !(packet.len() >= 8 && packet[0] == b'#' && packet[1] == b'#') {
-            return Err(rusb::Error::Other);
+            return Err(DeviceError::BadFraming(
+                "message doesn't start with '##'".to_owned(),
+            ));
         }
         let msg_len: usize = u32::from_be_bytes(packet[4..8].try_into().unwrap())
             .try_into()
@@ -179,7 +219,7 @@ impl<T: UsbContext> Transport for UsbTransport<T> {
                 Ok(_) => (),
                 Err(rusb::Error::Timeout) => return Ok(()),
                 Err(rusb::Error::Overflow) => (),
-                Err(x) => return Err(x),
+                Err(x) => return Err(x.into()),
             }
             buf.fill(0);
         }